@@ -9,6 +9,17 @@ mod m20230309_131331_create_idempotency_table;
 mod m20230312_033844_relax_null_checks_on_idempotency;
 mod m20230315_130704_create_newsletter_issues_table;
 mod m20230315_134230_create_issue_delivery_queue_table;
+mod m20230320_090000_add_two_factor_to_user;
+mod m20230322_090000_create_password_reset_tokens;
+mod m20230324_090000_create_api_tokens;
+mod m20230326_090000_create_audit_events;
+mod m20230328_090000_add_request_id_to_issue_delivery_queue;
+mod m20230330_090000_make_published_at_a_timestamp;
+mod m20230330_090001_add_primary_key_to_issue_delivery_queue;
+mod m20230401_090000_make_subscription_status_an_enum;
+mod m20230403_090000_create_data_request_tokens_and_erasures;
+mod m20230405_090000_create_suppressions;
+mod m20230407_090000_add_totp_last_step_to_user;
 
 pub struct Migrator;
 
@@ -25,6 +36,17 @@ impl MigratorTrait for Migrator {
             Box::new(m20230312_033844_relax_null_checks_on_idempotency::Migration),
             Box::new(m20230315_130704_create_newsletter_issues_table::Migration),
             Box::new(m20230315_134230_create_issue_delivery_queue_table::Migration),
+            Box::new(m20230320_090000_add_two_factor_to_user::Migration),
+            Box::new(m20230322_090000_create_password_reset_tokens::Migration),
+            Box::new(m20230324_090000_create_api_tokens::Migration),
+            Box::new(m20230326_090000_create_audit_events::Migration),
+            Box::new(m20230328_090000_add_request_id_to_issue_delivery_queue::Migration),
+            Box::new(m20230330_090000_make_published_at_a_timestamp::Migration),
+            Box::new(m20230330_090001_add_primary_key_to_issue_delivery_queue::Migration),
+            Box::new(m20230401_090000_make_subscription_status_an_enum::Migration),
+            Box::new(m20230403_090000_create_data_request_tokens_and_erasures::Migration),
+            Box::new(m20230405_090000_create_suppressions::Migration),
+            Box::new(m20230407_090000_add_totp_last_step_to_user::Migration),
         ]
     }
 }
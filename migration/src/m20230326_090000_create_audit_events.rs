@@ -0,0 +1,85 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum AuditEvents {
+    Table,
+    Id,
+    OccurredAt,
+    ActorId,
+    Actor,
+    Action,
+    Target,
+    Ip,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(AuditEvents::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(AuditEvents::Id)
+                            .uuid()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(AuditEvents::OccurredAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    // no foreign key, the events must outlive the user they mention
+                    .col(ColumnDef::new(AuditEvents::ActorId).uuid().not_null())
+                    // the username at the time of the event
+                    .col(ColumnDef::new(AuditEvents::Actor).string().not_null())
+                    .col(ColumnDef::new(AuditEvents::Action).string().not_null())
+                    .col(ColumnDef::new(AuditEvents::Target).string().null())
+                    .col(ColumnDef::new(AuditEvents::Ip).string().null())
+                    .to_owned(),
+            )
+            .await?;
+        manager
+            .create_index(
+                Index::create()
+                    .name("audit_events_occurred_at")
+                    .table(AuditEvents::Table)
+                    .col(AuditEvents::OccurredAt)
+                    .to_owned(),
+            )
+            .await?;
+
+        // the application only ever inserts, make sure nothing else rewrites history
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"CREATE FUNCTION audit_events_append_only() RETURNS trigger AS $$
+            BEGIN
+                RAISE EXCEPTION 'audit_events is append-only';
+            END;
+            $$ LANGUAGE plpgsql;"#,
+        )
+        .await?;
+        db.execute_unprepared(
+            "CREATE TRIGGER audit_events_append_only
+            BEFORE UPDATE OR DELETE ON audit_events
+            FOR EACH ROW EXECUTE FUNCTION audit_events_append_only();",
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(AuditEvents::Table).to_owned())
+            .await?;
+        let db = manager.get_connection();
+        db.execute_unprepared("DROP FUNCTION IF EXISTS audit_events_append_only;")
+            .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,37 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230315_134230_create_issue_delivery_queue_table::IssueDeliveryQueue;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum RequestTracing {
+    RequestId,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // tasks enqueued before this migration have no publishing request to point to
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueDeliveryQueue::Table)
+                    .add_column(ColumnDef::new(RequestTracing::RequestId).string().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(IssueDeliveryQueue::Table)
+                    .drop_column(RequestTracing::RequestId)
+                    .to_owned(),
+            )
+            .await
+    }
+}
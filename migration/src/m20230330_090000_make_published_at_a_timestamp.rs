@@ -0,0 +1,32 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the column was filled with `now()`, so every stored value parses back
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"
+            ALTER TABLE newsletter_issues
+                ALTER COLUMN published_at TYPE timestamptz USING published_at::timestamptz;
+            "#,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"
+            ALTER TABLE newsletter_issues
+                ALTER COLUMN published_at TYPE varchar USING published_at::varchar;
+            "#,
+        )
+        .await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,34 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // a subscriber gets an issue once, duplicated tasks would only have sent it twice
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"
+            DELETE FROM issue_delivery_queue a
+                USING issue_delivery_queue b
+                WHERE a.ctid > b.ctid
+                    AND a.newsletter_issue_id = b.newsletter_issue_id
+                    AND a.subscriber_email = b.subscriber_email;
+            ALTER TABLE issue_delivery_queue
+                ADD PRIMARY KEY (newsletter_issue_id, subscriber_email);
+            "#,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            "ALTER TABLE issue_delivery_queue DROP CONSTRAINT issue_delivery_queue_pkey;",
+        )
+        .await?;
+        Ok(())
+    }
+}
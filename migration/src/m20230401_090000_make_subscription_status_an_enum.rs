@@ -0,0 +1,38 @@
+use sea_orm_migration::{prelude::*, sea_orm::ConnectionTrait};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // a status outside of the enum fails the cast, and with it the migration
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"
+            CREATE TYPE subscription_status AS ENUM (
+                'pending', 'confirmed', 'unsubscribed', 'bounced', 'complained'
+            );
+            ALTER TABLE subscriptions
+                ALTER COLUMN status TYPE subscription_status
+                USING (CASE status WHEN 'pending_confirmed' THEN 'pending' ELSE status END)::subscription_status;
+            "#,
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let db = manager.get_connection();
+        db.execute_unprepared(
+            r#"
+            ALTER TABLE subscriptions
+                ALTER COLUMN status TYPE varchar
+                USING (CASE status WHEN 'pending' THEN 'pending_confirmed' ELSE status::varchar END);
+            DROP TYPE subscription_status;
+            "#,
+        )
+        .await?;
+        Ok(())
+    }
+}
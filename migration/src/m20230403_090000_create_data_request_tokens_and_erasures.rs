@@ -0,0 +1,88 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20220101_000001_create_subscription::Subscriptions;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum DataRequestTokens {
+    Table,
+    TokenHash,
+    SubscriberId,
+    ExpiresAt,
+}
+
+#[derive(Iden)]
+pub enum ErasedSubscribers {
+    Table,
+    EmailHash,
+    ErasedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .create_table(
+                Table::create()
+                    .table(DataRequestTokens::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(DataRequestTokens::TokenHash)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(DataRequestTokens::SubscriberId)
+                            .uuid()
+                            .not_null(),
+                    )
+                    .col(
+                        ColumnDef::new(DataRequestTokens::ExpiresAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .foreign_key(
+                        ForeignKey::create()
+                            .name("data_request_tokens_subscriber_id")
+                            .from(DataRequestTokens::Table, DataRequestTokens::SubscriberId)
+                            .to(Subscriptions::Table, Subscriptions::Id)
+                            .on_delete(ForeignKeyAction::Cascade),
+                    )
+                    .to_owned(),
+            )
+            .await?;
+
+        // the tombstones of erased addresses, only a hash is kept
+        manager
+            .create_table(
+                Table::create()
+                    .table(ErasedSubscribers::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(ErasedSubscribers::EmailHash)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(
+                        ColumnDef::new(ErasedSubscribers::ErasedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(ErasedSubscribers::Table).to_owned())
+            .await?;
+        manager
+            .drop_table(Table::drop().table(DataRequestTokens::Table).to_owned())
+            .await
+    }
+}
@@ -0,0 +1,47 @@
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum Suppressions {
+    Table,
+    Address,
+    Reason,
+    Source,
+    CreatedAt,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // an address or a whole domain, lowercased, that is never mailed
+        manager
+            .create_table(
+                Table::create()
+                    .table(Suppressions::Table)
+                    .if_not_exists()
+                    .col(
+                        ColumnDef::new(Suppressions::Address)
+                            .string()
+                            .not_null()
+                            .primary_key(),
+                    )
+                    .col(ColumnDef::new(Suppressions::Reason).string().not_null())
+                    .col(ColumnDef::new(Suppressions::Source).string().not_null())
+                    .col(
+                        ColumnDef::new(Suppressions::CreatedAt)
+                            .timestamp_with_time_zone()
+                            .not_null(),
+                    )
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .drop_table(Table::drop().table(Suppressions::Table).to_owned())
+            .await
+    }
+}
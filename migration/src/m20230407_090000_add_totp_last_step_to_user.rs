@@ -0,0 +1,38 @@
+use sea_orm_migration::prelude::*;
+
+use crate::m20230212_094638_create_user_table::User;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[derive(Iden)]
+pub enum TotpReplay {
+    TotpLastStep,
+}
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // the 30 second step of the last accepted totp code, a code is only
+        // accepted for a newer step so that it can't be replayed
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .add_column(ColumnDef::new(TotpReplay::TotpLastStep).big_integer().null())
+                    .to_owned(),
+            )
+            .await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager
+            .alter_table(
+                Table::alter()
+                    .table(User::Table)
+                    .drop_column(TotpReplay::TotpLastStep)
+                    .to_owned(),
+            )
+            .await
+    }
+}
@@ -0,0 +1,22 @@
+#!/usr/bin/env bash
+set -x
+set -eo pipefail
+
+# if a jaeger container is running, print instructions to kill it and exit
+RUNNING_CONTAINER=$(docker ps --filter 'name=jaeger' --format '{{.ID}}')
+if [[ -n $RUNNING_CONTAINER ]]; then
+  echo >&2 "there is a jaeger container already running, kill it with"
+  echo >&2 "    docker kill ${RUNNING_CONTAINER}"
+  exit 1
+fi
+
+# Launch Jaeger with its otlp grpc receiver using Docker
+docker run \
+    -e "COLLECTOR_OTLP_ENABLED=true" \
+    -p "4317:4317" \
+    -p "16686:16686" \
+    -d \
+    --name "jaeger" \
+    jaegertracing/all-in-one:1.43
+
+>&2 echo "Jaeger is ready to go! Set APP__TELEMETRY__OTLP_ENDPOINT=http://localhost:4317 and browse the traces at http://localhost:16686"
@@ -0,0 +1,184 @@
+use anyhow::Context;
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder, QuerySelect,
+};
+use uuid::Uuid;
+
+use crate::entities::{
+    audit_events::{self, Entity as AuditEvents},
+    user::Entity as Users,
+};
+
+// the most events a single listing returns
+const LISTING_LIMIT: u64 = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditAction {
+    Login,
+    Logout,
+    PasswordChanged,
+    PasswordReset,
+    EmailChanged,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
+    ApiTokenCreated,
+    ApiTokenRevoked,
+    NewsletterPublished,
+    SubscriberConfirmed,
+    SubscriberUnsubscribed,
+    SubscriberDeleted,
+    SubscribersImported,
+    SubscriberDataExported,
+    SubscriberErased,
+    SuppressionAdded,
+    SuppressionRemoved,
+}
+
+impl AuditAction {
+    pub const ALL: [AuditAction; 18] = [
+        AuditAction::Login,
+        AuditAction::Logout,
+        AuditAction::PasswordChanged,
+        AuditAction::PasswordReset,
+        AuditAction::EmailChanged,
+        AuditAction::TwoFactorEnabled,
+        AuditAction::TwoFactorDisabled,
+        AuditAction::ApiTokenCreated,
+        AuditAction::ApiTokenRevoked,
+        AuditAction::NewsletterPublished,
+        AuditAction::SubscriberConfirmed,
+        AuditAction::SubscriberUnsubscribed,
+        AuditAction::SubscriberDeleted,
+        AuditAction::SubscribersImported,
+        AuditAction::SubscriberDataExported,
+        AuditAction::SubscriberErased,
+        AuditAction::SuppressionAdded,
+        AuditAction::SuppressionRemoved,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditAction::Login => "login",
+            AuditAction::Logout => "logout",
+            AuditAction::PasswordChanged => "password_changed",
+            AuditAction::PasswordReset => "password_reset",
+            AuditAction::EmailChanged => "email_changed",
+            AuditAction::TwoFactorEnabled => "two_factor_enabled",
+            AuditAction::TwoFactorDisabled => "two_factor_disabled",
+            AuditAction::ApiTokenCreated => "api_token_created",
+            AuditAction::ApiTokenRevoked => "api_token_revoked",
+            AuditAction::NewsletterPublished => "newsletter_published",
+            AuditAction::SubscriberConfirmed => "subscriber_confirmed",
+            AuditAction::SubscriberUnsubscribed => "subscriber_unsubscribed",
+            AuditAction::SubscriberDeleted => "subscriber_deleted",
+            AuditAction::SubscribersImported => "subscribers_imported",
+            AuditAction::SubscriberDataExported => "subscriber_data_exported",
+            AuditAction::SubscriberErased => "subscriber_erased",
+            AuditAction::SuppressionAdded => "suppression_added",
+            AuditAction::SuppressionRemoved => "suppression_removed",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|action| action.as_str() == s)
+            .ok_or_else(|| format!("unknown audit action: {s}"))
+    }
+}
+
+impl std::fmt::Display for AuditAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// appends an event, the action it records has already happened so a failure
+// is only logged instead of failing the request
+#[tracing::instrument(name = "record an audit event", skip(db))]
+pub async fn record_audit_event(
+    db: &DatabaseConnection,
+    actor_id: Uuid,
+    action: AuditAction,
+    target: Option<&str>,
+    ip: &str,
+) {
+    if let Err(e) = insert_audit_event(db, actor_id, action, target, ip).await {
+        tracing::error!(error = ?e, "fail to record the audit event");
+    }
+}
+
+async fn insert_audit_event(
+    db: &DatabaseConnection,
+    actor_id: Uuid,
+    action: AuditAction,
+    target: Option<&str>,
+    ip: &str,
+) -> anyhow::Result<()> {
+    let actor = Users::find_by_id(actor_id)
+        .one(db)
+        .await
+        .context("fail to get the actor")?
+        .map(|user| user.user_name)
+        .unwrap_or_else(|| actor_id.to_string());
+    let event = audit_events::ActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        occurred_at: ActiveValue::Set(chrono::Utc::now().into()),
+        actor_id: ActiveValue::Set(actor_id),
+        actor: ActiveValue::Set(actor),
+        action: ActiveValue::Set(action.as_str().to_owned()),
+        target: ActiveValue::Set(target.map(str::to_owned)),
+        ip: ActiveValue::Set(Some(ip.to_owned())),
+    };
+    AuditEvents::insert(event).exec(db).await?;
+    Ok(())
+}
+
+#[derive(Debug, Default)]
+pub struct AuditFilter {
+    // the username
+    pub actor: Option<String>,
+    pub action: Option<AuditAction>,
+    pub since: Option<DateTimeWithTimeZone>,
+    pub until: Option<DateTimeWithTimeZone>,
+}
+
+// the most recent events first
+pub async fn list_audit_events(
+    db: &DatabaseConnection,
+    filter: &AuditFilter,
+) -> anyhow::Result<Vec<audit_events::Model>> {
+    let mut query = AuditEvents::find();
+    if let Some(actor) = &filter.actor {
+        query = query.filter(audit_events::Column::Actor.eq(actor.as_str()));
+    }
+    if let Some(action) = filter.action {
+        query = query.filter(audit_events::Column::Action.eq(action.as_str()));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(audit_events::Column::OccurredAt.gte(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(audit_events::Column::OccurredAt.lt(until));
+    }
+    let events = query
+        .order_by_desc(audit_events::Column::OccurredAt)
+        .limit(LISTING_LIMIT)
+        .all(db)
+        .await?;
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn actions_round_trip() {
+        for action in AuditAction::ALL {
+            assert_eq!(AuditAction::parse(action.as_str()), Ok(action));
+        }
+        assert!(AuditAction::parse("delete_everything").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, QueryOrder,
+};
+use uuid::Uuid;
+
+use crate::{
+    entities::api_tokens::{self, Entity as ApiTokens},
+    utils::sha256_hex,
+};
+
+// makes leaked tokens easy to spot, e.g. by secret scanners
+const TOKEN_PREFIX: &str = "z2p_";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    ReadAccount,
+    PublishNewsletters,
+    ReadSubscribers,
+    ManageSubscribers,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 4] = [
+        ApiScope::ReadAccount,
+        ApiScope::PublishNewsletters,
+        ApiScope::ReadSubscribers,
+        ApiScope::ManageSubscribers,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::ReadAccount => "account:read",
+            ApiScope::PublishNewsletters => "newsletters:publish",
+            ApiScope::ReadSubscribers => "subscribers:read",
+            ApiScope::ManageSubscribers => "subscribers:write",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("unknown scope: {s}"))
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// who a valid bearer token acts for, inserted into the request by `require_api_token`
+#[derive(Debug, Clone)]
+pub struct ApiPrincipal {
+    pub user_id: Uuid,
+    pub token_id: Uuid,
+    pub scopes: Vec<ApiScope>,
+}
+
+impl ApiPrincipal {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.contains(&scope)
+    }
+}
+
+// returns the stored token and the plain one, which is only shown to the user once
+#[tracing::instrument(name = "create an api token", skip(db))]
+pub async fn create_api_token(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    name: &str,
+    scopes: &[ApiScope],
+) -> anyhow::Result<(api_tokens::Model, String)> {
+    let token = generate_api_token();
+    let new_token = api_tokens::ActiveModel {
+        id: ActiveValue::Set(Uuid::new_v4()),
+        user_id: ActiveValue::Set(user_id),
+        name: ActiveValue::Set(name.to_owned()),
+        token_hash: ActiveValue::Set(sha256_hex(&token)),
+        scopes: ActiveValue::Set(
+            scopes
+                .iter()
+                .map(ApiScope::as_str)
+                .collect::<Vec<_>>()
+                .join(" "),
+        ),
+        created_at: ActiveValue::Set(now()),
+        last_used_at: ActiveValue::Set(None),
+        revoked_at: ActiveValue::Set(None),
+    };
+    let model = ApiTokens::insert(new_token)
+        .exec_with_returning(db)
+        .await
+        .context("fail to store the api token")?;
+    Ok((model, token))
+}
+
+pub async fn list_api_tokens(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+) -> Result<Vec<api_tokens::Model>, sea_orm::DbErr> {
+    ApiTokens::find()
+        .filter(api_tokens::Column::UserId.eq(user_id))
+        .order_by_desc(api_tokens::Column::CreatedAt)
+        .all(db)
+        .await
+}
+
+// returns false if the token doesn't exist, belongs to someone else or is already revoked
+#[tracing::instrument(name = "revoke an api token", skip(db))]
+pub async fn revoke_api_token(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    token_id: Uuid,
+) -> Result<bool, sea_orm::DbErr> {
+    let updated = ApiTokens::update_many()
+        .col_expr(api_tokens::Column::RevokedAt, now().into())
+        .filter(api_tokens::Column::Id.eq(token_id))
+        .filter(api_tokens::Column::UserId.eq(user_id))
+        .filter(api_tokens::Column::RevokedAt.is_null())
+        .exec(db)
+        .await?;
+    Ok(updated.rows_affected == 1)
+}
+
+// looks up an unrevoked token and records that it has been used
+#[tracing::instrument(name = "authenticate an api token", skip(db, token))]
+pub async fn authenticate_api_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> anyhow::Result<Option<ApiPrincipal>> {
+    let Some(stored) = ApiTokens::find()
+        .filter(api_tokens::Column::TokenHash.eq(sha256_hex(token)))
+        .filter(api_tokens::Column::RevokedAt.is_null())
+        .one(db)
+        .await
+        .context("fail to look up the api token")?
+    else {
+        return Ok(None);
+    };
+    ApiTokens::update_many()
+        .col_expr(api_tokens::Column::LastUsedAt, now().into())
+        .filter(api_tokens::Column::Id.eq(stored.id))
+        .exec(db)
+        .await
+        .context("fail to record the api token usage")?;
+    Ok(Some(ApiPrincipal {
+        user_id: stored.user_id,
+        token_id: stored.id,
+        scopes: parse_scopes(&stored.scopes),
+    }))
+}
+
+pub fn parse_scopes(scopes: &str) -> Vec<ApiScope> {
+    scopes
+        .split_whitespace()
+        // a scope that has been removed from the code simply grants nothing
+        .filter_map(|scope| ApiScope::parse(scope).ok())
+        .collect()
+}
+
+fn generate_api_token() -> String {
+    let mut rng = thread_rng();
+    let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(40)
+        .collect();
+    format!("{TOKEN_PREFIX}{random}")
+}
+
+fn now() -> DateTimeWithTimeZone {
+    chrono::Utc::now().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_round_trip() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+        assert!(ApiScope::parse("admin").is_err());
+    }
+
+    #[test]
+    fn unknown_scopes_are_ignored() {
+        assert_eq!(
+            parse_scopes("account:read  everything newsletters:publish"),
+            vec![ApiScope::ReadAccount, ApiScope::PublishNewsletters]
+        );
+        assert!(parse_scopes("").is_empty());
+    }
+
+    #[test]
+    fn tokens_are_prefixed() {
+        let token = generate_api_token();
+        assert!(token.starts_with(TOKEN_PREFIX));
+        assert_eq!(token.len(), TOKEN_PREFIX.len() + 40);
+    }
+}
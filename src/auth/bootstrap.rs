@@ -0,0 +1,51 @@
+use anyhow::Context;
+use sea_orm::{ConnectionTrait, DatabaseConnection, EntityTrait, TransactionTrait};
+use uuid::Uuid;
+
+use super::password::register_test_user;
+use crate::{configuration::PasswordHashSettings, entities::user::Entity as Users};
+
+// the admin password shipped in config/test.yaml
+pub const DEFAULT_ADMIN_PASSWORD: &str = "foobar123";
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum BootstrapOutcome {
+    Created,
+    AlreadyExists,
+}
+
+// only create the owner account if there is no user at all,
+// so that running it again against an existing db is a no-op
+#[tracing::instrument(name = "bootstrap admin user", skip(db, password, hash_settings))]
+pub async fn bootstrap_admin(
+    db: &DatabaseConnection,
+    username: &str,
+    password: &str,
+    hash_settings: &PasswordHashSettings,
+) -> anyhow::Result<BootstrapOutcome> {
+    if password == DEFAULT_ADMIN_PASSWORD {
+        tracing::warn!(
+            username,
+            "the admin password is the default one from config/test.yaml, change it as soon as possible"
+        );
+    }
+    let txn = db.begin().await.context("fail to begin a transaction")?;
+    // serialize concurrent bootstraps, e.g. several replicas running create-admin at once
+    txn.execute_unprepared(r#"LOCK TABLE "user" IN SHARE ROW EXCLUSIVE MODE"#)
+        .await
+        .context("fail to lock the user table")?;
+    if Users::find()
+        .one(&txn)
+        .await
+        .context("fail to look up existing users")?
+        .is_some()
+    {
+        tracing::info!("an owner account already exists, skip creating the admin");
+        return Ok(BootstrapOutcome::AlreadyExists);
+    }
+    let salt = Uuid::new_v4().to_string();
+    register_test_user(&txn, username, password, &salt, hash_settings).await?;
+    txn.commit().await.context("fail to commit the new admin")?;
+    tracing::info!(username, "admin user created");
+    Ok(BootstrapOutcome::Created)
+}
@@ -0,0 +1,138 @@
+use poem::{
+    http::Method, session::Session, web::Multipart, Body, Endpoint, FromRequest, Request,
+    RequestBody, Result,
+};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+
+use crate::{
+    routes::error::{AppError, ErrorCode},
+    session_state::CSRF_TOKEN_KEY,
+};
+
+// the name of the hidden form field `templates/csrf.html` renders, scripts may send the `X-CSRF-Token` header instead
+pub const CSRF_FIELD: &str = "csrf_token";
+const CSRF_HEADER: &str = "X-CSRF-Token";
+
+// the token of the session, a new one is issued the first time a form is rendered
+pub fn csrf_token(session: &Session) -> String {
+    if let Some(token) = session.get::<String>(CSRF_TOKEN_KEY) {
+        return token;
+    }
+    rotate_csrf_token(session)
+}
+
+// a fresh token once the user logs in, so that a token planted before the login is useless
+pub fn rotate_csrf_token(session: &Session) -> String {
+    let token = generate_csrf_token();
+    session.set(CSRF_TOKEN_KEY, token.clone());
+    token
+}
+
+// rejects form posts whose token doesn't match the one stored in the session,
+// a request without a session can't carry a valid token either
+pub async fn verify_csrf_token<E: Endpoint>(next: E, mut req: Request) -> Result<E::Output> {
+    if matches!(
+        *req.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    ) {
+        return next.call(req).await;
+    }
+    let expected = req
+        .extensions()
+        .get::<Session>()
+        .and_then(|session| session.get::<String>(CSRF_TOKEN_KEY));
+
+    let submitted = match req
+        .headers()
+        .get(CSRF_HEADER)
+        .and_then(|value| value.to_str().ok())
+    {
+        Some(token) => Some(token.to_owned()),
+        None => {
+            let body = req.take_body().into_bytes().await?;
+            let token = if is_multipart(&req) {
+                multipart_token(&req, Body::from(body.clone())).await
+            } else {
+                serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body)
+                    .ok()
+                    .and_then(|fields| {
+                        fields
+                            .into_iter()
+                            .find(|(name, _)| name == CSRF_FIELD)
+                            .map(|(_, value)| value)
+                    })
+            };
+            req.set_body(body);
+            token
+        }
+    };
+
+    match (expected, submitted) {
+        (Some(expected), Some(submitted)) if tokens_match(&expected, &submitted) => {
+            next.call(req).await
+        }
+        _ => {
+            tracing::warn!(
+                path = req.uri().path(),
+                "rejected a request with a bad csrf token"
+            );
+            Err(AppError::new(ErrorCode::CsrfTokenInvalid, "missing or invalid CSRF token").into())
+        }
+    }
+}
+
+fn is_multipart(req: &Request) -> bool {
+    req.content_type()
+        .map(|content_type| content_type.starts_with("multipart/form-data"))
+        .unwrap_or_default()
+}
+
+// file uploads post multipart forms, the token is one of their fields
+async fn multipart_token(req: &Request, body: Body) -> Option<String> {
+    let mut body = RequestBody::new(body);
+    let mut multipart = Multipart::from_request(req, &mut body).await.ok()?;
+    while let Ok(Some(field)) = multipart.next_field().await {
+        if field.name() == Some(CSRF_FIELD) {
+            return field.text().await.ok();
+        }
+    }
+    None
+}
+
+// compares every byte so the time taken doesn't reveal how much of the token was right
+pub(super) fn tokens_match(expected: &str, submitted: &str) -> bool {
+    expected.len() == submitted.len()
+        && expected
+            .bytes()
+            .zip(submitted.bytes())
+            .fold(0, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_identical_tokens_match() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc123", "abc124"));
+        assert!(!tokens_match("abc123", "abc12"));
+        assert!(!tokens_match("abc123", ""));
+    }
+
+    #[test]
+    fn tokens_are_random() {
+        let token = generate_csrf_token();
+        assert_eq!(token.len(), 32);
+        assert_ne!(token, generate_csrf_token());
+    }
+}
@@ -1,13 +1,205 @@
-use poem::{session::Session, Endpoint, Request, Result};
+use base64::{engine::general_purpose, Engine};
+use poem::{http::header, session::Session, Endpoint, Request, Result};
+use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
-use crate::{routes::error::see_other_error, session_state::USER_ID_KEY};
+use super::{
+    authenticate_api_token, csrf::tokens_match, touch_session, ApiScope, CurrentSession,
+    SessionStatus,
+};
+use crate::{
+    context::StateContext,
+    routes::{
+        error::{see_other_error, see_other_with_flash, AppError, ErrorCode},
+        flash::FlashLevel,
+    },
+    session_state::{SESSION_ID_KEY, USER_ID_KEY},
+};
 
 pub async fn reject_anoynmous_user<E: Endpoint>(next: E, mut req: Request) -> Result<E::Output> {
-    let Some(Some(user_id)) = req.extensions().get::<Session>().map(|s| s.get::<Uuid>(USER_ID_KEY)) else {
+    let Some(session) = req.extensions().get::<Session>().cloned() else {
         return Err(see_other_error("/login"));
     };
+    let Some(user_id) = session.get::<Uuid>(USER_ID_KEY) else {
+        return Err(see_other_error("/login"));
+    };
+    let Some(session_id) = session.get::<String>(SESSION_ID_KEY) else {
+        session.purge();
+        return Err(see_other_error("/login"));
+    };
+    // without the context a revoked session couldn't be told apart, refuse rather than let it in
+    let Some(context) = req.data::<StateContext>() else {
+        return Err(AppError::internal("the session routes have no state context").into());
+    };
+    let status = touch_session(&context.redis, user_id, &session_id, &context.session)
+        .await
+        .map_err(AppError::internal)?;
+    match status {
+        SessionStatus::Active => {}
+        SessionStatus::Revoked => {
+            tracing::info!(%user_id, "the session was revoked");
+            session.purge();
+            return Err(see_other_error("/login"));
+        }
+        SessionStatus::Expired => {
+            tracing::info!(%user_id, "the session has expired");
+            session.purge();
+            return Err(see_other_with_flash(
+                "/login",
+                &context.flash_key,
+                FlashLevel::Info,
+                "Your session has expired. Please log in again.",
+            ));
+        }
+    }
     req.extensions_mut().insert(user_id);
+    req.extensions_mut().insert(CurrentSession(session_id));
     let resp = next.call(req).await?;
     Ok(resp)
 }
+
+// the bearer token counterpart of `reject_anoynmous_user` for the json admin apis,
+// the user id and the `ApiPrincipal` are made available to the endpoint
+pub async fn require_api_token<E: Endpoint>(
+    next: E,
+    mut req: Request,
+    scope: ApiScope,
+) -> Result<E::Output> {
+    let Some(token) = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(|token| token.trim().to_owned())
+    else {
+        return Err(bearer_challenge("missing bearer token").into());
+    };
+    let Some(context) = req.data::<StateContext>() else {
+        return Err(AppError::internal("the api token routes have no state context").into());
+    };
+    let principal = authenticate_api_token(&context.db, &token)
+        .await
+        .map_err(AppError::internal)?
+        .ok_or_else(|| bearer_challenge("invalid or revoked token"))?;
+    if !principal.has_scope(scope) {
+        return Err(AppError::new(
+            ErrorCode::Forbidden,
+            format!("the token lacks the {scope} scope"),
+        )
+        .into());
+    }
+    req.extensions_mut().insert(principal.user_id);
+    req.extensions_mut().insert(principal);
+    next.call(req).await
+}
+
+fn bearer_challenge(message: &str) -> AppError {
+    AppError::new(ErrorCode::Unauthorized, message).with_challenge("Bearer")
+}
+
+// the email provider's webhooks authenticate with the basic auth credentials
+// of the `webhooks` settings
+pub async fn require_webhook_auth<E: Endpoint>(next: E, req: Request) -> Result<E::Output> {
+    let Some(context) = req.data::<StateContext>() else {
+        return Err(AppError::internal("the webhook routes have no state context").into());
+    };
+    let Some(password) = &context.webhooks.password else {
+        tracing::warn!("a webhook came in but no webhook password is configured");
+        return Err(basic_challenge("webhooks are disabled").into());
+    };
+    let credentials = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Basic "))
+        .and_then(|encoded| general_purpose::STANDARD.decode(encoded.trim()).ok())
+        .and_then(|decoded| String::from_utf8(decoded).ok());
+    let authenticated = credentials
+        .as_deref()
+        .and_then(|credentials| credentials.split_once(':'))
+        .map(|(username, submitted)| {
+            // both are compared so the time taken doesn't tell which one was wrong
+            let username_matches = tokens_match(&context.webhooks.username, username);
+            tokens_match(password.expose_secret(), submitted) && username_matches
+        })
+        .unwrap_or_default();
+    if !authenticated {
+        return Err(basic_challenge("invalid webhook credentials").into());
+    }
+    next.call(req).await
+}
+
+// `/metrics` on the public port is scraped with `Authorization: Bearer <metrics.token>`,
+// without a token configured there is no way in
+pub async fn require_metrics_token<E: Endpoint>(
+    next: E,
+    req: Request,
+    token: Option<Secret<String>>,
+) -> Result<E::Output> {
+    let Some(token) = token else {
+        return Err(bearer_challenge("no metrics token is configured").into());
+    };
+    let submitted = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim);
+    match submitted {
+        Some(submitted) if tokens_match(token.expose_secret(), submitted) => next.call(req).await,
+        _ => Err(bearer_challenge("invalid metrics token").into()),
+    }
+}
+
+fn basic_challenge(message: &str) -> AppError {
+    AppError::new(ErrorCode::Unauthorized, message).with_challenge("Basic")
+}
+
+#[cfg(test)]
+mod tests {
+    use poem::{
+        get, handler,
+        session::{CookieConfig, MemoryStorage, ServerSession},
+        test::TestClient,
+        EndpointExt, Route,
+    };
+
+    use super::*;
+
+    #[handler]
+    fn log_in(session: &Session) {
+        session.set(USER_ID_KEY, Uuid::new_v4());
+        session.set(SESSION_ID_KEY, "a-session");
+    }
+
+    #[handler]
+    fn protected() -> &'static str {
+        "secret"
+    }
+
+    #[tokio::test]
+    async fn a_route_without_the_state_context_fails_closed() {
+        let app = Route::new()
+            .at("/login", get(log_in))
+            .at("/protected", get(protected).around(reject_anoynmous_user))
+            .with(ServerSession::new(
+                CookieConfig::default(),
+                MemoryStorage::new(),
+            ));
+        let cli = TestClient::new(app);
+        let resp = cli.get("/login").send().await;
+        let cookie = resp.0.headers()[header::SET_COOKIE]
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_owned();
+        let resp = cli
+            .get("/protected")
+            .header(header::COOKIE, cookie)
+            .send()
+            .await;
+        resp.assert_status(poem::http::StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
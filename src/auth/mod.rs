@@ -1,5 +1,39 @@
-pub use middleware::reject_anoynmous_user;
-pub use password::{get_hash, register_test_user, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{
+    authenticate_api_token, create_api_token, list_api_tokens, parse_scopes, revoke_api_token,
+    ApiPrincipal, ApiScope,
+};
+pub use bootstrap::{bootstrap_admin, BootstrapOutcome, DEFAULT_ADMIN_PASSWORD};
+pub use csrf::{csrf_token, rotate_csrf_token, verify_csrf_token, CSRF_FIELD};
+pub use middleware::{
+    reject_anoynmous_user, require_api_token, require_metrics_token, require_webhook_auth,
+};
+pub use password::{
+    change_password, get_hash, register_test_user, validate_credentials, AuthError, Credentials,
+    DummyPasswordHash,
+};
+pub use password_reset::{
+    consume_password_reset_token, is_password_reset_token_valid, issue_password_reset_token,
+    PasswordResetRequest,
+};
+pub use sessions::{
+    list_sessions, register_session, revoke_other_sessions, revoke_session, touch_session,
+    ActiveSession, CurrentSession, SessionMetadata, SessionStatus,
+};
+pub use throttle::{
+    DataRequestThrottle, Lockout, LockoutKind, LoginAttempt, LoginThrottle, PasswordResetThrottle,
+    ThrottleDecision,
+};
+pub use two_factor::{
+    disable_two_factor, enable_two_factor, generate_totp_secret, get_totp_secret, provisioning_uri,
+    verify_second_factor, verify_totp_code,
+};
 
+mod api_tokens;
+mod bootstrap;
+mod csrf;
 mod middleware;
 mod password;
+mod password_reset;
+mod sessions;
+mod throttle;
+mod two_factor;
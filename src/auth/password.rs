@@ -1,11 +1,18 @@
+use std::sync::Arc;
+
 use anyhow::{anyhow, Context};
 use argon2::{Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier};
 use base64::{engine::general_purpose, Engine};
-use sea_orm::{ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
+use sea_orm::{
+    ActiveModelTrait, ActiveValue, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait,
+    QueryFilter,
+};
 use secrecy::{ExposeSecret, Secret};
 use uuid::Uuid;
 
 use crate::{
+    configuration::PasswordHashSettings,
+    domain::NewPassword,
     entities::user::{self, Entity as Users},
     utils::spawn_blocking_with_tracing,
 };
@@ -23,33 +30,78 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "validate user's credentials", skip(db, credentials))]
+// the phc string an unknown username is checked against, hashed once with the configured
+// parameters so that it takes as long to refuse as a real user's wrong password
+#[derive(Clone)]
+pub struct DummyPasswordHash(Arc<str>);
+
+impl DummyPasswordHash {
+    pub async fn new(hash_settings: &PasswordHashSettings) -> anyhow::Result<Self> {
+        let phc =
+            hash_with_new_salt(Secret::new(Uuid::new_v4().to_string()), hash_settings).await?;
+        Ok(Self(phc.into()))
+    }
+}
+
+#[tracing::instrument(
+    name = "validate user's credentials",
+    skip(db, credentials, hash_settings, dummy_hash)
+)]
 pub async fn validate_credentials(
     db: &DatabaseConnection,
     credentials: Credentials,
+    hash_settings: &PasswordHashSettings,
+    dummy_hash: &DummyPasswordHash,
 ) -> Result<Uuid, AuthError> {
     let (id, password_hashed) = match get_user_by_credentials(db, &credentials).await {
         Ok(user) => (Some(user.id), user.password_hashed),
-        Err(e) => {
-            if let AuthError::InvalidCredentials(_) = e {
-                (
-                    None,
-                    "$argon2id$v=19$m=15000,t=2,p=1$\
-                    gZiV/M1gPc22E1AH/Jh1Hw$\
-                    CWOrkoo7oJBQ/iyh7uJOLO2aLEfrHwTWllSAxTOzRno"
-                        .to_owned(),
-                )
-            } else {
-                return Err(e);
-            }
-        }
+        Err(AuthError::InvalidCredentials(_)) => (None, dummy_hash.0.to_string()),
+        Err(e) => return Err(e),
     };
+    let outdated = needs_rehash(&password_hashed, hash_settings);
+    let password = credentials.password.clone();
     spawn_blocking_with_tracing(|| verify_password(password_hashed, credentials.password))
         .await
         .map_err(|e| anyhow!(format!("hash phc string verify error: {}", e)))??;
 
-    id.ok_or_else(|| anyhow!("unknown username"))
-        .map_err(AuthError::InvalidCredentials)
+    let id = id
+        .ok_or_else(|| anyhow!("unknown username"))
+        .map_err(AuthError::InvalidCredentials)?;
+    if outdated {
+        // the login already succeeded, a failed upgrade is retried next time
+        if let Err(e) = rehash_password(db, id, password, hash_settings).await {
+            tracing::error!(error = %e, user_id = %id, "fail to upgrade the password hash");
+        }
+    }
+    Ok(id)
+}
+
+#[tracing::instrument(name = "upgrade password hash", skip(db, password, hash_settings))]
+async fn rehash_password(
+    db: &DatabaseConnection,
+    uid: Uuid,
+    password: Secret<String>,
+    hash_settings: &PasswordHashSettings,
+) -> anyhow::Result<()> {
+    let password_hashed = hash_with_new_salt(password, hash_settings).await?;
+    store_password_hash(db, uid, password_hashed).await?;
+    tracing::info!("password hash upgraded to the current parameters");
+    Ok(())
+}
+
+// whether a stored phc string was created with other parameters than the configured ones
+fn needs_rehash(phc: &str, hash_settings: &PasswordHashSettings) -> bool {
+    let Ok(hash) = PasswordHash::new(phc) else {
+        return false;
+    };
+    let Ok(params) = Params::try_from(&hash) else {
+        return false;
+    };
+    hash.algorithm != argon2::Algorithm::Argon2id.ident()
+        || hash.version != Some(argon2::Version::V0x13.into())
+        || params.m_cost() != hash_settings.memory_kib
+        || params.t_cost() != hash_settings.iterations
+        || params.p_cost() != hash_settings.parallelism
 }
 
 #[tracing::instrument(name = "get user by provided credentials", skip(db, credentials))]
@@ -70,7 +122,7 @@ async fn get_user_by_credentials(
 }
 
 #[tracing::instrument(name = "verify password", skip(phc, password))]
-fn verify_password(phc: String, password: Secret<String>) -> Result<(), AuthError> {
+pub(crate) fn verify_password(phc: String, password: Secret<String>) -> Result<(), AuthError> {
     let expected_hash = PasswordHash::new(&phc)
         .map_err(|e| anyhow!(format!("fail to extract hash in phc string format: {}", e)))?;
     Argon2::default()
@@ -80,31 +132,121 @@ fn verify_password(phc: String, password: Secret<String>) -> Result<(), AuthErro
     Ok(())
 }
 
-pub fn get_hash(input: &str, salt: &str) -> Result<String, argon2::password_hash::Error> {
+pub fn get_hash(
+    input: &str,
+    salt: &str,
+    hash_settings: &PasswordHashSettings,
+) -> Result<String, argon2::password_hash::Error> {
     let salt = general_purpose::STANDARD.encode(salt);
     // here password_hash is already PHC format
     let password_hash = Argon2::new(
         argon2::Algorithm::Argon2id,
         argon2::Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
+        hash_settings.params()?,
     )
     .hash_password(input.as_bytes(), &salt)?
     .to_string();
     Ok(password_hash)
 }
 
-pub async fn register_test_user(
+pub async fn change_password(
+    uid: Uuid,
+    password: NewPassword,
     db: &DatabaseConnection,
+    hash_settings: &PasswordHashSettings,
+) -> Result<(), anyhow::Error> {
+    let password_hashed = hash_with_new_salt(password.inner(), hash_settings).await?;
+    store_password_hash(db, uid, password_hashed).await
+}
+
+async fn hash_with_new_salt(
+    password: Secret<String>,
+    hash_settings: &PasswordHashSettings,
+) -> anyhow::Result<String> {
+    let hash_settings = hash_settings.clone();
+    // generate a random new salt
+    let password_hashed =
+        spawn_blocking_with_tracing(move || -> Result<String, argon2::password_hash::Error> {
+            // let salt = SaltString::generate(&mut rand::thread_rng()).to_string();
+            let salt = Uuid::new_v4().to_string();
+            get_hash(password.expose_secret(), &salt, &hash_settings)
+        })
+        .await
+        .map_err(|e| {
+            anyhow!(format!(
+                "fail to join spawning tokio task for computing hash {e}"
+            ))
+        })??;
+    Ok(password_hashed)
+}
+
+async fn store_password_hash(
+    db: &DatabaseConnection,
+    uid: Uuid,
+    password_hashed: String,
+) -> anyhow::Result<()> {
+    let active_user = user::ActiveModel {
+        id: ActiveValue::Set(uid),
+        password_hashed: ActiveValue::Set(password_hashed),
+        ..Default::default()
+    };
+    active_user.update(db).await?;
+    Ok(())
+}
+
+pub async fn register_test_user<C: ConnectionTrait>(
+    db: &C,
     username: &str,
     password: &str,
     salt: &str,
+    hash_settings: &PasswordHashSettings,
 ) -> anyhow::Result<()> {
-    let password_hash = get_hash(password, salt).context("fail to register_test_user")?;
+    let password_hash =
+        get_hash(password, salt, hash_settings).context("fail to register_test_user")?;
     let new_user = user::ActiveModel {
         id: ActiveValue::Set(Uuid::new_v4()),
         user_name: ActiveValue::Set(username.to_owned()),
         password_hashed: ActiveValue::Set(password_hash),
+        ..Default::default()
     };
     Users::insert(new_user).exec(db).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hashes_with_other_parameters_need_a_rehash() {
+        let default_settings = PasswordHashSettings::default();
+        let cheaper_settings = PasswordHashSettings {
+            memory_kib: 4096,
+            iterations: 1,
+            parallelism: 1,
+        };
+        let hash = get_hash("password", "some-salt", &default_settings).unwrap();
+        assert!(!needs_rehash(&hash, &default_settings));
+        assert!(needs_rehash(&hash, &cheaper_settings));
+    }
+
+    #[tokio::test]
+    async fn the_dummy_hash_follows_the_configured_parameters() {
+        let settings = PasswordHashSettings {
+            memory_kib: 8192,
+            iterations: 3,
+            parallelism: 2,
+        };
+        let dummy = DummyPasswordHash::new(&settings).await.unwrap();
+        assert!(!needs_rehash(&dummy.0, &settings));
+        assert!(needs_rehash(&dummy.0, &PasswordHashSettings::default()));
+    }
+
+    #[test]
+    fn unparsable_hashes_are_left_alone() {
+        assert!(!needs_rehash(
+            "not a phc string",
+            &PasswordHashSettings::default()
+        ));
+    }
+}
@@ -0,0 +1,149 @@
+use anyhow::Context;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait,
+    QueryFilter, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::{
+    domain::Email,
+    entities::{
+        password_reset_tokens::{self, Entity as PasswordResetTokens},
+        user::{self, Entity as Users},
+    },
+    utils::sha256_hex,
+};
+
+const RESET_TOKEN_TTL_MINUTES: i64 = 30;
+
+pub struct PasswordResetRequest {
+    pub recipient: Email,
+    pub token: String,
+}
+
+// returns None if the username is unknown or has no email to send the link to,
+// the caller must answer the same way in both cases to not leak which accounts exist
+#[tracing::instrument(name = "issue a password reset token", skip(db))]
+pub async fn issue_password_reset_token(
+    db: &DatabaseConnection,
+    username: &str,
+) -> anyhow::Result<Option<PasswordResetRequest>> {
+    let Some(user) = Users::find()
+        .filter(user::Column::UserName.eq(username))
+        .one(db)
+        .await
+        .context("fail to find the user")?
+    else {
+        return Ok(None);
+    };
+    let Some(email) = user.email else {
+        tracing::warn!("the user has no email, cannot send a password reset link");
+        return Ok(None);
+    };
+    let recipient = Email::parse(email).map_err(|e| anyhow::anyhow!(e))?;
+    let token = generate_reset_token();
+    let now = chrono::Utc::now();
+    let new_token = password_reset_tokens::ActiveModel {
+        token_hash: ActiveValue::Set(hash_reset_token(&token)),
+        user_id: ActiveValue::Set(user.id),
+        expires_at: ActiveValue::Set(
+            (now + chrono::Duration::minutes(RESET_TOKEN_TTL_MINUTES)).into(),
+        ),
+        used_at: ActiveValue::NotSet,
+    };
+    // only the latest link works, the ones sent before it are spent
+    let txn = db.begin().await?;
+    let now: DateTimeWithTimeZone = now.into();
+    PasswordResetTokens::update_many()
+        .col_expr(password_reset_tokens::Column::UsedAt, now.into())
+        .filter(password_reset_tokens::Column::UserId.eq(user.id))
+        .filter(password_reset_tokens::Column::UsedAt.is_null())
+        .exec(&txn)
+        .await
+        .context("fail to spend the earlier password reset tokens")?;
+    PasswordResetTokens::insert(new_token)
+        .exec(&txn)
+        .await
+        .context("fail to store the password reset token")?;
+    txn.commit().await?;
+    Ok(Some(PasswordResetRequest { recipient, token }))
+}
+
+pub async fn is_password_reset_token_valid(
+    db: &DatabaseConnection,
+    token: &str,
+) -> anyhow::Result<bool> {
+    Ok(PasswordResetTokens::find()
+        .filter(valid_token(token))
+        .one(db)
+        .await
+        .context("fail to look up the password reset token")?
+        .is_some())
+}
+
+// marks the token used and returns its owner, the conditional update makes
+// sure two concurrent requests can't both redeem the same token
+#[tracing::instrument(name = "consume a password reset token", skip(db, token))]
+pub async fn consume_password_reset_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> anyhow::Result<Option<Uuid>> {
+    let Some(reset_token) = PasswordResetTokens::find()
+        .filter(valid_token(token))
+        .one(db)
+        .await
+        .context("fail to look up the password reset token")?
+    else {
+        return Ok(None);
+    };
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    let updated = PasswordResetTokens::update_many()
+        .col_expr(password_reset_tokens::Column::UsedAt, now.into())
+        .filter(password_reset_tokens::Column::TokenHash.eq(reset_token.token_hash))
+        .filter(password_reset_tokens::Column::UsedAt.is_null())
+        .exec(db)
+        .await
+        .context("fail to mark the password reset token as used")?;
+    if updated.rows_affected != 1 {
+        return Ok(None);
+    }
+    Ok(Some(reset_token.user_id))
+}
+
+fn valid_token(token: &str) -> sea_orm::Condition {
+    let now: DateTimeWithTimeZone = chrono::Utc::now().into();
+    sea_orm::Condition::all()
+        .add(password_reset_tokens::Column::TokenHash.eq(hash_reset_token(token)))
+        .add(password_reset_tokens::Column::UsedAt.is_null())
+        .add(password_reset_tokens::Column::ExpiresAt.gt(now))
+}
+
+fn generate_reset_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+fn hash_reset_token(token: &str) -> String {
+    sha256_hex(token)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_hashed_deterministically() {
+        let token = generate_reset_token();
+        assert_eq!(token.len(), 32);
+        assert_eq!(hash_reset_token(&token), hash_reset_token(&token));
+        assert_ne!(hash_reset_token(&token), token);
+        assert_ne!(
+            hash_reset_token(&token),
+            hash_reset_token(&generate_reset_token())
+        );
+    }
+}
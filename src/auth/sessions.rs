@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::configuration::SessionSettings;
+
+const USER_SESSIONS_PREFIX: &str = "user_sessions";
+
+// the check and the write of `touch_session` in one step, a revoke landing in
+// between would otherwise have its entry written back. the metadata is updated
+// in place so that a revoked entry is never recreated
+static TOUCH_SESSION: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        -- touch_session
+        local stored = redis.call('HGET', KEYS[1], ARGV[1])
+        if not stored then
+            return 'revoked'
+        end
+        local metadata = cjson.decode(stored)
+        local now = tonumber(ARGV[2])
+        if now - metadata.last_seen > tonumber(ARGV[3])
+            or now - metadata.created_at > tonumber(ARGV[4]) then
+            redis.call('HDEL', KEYS[1], ARGV[1])
+            return 'expired'
+        end
+        metadata.last_seen = now
+        redis.call('HSET', KEYS[1], ARGV[1], cjson.encode(metadata))
+        redis.call('EXPIRE', KEYS[1], ARGV[5])
+        return 'active'
+        "#,
+    )
+});
+
+// the session storage can't be searched by user, so every login also registers
+// its own id in a redis hash per user; a session whose id is no longer in that
+// hash has been revoked
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionMetadata {
+    pub created_at: i64,
+    pub last_seen: i64,
+    pub ip: String,
+    pub user_agent: String,
+}
+
+#[derive(Debug)]
+pub struct ActiveSession {
+    pub id: String,
+    pub metadata: SessionMetadata,
+}
+
+// the id of the session handling the request, inserted by `reject_anoynmous_user`
+#[derive(Debug, Clone)]
+pub struct CurrentSession(pub String);
+
+impl CurrentSession {
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+#[tracing::instrument(name = "register a session", skip(redis, settings))]
+pub async fn register_session(
+    redis: &ConnectionManager,
+    user_id: Uuid,
+    ip: &str,
+    user_agent: &str,
+    settings: &SessionSettings,
+) -> anyhow::Result<String> {
+    let session_id = Uuid::new_v4().to_string();
+    let now = chrono::Utc::now().timestamp();
+    let metadata = SessionMetadata {
+        created_at: now,
+        last_seen: now,
+        ip: ip.to_owned(),
+        user_agent: user_agent.to_owned(),
+    };
+    let key = user_sessions_key(user_id);
+    let mut conn = redis.clone();
+    conn.hset::<_, _, _, ()>(&key, &session_id, serde_json::to_string(&metadata)?)
+        .await?;
+    conn.expire::<_, ()>(&key, stale_after_seconds(settings) as usize)
+        .await?;
+    Ok(session_id)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionStatus {
+    Active,
+    Revoked,
+    // idle or too old, the entry has been removed
+    Expired,
+}
+
+// checks the session against the timeouts and records the activity if it is still valid
+pub async fn touch_session(
+    redis: &ConnectionManager,
+    user_id: Uuid,
+    session_id: &str,
+    settings: &SessionSettings,
+) -> anyhow::Result<SessionStatus> {
+    let mut conn = redis.clone();
+    let status: String = TOUCH_SESSION
+        .key(user_sessions_key(user_id))
+        .arg(session_id)
+        .arg(chrono::Utc::now().timestamp())
+        .arg(settings.idle_timeout_seconds)
+        .arg(settings.absolute_timeout_seconds)
+        .arg(stale_after_seconds(settings))
+        .invoke_async(&mut conn)
+        .await?;
+    match status.as_str() {
+        "active" => Ok(SessionStatus::Active),
+        "expired" => Ok(SessionStatus::Expired),
+        "revoked" => Ok(SessionStatus::Revoked),
+        status => Err(anyhow::anyhow!("unexpected session status: {status}")),
+    }
+}
+
+// the most recently used first
+pub async fn list_sessions(
+    redis: &ConnectionManager,
+    user_id: Uuid,
+    settings: &SessionSettings,
+) -> anyhow::Result<Vec<ActiveSession>> {
+    let key = user_sessions_key(user_id);
+    let mut conn = redis.clone();
+    let stored: HashMap<String, String> = conn.hgetall(&key).await?;
+    let now = chrono::Utc::now().timestamp();
+    let mut sessions = Vec::with_capacity(stored.len());
+    for (id, metadata) in stored {
+        let metadata: SessionMetadata = serde_json::from_str(&metadata)?;
+        if now - metadata.last_seen > stale_after_seconds(settings) as i64 {
+            conn.hdel::<_, _, ()>(&key, &id).await?;
+            continue;
+        }
+        // kept until then, so that its next request is told why it was logged out
+        if now - metadata.last_seen > settings.idle_timeout().num_seconds()
+            || now - metadata.created_at > settings.absolute_timeout().num_seconds()
+        {
+            continue;
+        }
+        sessions.push(ActiveSession { id, metadata });
+    }
+    sessions.sort_by_key(|session| std::cmp::Reverse(session.metadata.last_seen));
+    Ok(sessions)
+}
+
+// returns false if there was no such session
+#[tracing::instrument(name = "revoke a session", skip(redis))]
+pub async fn revoke_session(
+    redis: &ConnectionManager,
+    user_id: Uuid,
+    session_id: &str,
+) -> anyhow::Result<bool> {
+    let mut conn = redis.clone();
+    let removed: u64 = conn.hdel(user_sessions_key(user_id), session_id).await?;
+    Ok(removed > 0)
+}
+
+// revokes every session of the user except `keep`, e.g. the one changing the password
+#[tracing::instrument(name = "revoke other sessions", skip(redis))]
+pub async fn revoke_other_sessions(
+    redis: &ConnectionManager,
+    user_id: Uuid,
+    keep: Option<&str>,
+) -> anyhow::Result<()> {
+    let key = user_sessions_key(user_id);
+    let mut conn = redis.clone();
+    let Some(keep) = keep else {
+        conn.del::<_, ()>(&key).await?;
+        return Ok(());
+    };
+    let ids: Vec<String> = conn.hkeys(&key).await?;
+    let others: Vec<&String> = ids.iter().filter(|id| id.as_str() != keep).collect();
+    if !others.is_empty() {
+        conn.hdel::<_, _, ()>(&key, others).await?;
+    }
+    Ok(())
+}
+
+// entries of sessions that silently expired are dropped this long after their
+// last activity, no session is valid by then
+fn stale_after_seconds(settings: &SessionSettings) -> u64 {
+    2 * settings.absolute_timeout_seconds
+}
+
+fn user_sessions_key(user_id: Uuid) -> String {
+    format!("{USER_SESSIONS_PREFIX}:{user_id}")
+}
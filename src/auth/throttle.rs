@@ -0,0 +1,410 @@
+use std::time::Duration;
+
+use once_cell::sync::Lazy;
+use redis::{aio::ConnectionManager, AsyncCommands, Script};
+
+use crate::{
+    configuration::{
+        DataRequestThrottleSettings, LoginThrottleSettings, PasswordResetThrottleSettings,
+        ThrottleLimits,
+    },
+    utils::sha256_hex,
+};
+
+const FAILURES_PREFIX: &str = "login_failures";
+const LOCKOUT_PREFIX: &str = "login_lockout";
+const DATA_REQUESTS_PREFIX: &str = "data_requests";
+const PASSWORD_RESETS_PREFIX: &str = "password_resets";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutKind {
+    User,
+    Ip,
+}
+
+impl LockoutKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            LockoutKind::User => "user",
+            LockoutKind::Ip => "ip",
+        }
+    }
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "user" => Ok(LockoutKind::User),
+            "ip" => Ok(LockoutKind::Ip),
+            other => Err(format!("unknown lockout kind: {other}")),
+        }
+    }
+}
+
+impl std::fmt::Display for LockoutKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum ThrottleDecision {
+    Allowed {
+        delay: Duration,
+        attempt: LoginAttempt,
+    },
+    Locked {
+        retry_after: Duration,
+    },
+}
+
+// an attempt that got past the throttle, already counted as a failure
+// until `record_success` gives it back
+#[derive(Debug, PartialEq, Eq)]
+pub struct LoginAttempt {
+    username: String,
+    ip: String,
+    user_failures: u64,
+    ip_failures: u64,
+}
+
+#[derive(Debug)]
+pub struct Lockout {
+    pub kind: LockoutKind,
+    pub subject: String,
+    pub remaining: Duration,
+}
+
+// the lockout check and the count of the attempt in one step, so that concurrent
+// attempts can't all get past the check before the first failure is recorded.
+// an attempt past `lockout_after` locks its subject out on the spot
+static START_ATTEMPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        -- start_login_attempt
+        local retry_after = math.max(redis.call('TTL', KEYS[1]), redis.call('TTL', KEYS[2]))
+        if retry_after > 0 then
+            return {0, retry_after}
+        end
+        local failures = {}
+        for i = 1, 2 do
+            failures[i] = redis.call('INCR', KEYS[i + 2])
+            redis.call('EXPIRE', KEYS[i + 2], ARGV[1])
+        end
+        for i = 1, 2 do
+            if failures[i] > tonumber(ARGV[i + 2]) then
+                redis.call('SET', KEYS[i], failures[i], 'EX', ARGV[2])
+                redis.call('DEL', KEYS[i + 2])
+                return {0, tonumber(ARGV[2])}
+            end
+        end
+        return {1, failures[1], failures[2]}
+        "#,
+    )
+});
+
+// the ip counter only gets the attempt back while it still exists, decrementing
+// an expired counter would leave a negative one behind without a ttl
+static FINISH_ATTEMPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+        -- finish_login_attempt
+        redis.call('DEL', KEYS[1])
+        if redis.call('EXISTS', KEYS[2]) == 1 then
+            redis.call('DECR', KEYS[2])
+        end
+        return 1
+        "#,
+    )
+});
+
+// login attempts are counted in redis per username and per client ip as they start,
+// each failure past `delay_after` doubles the wait before the next attempt is checked,
+// and reaching `lockout_after` blocks the subject for `lockout_seconds`
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+impl LoginThrottle {
+    pub fn new(redis: ConnectionManager, settings: LoginThrottleSettings) -> Self {
+        Self { redis, settings }
+    }
+
+    #[tracing::instrument(name = "check login throttle", skip(self))]
+    pub async fn check(&self, username: &str, ip: &str) -> anyhow::Result<ThrottleDecision> {
+        let mut conn = self.redis.clone();
+        let outcome: Vec<u64> = START_ATTEMPT
+            .key(lockout_key(LockoutKind::User, username))
+            .key(lockout_key(LockoutKind::Ip, ip))
+            .key(failures_key(LockoutKind::User, username))
+            .key(failures_key(LockoutKind::Ip, ip))
+            .arg(self.settings.window_seconds)
+            .arg(self.settings.lockout_seconds)
+            .arg(self.settings.per_user.lockout_after)
+            .arg(self.settings.per_ip.lockout_after)
+            .invoke_async(&mut conn)
+            .await?;
+        match outcome[..] {
+            [0, retry_after] => Ok(ThrottleDecision::Locked {
+                retry_after: Duration::from_secs(retry_after),
+            }),
+            [1, user_failures, ip_failures] => {
+                // the delay depends on the failures before this attempt
+                let delay = progressive_delay(
+                    &self.settings,
+                    &self.settings.per_user,
+                    Some(user_failures - 1),
+                )
+                .max(progressive_delay(
+                    &self.settings,
+                    &self.settings.per_ip,
+                    Some(ip_failures - 1),
+                ));
+                Ok(ThrottleDecision::Allowed {
+                    delay,
+                    attempt: LoginAttempt {
+                        username: username.to_owned(),
+                        ip: ip.to_owned(),
+                        user_failures,
+                        ip_failures,
+                    },
+                })
+            }
+            _ => Err(anyhow::anyhow!("unexpected throttle outcome: {outcome:?}")),
+        }
+    }
+
+    // the failure is already counted, a subject that just reached
+    // `lockout_after` is locked out without waiting for its next attempt
+    #[tracing::instrument(name = "record a failed login", skip(self))]
+    pub async fn record_failure(&self, attempt: &LoginAttempt) -> anyhow::Result<()> {
+        self.lock_out_at_limit(
+            LockoutKind::User,
+            &attempt.username,
+            attempt.user_failures,
+            &self.settings.per_user,
+        )
+        .await?;
+        self.lock_out_at_limit(
+            LockoutKind::Ip,
+            &attempt.ip,
+            attempt.ip_failures,
+            &self.settings.per_ip,
+        )
+        .await
+    }
+
+    // the account counter is reset, the ip one only gets this attempt back so
+    // that a successful login doesn't whitewash an ip that keeps guessing other usernames
+    pub async fn record_success(&self, attempt: &LoginAttempt) -> anyhow::Result<()> {
+        let mut conn = self.redis.clone();
+        FINISH_ATTEMPT
+            .key(failures_key(LockoutKind::User, &attempt.username))
+            .key(failures_key(LockoutKind::Ip, &attempt.ip))
+            .invoke_async::<_, ()>(&mut conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn list_lockouts(&self) -> anyhow::Result<Vec<Lockout>> {
+        let mut conn = self.redis.clone();
+        let keys: Vec<String> = {
+            let mut iter = conn
+                .scan_match::<_, String>(format!("{LOCKOUT_PREFIX}:*"))
+                .await?;
+            let mut keys = Vec::new();
+            while let Some(key) = iter.next_item().await {
+                keys.push(key);
+            }
+            keys
+        };
+        let mut lockouts = Vec::with_capacity(keys.len());
+        for key in keys {
+            let Some((kind, subject)) = parse_lockout_key(&key) else {
+                continue;
+            };
+            let remaining: i64 = conn.ttl(&key).await?;
+            if remaining > 0 {
+                lockouts.push(Lockout {
+                    kind,
+                    subject: subject.to_owned(),
+                    remaining: Duration::from_secs(remaining as u64),
+                });
+            }
+        }
+        lockouts.sort_by_key(|lockout| std::cmp::Reverse(lockout.remaining));
+        Ok(lockouts)
+    }
+
+    pub async fn clear_lockout(&self, kind: LockoutKind, subject: &str) -> anyhow::Result<()> {
+        let mut conn = self.redis.clone();
+        conn.del::<_, ()>(&[lockout_key(kind, subject), failures_key(kind, subject)])
+            .await?;
+        Ok(())
+    }
+
+    async fn lock_out_at_limit(
+        &self,
+        kind: LockoutKind,
+        subject: &str,
+        failures: u64,
+        limits: &ThrottleLimits,
+    ) -> anyhow::Result<()> {
+        if failures < limits.lockout_after {
+            return Ok(());
+        }
+        tracing::warn!(%kind, subject, failures, "too many failed logins, locking out");
+        let mut conn = self.redis.clone();
+        conn.set_ex::<_, _, ()>(
+            lockout_key(kind, subject),
+            failures,
+            self.settings.lockout_seconds as usize,
+        )
+        .await?;
+        // start over once the lockout expires
+        conn.del::<_, ()>(failures_key(kind, subject)).await?;
+        Ok(())
+    }
+}
+
+// data requests are counted in redis per address and per client ip, past the
+// limit of either one a request is refused until the window is over
+#[derive(Clone)]
+pub struct DataRequestThrottle {
+    redis: ConnectionManager,
+    settings: DataRequestThrottleSettings,
+}
+
+impl DataRequestThrottle {
+    pub fn new(redis: ConnectionManager, settings: DataRequestThrottleSettings) -> Self {
+        Self { redis, settings }
+    }
+
+    // counts the request, `false` when it is over a limit
+    #[tracing::instrument(name = "check data request throttle", skip(self, email))]
+    pub async fn allow(&self, email: &str, ip: &str) -> anyhow::Result<bool> {
+        // the key doesn't keep an erased address around for the window
+        let address_key = format!(
+            "{DATA_REQUESTS_PREFIX}:address:{}",
+            sha256_hex(&email.trim().to_lowercase())
+        );
+        let ip_key = format!("{DATA_REQUESTS_PREFIX}:ip:{ip}");
+        let (per_address, per_ip) = count_in_window(
+            &self.redis,
+            &address_key,
+            &ip_key,
+            self.settings.window_seconds,
+        )
+        .await?;
+        Ok(per_address <= self.settings.per_address && per_ip <= self.settings.per_ip)
+    }
+}
+
+// password reset requests are counted the same way, per username and per client ip;
+// unknown usernames count too, so the limit tells nothing about which accounts exist
+#[derive(Clone)]
+pub struct PasswordResetThrottle {
+    redis: ConnectionManager,
+    settings: PasswordResetThrottleSettings,
+}
+
+impl PasswordResetThrottle {
+    pub fn new(redis: ConnectionManager, settings: PasswordResetThrottleSettings) -> Self {
+        Self { redis, settings }
+    }
+
+    // counts the request, `false` when it is over a limit
+    #[tracing::instrument(name = "check password reset throttle", skip(self))]
+    pub async fn allow(&self, username: &str, ip: &str) -> anyhow::Result<bool> {
+        let username_key = format!("{PASSWORD_RESETS_PREFIX}:username:{username}");
+        let ip_key = format!("{PASSWORD_RESETS_PREFIX}:ip:{ip}");
+        let (per_username, per_ip) = count_in_window(
+            &self.redis,
+            &username_key,
+            &ip_key,
+            self.settings.window_seconds,
+        )
+        .await?;
+        Ok(per_username <= self.settings.per_username && per_ip <= self.settings.per_ip)
+    }
+}
+
+// increments both counters, a counter starts over `window_seconds` after its last request
+async fn count_in_window(
+    redis: &ConnectionManager,
+    subject_key: &str,
+    ip_key: &str,
+    window_seconds: u64,
+) -> redis::RedisResult<(u64, u64)> {
+    let mut conn = redis.clone();
+    let window = window_seconds as usize;
+    redis::pipe()
+        .atomic()
+        .incr(subject_key, 1)
+        .expire(subject_key, window)
+        .ignore()
+        .incr(ip_key, 1)
+        .expire(ip_key, window)
+        .ignore()
+        .query_async(&mut conn)
+        .await
+}
+
+fn progressive_delay(
+    settings: &LoginThrottleSettings,
+    limits: &ThrottleLimits,
+    failures: Option<u64>,
+) -> Duration {
+    let failures = failures.unwrap_or(0);
+    if failures < limits.delay_after {
+        return Duration::ZERO;
+    }
+    let exponent = (failures - limits.delay_after).min(16) as u32;
+    let delay = settings
+        .base_delay_milliseconds
+        .saturating_mul(2u64.pow(exponent));
+    Duration::from_millis(delay.min(settings.max_delay_milliseconds))
+}
+
+fn failures_key(kind: LockoutKind, subject: &str) -> String {
+    format!("{FAILURES_PREFIX}:{kind}:{subject}")
+}
+
+fn lockout_key(kind: LockoutKind, subject: &str) -> String {
+    format!("{LOCKOUT_PREFIX}:{kind}:{subject}")
+}
+
+fn parse_lockout_key(key: &str) -> Option<(LockoutKind, &str)> {
+    let rest = key.strip_prefix(LOCKOUT_PREFIX)?.strip_prefix(':')?;
+    let (kind, subject) = rest.split_once(':')?;
+    Some((LockoutKind::parse(kind).ok()?, subject))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delay_doubles_after_the_free_attempts() {
+        let settings = LoginThrottleSettings::default();
+        let limits = &settings.per_user;
+        let delay = |failures| progressive_delay(&settings, limits, Some(failures));
+        assert_eq!(progressive_delay(&settings, limits, None), Duration::ZERO);
+        assert_eq!(delay(limits.delay_after - 1), Duration::ZERO);
+        assert_eq!(delay(limits.delay_after), Duration::from_millis(500));
+        assert_eq!(delay(limits.delay_after + 1), Duration::from_millis(1000));
+        assert_eq!(delay(limits.delay_after + 100), Duration::from_millis(8000));
+    }
+
+    #[test]
+    fn lockout_keys_round_trip() {
+        let key = lockout_key(LockoutKind::Ip, "::1");
+        assert_eq!(parse_lockout_key(&key), Some((LockoutKind::Ip, "::1")));
+        let key = lockout_key(LockoutKind::User, "foo:bar");
+        assert_eq!(
+            parse_lockout_key(&key),
+            Some((LockoutKind::User, "foo:bar"))
+        );
+        assert_eq!(parse_lockout_key("login_lockout:nope:foo"), None);
+    }
+}
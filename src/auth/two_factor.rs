@@ -0,0 +1,281 @@
+use anyhow::{anyhow, Context};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::Expr, ActiveModelTrait, ActiveValue, ColumnTrait,
+    Condition, DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+};
+use secrecy::Secret;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+use super::password::{get_hash, verify_password};
+use crate::{
+    configuration::PasswordHashSettings,
+    entities::{
+        recovery_codes::{self, Entity as RecoveryCodes},
+        user::{self, Entity as Users},
+    },
+    utils::spawn_blocking_with_tracing,
+};
+
+const ISSUER: &str = "zero2prod";
+const RECOVERY_CODE_COUNT: usize = 10;
+
+pub fn generate_totp_secret() -> String {
+    totp_rs::Secret::generate_secret().to_encoded().to_string()
+}
+
+fn totp(secret: &str, account_name: &str) -> anyhow::Result<TOTP> {
+    let secret = totp_rs::Secret::Encoded(secret.to_owned())
+        .to_bytes()
+        .map_err(|e| anyhow!("invalid totp secret: {:?}", e))?;
+    // sha1, 6 digits and 30 second steps are what authenticator apps expect
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        secret,
+        Some(ISSUER.to_owned()),
+        account_name.replace(':', "_"),
+    )
+    .context("fail to build totp")
+}
+
+// the otpauth:// uri that authenticator apps scan as a qr code
+pub fn provisioning_uri(secret: &str, username: &str) -> anyhow::Result<String> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+// the time step `code` was generated for, `None` when it is the code of none
+// of the steps around the current one
+pub fn verify_totp_code(secret: &str, code: &str) -> anyhow::Result<Option<i64>> {
+    let code = code.trim();
+    let mut totp = totp(secret, "")?;
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .context("system time is before the unix epoch")?
+        .as_secs();
+    let skew = u64::from(totp.skew);
+    let current = now / totp.step;
+    // each step is checked on its own to know which one matched
+    totp.skew = 0;
+    let step = (current.saturating_sub(skew)..=current + skew)
+        .find(|step| totp.check(code, step * totp.step));
+    Ok(step.map(|step| step as i64))
+}
+
+fn generate_recovery_code() -> String {
+    let raw: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .take(10)
+        .collect();
+    format!("{}-{}", &raw[..5], &raw[5..])
+}
+
+// the totp secret of the user if two-factor authentication is enabled
+pub async fn get_totp_secret(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+) -> Result<Option<String>, sea_orm::DbErr> {
+    let user = Users::find_by_id(user_id).one(db).await?;
+    Ok(user.and_then(|u| u.totp_enabled_at.and(u.totp_secret)))
+}
+
+// returns the plain recovery codes, they are only shown to the user once
+#[tracing::instrument(
+    name = "enable two-factor authentication",
+    skip(db, secret, hash_settings)
+)]
+pub async fn enable_two_factor(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    secret: String,
+    // of the code the enrolment was confirmed with
+    totp_step: i64,
+    hash_settings: &PasswordHashSettings,
+) -> anyhow::Result<Vec<String>> {
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| generate_recovery_code())
+        .collect();
+    let plain_codes = codes.clone();
+    let hash_settings = hash_settings.clone();
+    let hashes = spawn_blocking_with_tracing(move || {
+        codes
+            .iter()
+            .map(|code| get_hash(code, &Uuid::new_v4().to_string(), &hash_settings))
+            .collect::<Result<Vec<_>, _>>()
+    })
+    .await
+    .context("fail to join the hashing task")?
+    .map_err(|e| anyhow!("fail to hash recovery codes: {}", e))?;
+
+    let txn = db.begin().await?;
+    user::ActiveModel {
+        id: ActiveValue::Set(user_id),
+        totp_secret: ActiveValue::Set(Some(secret)),
+        totp_enabled_at: ActiveValue::Set(Some(now())),
+        totp_last_step: ActiveValue::Set(Some(totp_step)),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+    RecoveryCodes::delete_many()
+        .filter(recovery_codes::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    RecoveryCodes::insert_many(
+        hashes
+            .into_iter()
+            .map(|code_hash| recovery_codes::ActiveModel {
+                id: ActiveValue::Set(Uuid::new_v4()),
+                user_id: ActiveValue::Set(user_id),
+                code_hash: ActiveValue::Set(code_hash),
+                used_at: ActiveValue::Set(None),
+            }),
+    )
+    .exec(&txn)
+    .await?;
+    txn.commit().await?;
+    Ok(plain_codes)
+}
+
+#[tracing::instrument(name = "disable two-factor authentication", skip(db))]
+pub async fn disable_two_factor(db: &DatabaseConnection, user_id: Uuid) -> anyhow::Result<()> {
+    let txn = db.begin().await?;
+    user::ActiveModel {
+        id: ActiveValue::Set(user_id),
+        totp_secret: ActiveValue::Set(None),
+        totp_enabled_at: ActiveValue::Set(None),
+        totp_last_step: ActiveValue::Set(None),
+        ..Default::default()
+    }
+    .update(&txn)
+    .await?;
+    RecoveryCodes::delete_many()
+        .filter(recovery_codes::Column::UserId.eq(user_id))
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(())
+}
+
+// accepts either a current totp code or an unused recovery code; a totp code
+// is only accepted for a step newer than the last accepted one and a recovery
+// code is burnt once it has been accepted, so that neither can be replayed
+#[tracing::instrument(name = "verify the second factor", skip(db, code))]
+pub async fn verify_second_factor(
+    db: &DatabaseConnection,
+    user_id: Uuid,
+    code: &str,
+) -> anyhow::Result<bool> {
+    let Some(secret) = get_totp_secret(db, user_id).await? else {
+        return Ok(false);
+    };
+    let code = code.trim().to_owned();
+    if code.chars().all(|c| c.is_ascii_digit()) {
+        let Some(step) = verify_totp_code(&secret, &code)? else {
+            return Ok(false);
+        };
+        // the check and the write in one statement, two requests racing with
+        // the same code can't both get in
+        let accepted = Users::update_many()
+            .col_expr(user::Column::TotpLastStep, Expr::value(step))
+            .filter(user::Column::Id.eq(user_id))
+            .filter(
+                Condition::any()
+                    .add(user::Column::TotpLastStep.is_null())
+                    .add(user::Column::TotpLastStep.lt(step)),
+            )
+            .exec(db)
+            .await?;
+        if accepted.rows_affected != 1 {
+            tracing::warn!("a totp code was replayed");
+            return Ok(false);
+        }
+        return Ok(true);
+    }
+
+    let unused_codes = RecoveryCodes::find()
+        .filter(recovery_codes::Column::UserId.eq(user_id))
+        .filter(recovery_codes::Column::UsedAt.is_null())
+        .all(db)
+        .await?;
+    let matched = spawn_blocking_with_tracing(move || {
+        unused_codes.into_iter().find(|recovery_code| {
+            verify_password(
+                recovery_code.code_hash.clone(),
+                Secret::new(code.to_ascii_lowercase()),
+            )
+            .is_ok()
+        })
+    })
+    .await
+    .context("fail to join the verifying task")?;
+    let Some(matched) = matched else {
+        return Ok(false);
+    };
+    // only one of the requests racing with the same code burns it
+    let burnt = RecoveryCodes::update_many()
+        .col_expr(recovery_codes::Column::UsedAt, Expr::value(now()))
+        .filter(recovery_codes::Column::Id.eq(matched.id))
+        .filter(recovery_codes::Column::UsedAt.is_null())
+        .exec(db)
+        .await?;
+    if burnt.rows_affected != 1 {
+        tracing::warn!("a recovery code was used twice");
+        return Ok(false);
+    }
+    tracing::info!("a recovery code has been used");
+    Ok(true)
+}
+
+fn now() -> DateTimeWithTimeZone {
+    chrono::Utc::now().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn current_code_is_accepted() {
+        let secret = generate_totp_secret();
+        let code = totp(&secret, "foo").unwrap().generate_current().unwrap();
+        assert!(verify_totp_code(&secret, &code).unwrap().is_some());
+        assert_eq!(verify_totp_code(&secret, "abcdef").unwrap(), None);
+    }
+
+    #[test]
+    fn the_step_of_a_code_is_found() {
+        let secret = generate_totp_secret();
+        let totp = totp(&secret, "foo").unwrap();
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let next = totp.generate(now + totp.step);
+        assert_eq!(
+            verify_totp_code(&secret, &next).unwrap(),
+            Some((now / totp.step + 1) as i64)
+        );
+        let stale = totp.generate(now - 10 * totp.step);
+        assert_eq!(verify_totp_code(&secret, &stale).unwrap(), None);
+    }
+
+    #[test]
+    fn provisioning_uri_contains_the_secret() {
+        let secret = generate_totp_secret();
+        let uri = provisioning_uri(&secret, "foo").unwrap();
+        assert!(uri.starts_with("otpauth://totp/zero2prod:foo"), "{}", uri);
+        assert!(uri.contains(&secret), "{}", uri);
+    }
+
+    #[test]
+    fn recovery_codes_are_well_formed() {
+        let code = generate_recovery_code();
+        assert_eq!(code.len(), 11);
+        assert_eq!(code.chars().nth(5), Some('-'));
+    }
+}
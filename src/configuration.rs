@@ -1,10 +1,10 @@
 use std::path::Path;
 
 use config::{Config, Environment};
+use poem::web::cookie::SameSite;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
-use tracing::info;
 
 use crate::domain::Email;
 
@@ -15,6 +15,22 @@ pub struct Configuration {
     pub db: RelationalDBSettings,
     pub email_client: EmailClientSettings,
     pub redis_uri: Secret<String>,
+    #[serde(default)]
+    pub login_throttle: LoginThrottleSettings,
+    #[serde(default)]
+    pub data_request_throttle: DataRequestThrottleSettings,
+    #[serde(default)]
+    pub password_reset_throttle: PasswordResetThrottleSettings,
+    #[serde(default)]
+    pub password_hash: PasswordHashSettings,
+    #[serde(default)]
+    pub session: SessionSettings,
+    #[serde(default)]
+    pub telemetry: TelemetrySettings,
+    #[serde(default)]
+    pub metrics: MetricsSettings,
+    #[serde(default)]
+    pub webhooks: WebhookSettings,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -24,6 +40,11 @@ pub struct AppSettings {
     pub base_url: String,
     pub admin_username: String,
     pub admin_password: String,
+    // signs the flash message cookies
+    pub hmac_secret: Secret<String>,
+    // adds the error chain of internal errors to problem responses, never in production
+    #[serde(default)]
+    pub expose_internal_errors: bool,
 }
 
 #[derive(Deserialize, Debug, Clone)]
@@ -35,6 +56,19 @@ pub struct RelationalDBSettings {
     pub host: String,
     pub name: String,
     pub require_ssl: bool,
+    #[serde(default)]
+    pub migrations: MigrationMode,
+}
+
+// what the server does on startup about migrations the database has not applied yet
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum MigrationMode {
+    // applies them, replicas starting together take turns through an advisory lock
+    Apply,
+    // refuses to start, the migrations are run separately
+    #[default]
+    Verify,
 }
 
 impl RelationalDBSettings {
@@ -75,9 +109,244 @@ impl EmailClientSettings {
     }
 }
 
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct LoginThrottleSettings {
+    // failures are forgotten once nothing happens within the window
+    pub window_seconds: u64,
+    pub lockout_seconds: u64,
+    pub base_delay_milliseconds: u64,
+    pub max_delay_milliseconds: u64,
+    pub per_user: ThrottleLimits,
+    pub per_ip: ThrottleLimits,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct ThrottleLimits {
+    // number of failures before each attempt gets delayed
+    pub delay_after: u64,
+    // number of failures before the subject is locked out
+    pub lockout_after: u64,
+}
+
+impl Default for LoginThrottleSettings {
+    fn default() -> Self {
+        Self {
+            window_seconds: 15 * 60,
+            lockout_seconds: 15 * 60,
+            base_delay_milliseconds: 500,
+            max_delay_milliseconds: 8000,
+            per_user: ThrottleLimits {
+                delay_after: 3,
+                lockout_after: 10,
+            },
+            per_ip: ThrottleLimits {
+                delay_after: 10,
+                lockout_after: 50,
+            },
+        }
+    }
+}
+
+// how many data requests an address and a client ip may make within the window,
+// each one mails the address
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct DataRequestThrottleSettings {
+    pub window_seconds: u64,
+    pub per_address: u64,
+    pub per_ip: u64,
+}
+
+impl Default for DataRequestThrottleSettings {
+    fn default() -> Self {
+        Self {
+            window_seconds: 60 * 60,
+            per_address: 3,
+            per_ip: 20,
+        }
+    }
+}
+
+// how many password reset links a username and a client ip may ask for within
+// the window, each one mails the account
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct PasswordResetThrottleSettings {
+    pub window_seconds: u64,
+    pub per_username: u64,
+    pub per_ip: u64,
+}
+
+impl Default for PasswordResetThrottleSettings {
+    fn default() -> Self {
+        Self {
+            window_seconds: 60 * 60,
+            per_username: 3,
+            per_ip: 20,
+        }
+    }
+}
+
+// argon2id cost parameters for new password hashes, stored hashes with other
+// parameters are upgraded the next time their owner logs in
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(default)]
+pub struct PasswordHashSettings {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashSettings {
+    fn default() -> Self {
+        // the parameters every existing hash was created with
+        Self {
+            memory_kib: 15000,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordHashSettings {
+    pub fn params(&self) -> Result<argon2::Params, argon2::Error> {
+        argon2::Params::new(self.memory_kib, self.iterations, self.parallelism, None)
+    }
+}
+
+// the session cookie attributes and how long an admin session stays valid,
+// the timeouts are enforced by `reject_anoynmous_user`
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct SessionSettings {
+    pub cookie_name: String,
+    pub cookie_path: String,
+    pub cookie_domain: Option<String>,
+    pub same_site: SameSiteSetting,
+    pub secure: bool,
+    pub http_only: bool,
+    // without a max age the cookie only lives as long as the browser session
+    pub cookie_max_age_seconds: Option<u64>,
+    // a session unused for this long has to log in again
+    pub idle_timeout_seconds: u64,
+    // a session older than this has to log in again however active it is
+    pub absolute_timeout_seconds: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SameSiteSetting {
+    Strict,
+    Lax,
+    None,
+}
+
+impl Default for SessionSettings {
+    fn default() -> Self {
+        Self {
+            cookie_name: "poem-session".to_owned(),
+            cookie_path: "/".to_owned(),
+            cookie_domain: None,
+            same_site: SameSiteSetting::Lax,
+            secure: true,
+            http_only: true,
+            cookie_max_age_seconds: None,
+            idle_timeout_seconds: 30 * 60,
+            absolute_timeout_seconds: 12 * 60 * 60,
+        }
+    }
+}
+
+impl SessionSettings {
+    pub fn cookie_config(&self) -> poem::session::CookieConfig {
+        let same_site = match self.same_site {
+            SameSiteSetting::Strict => SameSite::Strict,
+            SameSiteSetting::Lax => SameSite::Lax,
+            SameSiteSetting::None => SameSite::None,
+        };
+        let mut config = poem::session::CookieConfig::default()
+            .name(self.cookie_name.as_str())
+            .path(self.cookie_path.as_str())
+            .same_site(same_site)
+            .secure(self.secure)
+            .http_only(self.http_only)
+            .max_age(
+                self.cookie_max_age_seconds
+                    .map(std::time::Duration::from_secs),
+            );
+        if let Some(domain) = &self.cookie_domain {
+            config = config.domain(domain.as_str());
+        }
+        config
+    }
+
+    pub fn idle_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.idle_timeout_seconds as i64)
+    }
+
+    pub fn absolute_timeout(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.absolute_timeout_seconds as i64)
+    }
+}
+
+// spans are exported to an otlp collector (grpc), e.g. a local jaeger, only when an endpoint is set
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct TelemetrySettings {
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+    // the share of traces that are exported, from 0.0 (none) to 1.0 (all)
+    pub sampling_ratio: f64,
+}
+
+impl Default for TelemetrySettings {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: "zero2prod".to_owned(),
+            sampling_ratio: 1.0,
+        }
+    }
+}
+
+// `/metrics` in the prometheus text format, it is only served when enabled; with a `port`
+// it moves off the public port to one that can be kept inside the cluster, without one
+// it stays on the public port behind `token` (`APP__METRICS__TOKEN`)
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MetricsSettings {
+    pub enabled: bool,
+    pub port: Option<u16>,
+    pub token: Option<Secret<String>>,
+}
+
+// the basic auth credentials the email provider sends with its webhooks, e.g.
+// https://postmark:<password>@example.com/webhooks/email; without a password every
+// webhook is refused
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct WebhookSettings {
+    pub username: String,
+    pub password: Option<Secret<String>>,
+}
+
+impl Default for WebhookSettings {
+    fn default() -> Self {
+        Self {
+            username: "postmark".to_owned(),
+            password: None,
+        }
+    }
+}
+
+// `config/<environment>.yaml` is read on top of `config/default.yaml`
+pub fn environment() -> String {
+    std::env::var("APP__ENVIRONMENT").unwrap_or_else(|_| "test".to_owned())
+}
+
 pub fn get_configuration() -> Result<Configuration, config::ConfigError> {
-    let environment = std::env::var("APP__ENVIRONMENT").unwrap_or_else(|_| "test".to_owned());
-    info!("using environment: {}", environment);
+    let environment = environment();
     let conf_path = Path::new("config").join(environment);
     let default_conf_path = Path::new("config").join("default");
 
@@ -123,7 +392,7 @@ mod tests {
     use secrecy::ExposeSecret;
     use serial_test::serial;
 
-    use super::get_test_configuration;
+    use super::{get_test_configuration, MigrationMode};
 
     // just test Environment variables
     #[test]
@@ -167,6 +436,7 @@ app:
   base_url: "http://127.0.0.1"
   admin_username: foo
   admin_password: foobar123
+  hmac_secret: "long-and-very-secret-random-key"
 email_client:
   api_base_url: "https://api.postmarkapp.com"
   sender_email: "something@gmail.com"
@@ -196,6 +466,8 @@ redis_uri: "redis://localhost:6379/"
             conf.email_client.api_base_url,
             "https://api.postmarkapp.com"
         );
+        assert!(conf.telemetry.otlp_endpoint.is_none());
+        assert_eq!(conf.db.migrations, MigrationMode::Verify);
     }
 
     #[test]
@@ -225,8 +497,12 @@ db:
                 ("DB__PASSWORD", "aaa"),
                 ("DB__PORT", "111"),
                 ("DB__REQUIRE_SSL", "FALSE"),
+                ("DB__MIGRATIONS", "apply"),
                 ("APP__ADMIN_USERNAME", "foo"),
                 ("APP__ADMIN_PASSWORD", "bar"),
+                ("APP__HMAC_SECRET", "baz"),
+                ("TELEMETRY__OTLP_ENDPOINT", "http://localhost:4317"),
+                ("TELEMETRY__SAMPLING_RATIO", "0.25"),
             ],
         );
         let conf = get_test_configuration(&guard.path).expect("fail to get conf");
@@ -238,7 +514,15 @@ db:
         assert_eq!(conf.app.port, 1234);
         assert_eq!(conf.app.admin_username, "foo");
         assert_eq!(conf.app.admin_password, "bar");
+        assert_eq!(conf.app.hmac_secret.expose_secret(), "baz");
         assert!(!conf.db.require_ssl);
+        assert_eq!(conf.db.migrations, MigrationMode::Apply);
+        assert_eq!(
+            conf.telemetry.otlp_endpoint.as_deref(),
+            Some("http://localhost:4317")
+        );
+        assert_eq!(conf.telemetry.sampling_ratio, 0.25);
+        assert_eq!(conf.telemetry.service_name, "zero2prod");
     }
 
     struct TempTestConfig {
@@ -255,7 +539,7 @@ db:
                 .expect("fail to write config content");
             file.flush().expect("fail to flush files");
             for (key, value) in env_vars.into_iter() {
-                std::env::set_var(&format!("APP__{}", key), value)
+                std::env::set_var(format!("APP__{}", key), value)
             }
             TempTestConfig { path: path_str }
         }
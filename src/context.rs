@@ -1,12 +1,15 @@
 use std::sync::Arc;
 
+use redis::{aio::ConnectionManager, Client};
 use sea_orm::DatabaseConnection;
-use uuid::Uuid;
+use secrecy::ExposeSecret;
 
 use crate::{
-    auth::register_test_user,
-    configuration::Configuration,
+    auth::{DataRequestThrottle, DummyPasswordHash, LoginThrottle, PasswordResetThrottle},
+    configuration::{Configuration, PasswordHashSettings, SessionSettings, WebhookSettings},
     email_client::EmailClient,
+    issue_delivery_worker::WorkerHeartbeat,
+    routes::flash::FlashKey,
     startup::{get_database_connection, get_email_client},
 };
 
@@ -15,26 +18,47 @@ pub struct StateContext {
     pub db: DatabaseConnection,
     pub email_client: Arc<EmailClient>,
     pub base_url: String,
+    pub redis: ConnectionManager,
+    pub login_throttle: LoginThrottle,
+    pub data_request_throttle: DataRequestThrottle,
+    pub password_reset_throttle: PasswordResetThrottle,
+    pub password_hash: PasswordHashSettings,
+    pub dummy_password_hash: DummyPasswordHash,
+    pub session: SessionSettings,
+    pub webhooks: WebhookSettings,
+    pub worker_heartbeat: WorkerHeartbeat,
+    pub flash_key: FlashKey,
 }
 
 impl StateContext {
     pub async fn new(conf: Configuration) -> Result<Self, anyhow::Error> {
+        let flash_key = FlashKey::new(&conf.app.hmac_secret, conf.session.secure);
+        let dummy_password_hash = DummyPasswordHash::new(&conf.password_hash).await?;
         let db = get_database_connection(conf.db).await?;
-        let salt = Uuid::new_v4().to_string();
-        register_test_user(
-            &db,
-            &conf.app.admin_username,
-            &conf.app.admin_password,
-            &salt,
-        )
-        .await?;
         let email_client = Arc::new(get_email_client(conf.email_client)?);
         let base_url = conf.app.base_url;
+        let redis =
+            ConnectionManager::new(Client::open(conf.redis_uri.expose_secret().as_str())?).await?;
+        let login_throttle = LoginThrottle::new(redis.clone(), conf.login_throttle);
+        let data_request_throttle =
+            DataRequestThrottle::new(redis.clone(), conf.data_request_throttle);
+        let password_reset_throttle =
+            PasswordResetThrottle::new(redis.clone(), conf.password_reset_throttle);
         Ok(Self {
             db,
             // email_client: Arc::new(email_client),
             email_client,
             base_url,
+            redis,
+            login_throttle,
+            data_request_throttle,
+            password_reset_throttle,
+            password_hash: conf.password_hash,
+            dummy_password_hash,
+            session: conf.session,
+            webhooks: conf.webhooks,
+            worker_heartbeat: WorkerHeartbeat::default(),
+            flash_key,
         })
     }
 }
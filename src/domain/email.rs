@@ -27,9 +27,9 @@ impl Serialize for Email {
     }
 }
 
-impl ToString for Email {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl std::fmt::Display for Email {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
     }
 }
 
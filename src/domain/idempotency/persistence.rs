@@ -101,6 +101,7 @@ pub async fn save_response(
     Ok(resp)
 }
 
+#[allow(clippy::large_enum_variant)]
 pub enum NextAction {
     ContinueProcessing(Transaction<'static, Postgres>),
     ReturnSavedResponse(poem::Response),
@@ -4,4 +4,7 @@ pub use username::UserName;
 mod email;
 pub use email::Email;
 
+mod new_password;
+pub use new_password::NewPassword;
+
 pub mod idempotency;
@@ -0,0 +1,117 @@
+use once_cell::sync::Lazy;
+use secrecy::Secret;
+use std::collections::HashSet;
+
+const MIN_LENGTH: usize = 12;
+const MAX_LENGTH: usize = 128;
+const MIN_ENTROPY_BITS: f64 = 50.0;
+
+static COMMON_PASSWORDS: Lazy<HashSet<&'static str>> = Lazy::new(|| {
+    include_str!("common_passwords.txt")
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect()
+});
+
+// a password chosen by the user, either when changing or resetting it
+#[derive(Debug)]
+pub struct NewPassword(Secret<String>);
+
+impl NewPassword {
+    pub fn parse(password: String) -> Result<Self, String> {
+        if password.len() > MAX_LENGTH || password.len() < MIN_LENGTH {
+            return Err(format!(
+                "The password length must be between {MIN_LENGTH} to {MAX_LENGTH}"
+            ));
+        }
+        if COMMON_PASSWORDS.contains(password.to_lowercase().as_str()) {
+            return Err("The password is too common".to_owned());
+        }
+        if estimate_entropy(&password) < MIN_ENTROPY_BITS {
+            return Err(
+                "The password is too weak - make it longer or mix letters, digits and symbols"
+                    .to_owned(),
+            );
+        }
+        Ok(Self(Secret::new(password)))
+    }
+
+    pub fn inner(self) -> Secret<String> {
+        self.0
+    }
+}
+
+// a rough upper bound in bits: the alphabet size implied by the character classes
+// in use, where repeated characters only count for so much
+fn estimate_entropy(password: &str) -> f64 {
+    let (mut lower, mut upper, mut digit, mut symbol, mut other) =
+        (false, false, false, false, false);
+    for c in password.chars() {
+        match c {
+            'a'..='z' => lower = true,
+            'A'..='Z' => upper = true,
+            '0'..='9' => digit = true,
+            c if c.is_ascii() => symbol = true,
+            _ => other = true,
+        }
+    }
+    let pool = [
+        (lower, 26),
+        (upper, 26),
+        (digit, 10),
+        (symbol, 33),
+        (other, 100),
+    ]
+    .iter()
+    .filter(|(used, _)| *used)
+    .map(|(_, size)| size)
+    .sum::<u32>();
+    let length = password.chars().count();
+    let distinct = password.chars().collect::<HashSet<_>>().len();
+    let effective_length = length.min(distinct * 2);
+    effective_length as f64 * f64::from(pool).log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn too_short() {
+        assert!(NewPassword::parse("a".repeat(11)).is_err());
+    }
+
+    #[test]
+    fn too_long() {
+        assert!(NewPassword::parse("a".repeat(129)).is_err());
+    }
+
+    #[test]
+    fn boundaries_are_valid() {
+        assert!(NewPassword::parse("correcthorse".to_owned()).is_ok());
+        assert!(
+            NewPassword::parse("correct horse battery staple ".repeat(5)[..128].to_owned()).is_ok()
+        );
+    }
+
+    #[test]
+    fn common_passwords_are_rejected() {
+        assert!(NewPassword::parse("password1234".to_owned()).is_err());
+        assert!(NewPassword::parse("Password1234".to_owned()).is_err());
+        assert!(NewPassword::parse("qwertyuiopas".to_owned()).is_err());
+    }
+
+    #[test]
+    fn low_entropy_passwords_are_rejected() {
+        assert!(NewPassword::parse("a".repeat(20)).is_err());
+        assert!(NewPassword::parse("abababababababab".to_owned()).is_err());
+        assert!(NewPassword::parse("864209753186".to_owned()).is_err());
+    }
+
+    #[test]
+    fn random_passwords_are_accepted() {
+        assert!(NewPassword::parse(uuid::Uuid::new_v4().to_string()).is_ok());
+        assert!(NewPassword::parse("Tr0ub4dor&3x!".to_owned()).is_ok());
+    }
+}
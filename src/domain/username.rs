@@ -35,9 +35,9 @@ impl AsRef<str> for UserName {
     }
 }
 
-impl ToString for UserName {
-    fn to_string(&self) -> String {
-        self.0.clone()
+impl std::fmt::Display for UserName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
     }
 }
 
@@ -1,8 +1,10 @@
+use std::time::Instant;
+
 use reqwest::Client;
 use secrecy::{ExposeSecret, Secret};
 use serde::Serialize;
 
-use crate::domain::Email;
+use crate::{domain::Email, metrics::record_email_request};
 
 #[derive(Clone)]
 pub struct EmailClient {
@@ -10,6 +12,8 @@ pub struct EmailClient {
     api_base_url: String,
     sender: Email,
     authorization_token: Secret<String>,
+    // sent along so that the provider side logs can be matched with ours
+    request_id: Option<String>,
 }
 
 impl EmailClient {
@@ -24,6 +28,15 @@ impl EmailClient {
             api_base_url,
             sender,
             authorization_token: Secret::new(authorization_token),
+            request_id: None,
+        }
+    }
+
+    // a copy of the client whose requests carry the `X-Request-Id` of the work they belong to
+    pub fn with_request_id(&self, request_id: &str) -> Self {
+        Self {
+            request_id: Some(request_id.to_owned()),
+            ..self.clone()
         }
     }
 
@@ -44,17 +57,21 @@ impl EmailClient {
             html_body,
             text_body,
         };
-        let resp = self
-            .http_client
-            .post(&url)
-            .header(
-                "X-Postmark-Server-Token",
-                self.authorization_token.expose_secret(),
-            )
-            .json(&request_body)
-            .send()
-            .await?
-            .error_for_status()?;
+        let mut request = self.http_client.post(&url).header(
+            "X-Postmark-Server-Token",
+            self.authorization_token.expose_secret(),
+        );
+        if let Some(request_id) = &self.request_id {
+            request = request.header("X-Request-Id", request_id);
+        }
+        let started = Instant::now();
+        let resp = request.json(&request_body).send().await;
+        let status = match &resp {
+            Ok(resp) => resp.status().as_u16().to_string(),
+            Err(_) => "error".to_owned(),
+        };
+        record_email_request(status, started.elapsed());
+        let resp = resp?.error_for_status()?;
         Ok(resp.status())
     }
 }
@@ -131,6 +148,25 @@ mod tests {
         assert!(outcome.is_ok());
     }
 
+    #[ignore]
+    #[tokio::test]
+    async fn send_email_forwards_the_request_id() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_request_id("publish-42");
+
+        Mock::given(header("X-Request-Id", "publish-42"))
+            .and(path("/email"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+        assert!(outcome.is_ok());
+    }
+
     #[ignore]
     #[tokio::test]
     async fn send_email_fails_if_the_server_returns_500() {
@@ -2,7 +2,17 @@
 
 pub mod prelude;
 
+pub mod api_tokens;
+pub mod audit_events;
+pub mod data_request_tokens;
+pub mod erased_subscribers;
+pub mod idempotency;
+pub mod issue_delivery_queue;
 pub mod newsletter_issues;
+pub mod password_reset_tokens;
+pub mod recovery_codes;
+pub mod sea_orm_active_enums;
 pub mod subscription_tokens;
 pub mod subscriptions;
+pub mod suppressions;
 pub mod user;
@@ -10,10 +10,19 @@ pub struct Model {
     pub title: String,
     pub text_content: String,
     pub html_content: String,
-    pub published_at: String,
+    pub published_at: DateTimeWithTimeZone,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::issue_delivery_queue::Entity")]
+    IssueDeliveryQueue,
+}
+
+impl Related<super::issue_delivery_queue::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::IssueDeliveryQueue.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
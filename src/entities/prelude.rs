@@ -1,7 +1,12 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.11.0
 
 pub use super::{
+    api_tokens::Entity as ApiTokens, audit_events::Entity as AuditEvents,
+    data_request_tokens::Entity as DataRequestTokens,
+    erased_subscribers::Entity as ErasedSubscribers, idempotency::Entity as Idempotency,
+    issue_delivery_queue::Entity as IssueDeliveryQueue,
     newsletter_issues::Entity as NewsletterIssues,
+    password_reset_tokens::Entity as PasswordResetTokens, recovery_codes::Entity as RecoveryCodes,
     subscription_tokens::Entity as SubscriptionTokens, subscriptions::Entity as Subscriptions,
-    user::Entity as User,
+    suppressions::Entity as Suppressions, user::Entity as User,
 };
@@ -0,0 +1,24 @@
+//! Maintained by hand, sea-orm-codegen would drop the `sqlx::Type` derive.
+
+use sea_orm::entity::prelude::*;
+
+// also a `sqlx::Type`, so that the raw queries bind it instead of spelling out a literal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumIter, DeriveActiveEnum, sqlx::Type)]
+#[sea_orm(
+    rs_type = "String",
+    db_type = "Enum",
+    enum_name = "subscription_status"
+)]
+#[sqlx(type_name = "subscription_status", rename_all = "lowercase")]
+pub enum SubscriptionStatus {
+    #[sea_orm(string_value = "pending")]
+    Pending,
+    #[sea_orm(string_value = "confirmed")]
+    Confirmed,
+    #[sea_orm(string_value = "unsubscribed")]
+    Unsubscribed,
+    #[sea_orm(string_value = "bounced")]
+    Bounced,
+    #[sea_orm(string_value = "complained")]
+    Complained,
+}
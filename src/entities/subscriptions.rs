@@ -2,6 +2,8 @@
 
 use sea_orm::entity::prelude::*;
 
+use super::sea_orm_active_enums::SubscriptionStatus;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "subscriptions")]
 pub struct Model {
@@ -11,15 +13,23 @@ pub struct Model {
     pub email: String,
     pub name: String,
     pub subscribed_at: DateTimeWithTimeZone,
-    pub status: String,
+    pub status: SubscriptionStatus,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::data_request_tokens::Entity")]
+    DataRequestTokens,
     #[sea_orm(has_many = "super::subscription_tokens::Entity")]
     SubscriptionTokens,
 }
 
+impl Related<super::data_request_tokens::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::DataRequestTokens.def()
+    }
+}
+
 impl Related<super::subscription_tokens::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::SubscriptionTokens.def()
@@ -1,4 +1,7 @@
-use std::time::Duration;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use anyhow::anyhow;
 use sea_orm::{DatabaseConnection, EntityTrait};
@@ -12,23 +15,45 @@ use crate::{
     email_client::EmailClient,
     entities::{newsletter_issues, prelude::NewsletterIssues},
     get_database_connection, get_email_client,
+    metrics::record_delivery,
+    suppressions::find_suppression,
 };
 
 type IssueDeliveryResult<T> = std::result::Result<T, anyhow::Error>;
 
+// when the worker last went around its loop, so that readiness can tell a stuck or dead worker
+#[derive(Clone, Default)]
+pub struct WorkerHeartbeat(Arc<Mutex<Option<Instant>>>);
+
+impl WorkerHeartbeat {
+    pub fn beat(&self) {
+        *self.0.lock().unwrap() = Some(Instant::now());
+    }
+
+    // `None` until the worker has started
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.0.lock().unwrap().map(|last_beat| last_beat.elapsed())
+    }
+}
+
 #[tracing::instrument(skip_all)]
-pub async fn run_worker_until_stop(config: Configuration) -> IssueDeliveryResult<()> {
+pub async fn run_worker_until_stop(
+    config: Configuration,
+    heartbeat: WorkerHeartbeat,
+) -> IssueDeliveryResult<()> {
     let db = get_database_connection(config.db).await?;
     let email_client = get_email_client(config.email_client)?;
-    worker_loop(&db, &email_client).await
+    worker_loop(&db, &email_client, &heartbeat).await
 }
 
 #[tracing::instrument(skip_all)]
 async fn worker_loop(
     db: &DatabaseConnection,
     email_client: &EmailClient,
+    heartbeat: &WorkerHeartbeat,
 ) -> IssueDeliveryResult<()> {
     loop {
+        heartbeat.beat();
         match try_execute_task(db, email_client).await {
             Ok(ExecutionOutcome::EmptyQueue) => {
                 tokio::time::sleep(Duration::from_secs(10)).await;
@@ -51,7 +76,8 @@ pub enum ExecutionOutcome {
     skip_all,
     fields(
         newsletter_issue_id=tracing::field::Empty,
-        subscriber_email=tracing::field::Empty
+        subscriber_email=tracing::field::Empty,
+        request_id=tracing::field::Empty
     ),
     err
 )]
@@ -59,12 +85,25 @@ pub async fn try_execute_task(
     db: &DatabaseConnection,
     email_client: &EmailClient,
 ) -> IssueDeliveryResult<ExecutionOutcome> {
-    let Some((tx, issue_id, subscriber_email)) = dequeue_task(db).await? else {
+    let Some((tx, task)) = dequeue_task(db).await? else {
         return Ok(ExecutionOutcome::EmptyQueue);
     };
+    let DeliveryTask {
+        issue_id,
+        subscriber_email,
+        request_id,
+    } = task;
     Span::current()
-        .record("newsletter_issue_id", &display(issue_id))
-        .record("subscriber_email", &display(&subscriber_email));
+        .record("newsletter_issue_id", display(issue_id))
+        .record("subscriber_email", display(&subscriber_email));
+    // links the delivery back to the request that published the issue
+    let email_client = match &request_id {
+        Some(request_id) => {
+            Span::current().record("request_id", display(request_id));
+            email_client.with_request_id(request_id)
+        }
+        None => email_client.clone(),
+    };
     // send the email
     // we need to get the email content from the issue_id
     let Some(issue) = get_issue(db, issue_id).await? else {
@@ -79,16 +118,24 @@ pub async fn try_execute_task(
         }
     };
 
+    // the address bounced, complained or was suppressed after the issue was enqueued
+    if find_suppression(db, &subscriber_email).await?.is_some() {
+        tracing::info!("skipping a suppressed address");
+        delete_task(tx, issue_id, &subscriber_email).await?;
+        return Ok(ExecutionOutcome::TaskCompleted);
+    }
+
     // TODO: add retry
-    if let Err(e) = email_client
+    let outcome = email_client
         .send_email(
             &email,
             &issue.title,
             &issue.html_content,
             &issue.text_content,
         )
-        .await
-    {
+        .await;
+    record_delivery(outcome.is_ok());
+    if let Err(e) = outcome {
         tracing::error!(error.cause_chain=?e, error.message=%e, "failed to deliver issue to a confirmed subscriber");
     }
 
@@ -99,16 +146,23 @@ pub async fn try_execute_task(
 type PgTransaction = Transaction<'static, Postgres>;
 // type PgTransaction = sea_orm::DatabaseTransaction;
 
+struct DeliveryTask {
+    issue_id: Uuid,
+    subscriber_email: String,
+    // not set for tasks enqueued before request ids were recorded
+    request_id: Option<String>,
+}
+
 #[tracing::instrument(skip_all)]
 async fn dequeue_task(
     db: &DatabaseConnection,
-) -> IssueDeliveryResult<Option<(PgTransaction, Uuid, String)>> {
+) -> IssueDeliveryResult<Option<(PgTransaction, DeliveryTask)>> {
     // let mut tx = db.begin().await.context("fail to get a transaction")?;
     let pool = db.get_postgres_connection_pool();
     let mut tx = pool.begin().await?;
     let r = sqlx::query!(
         r#"
-        select newsletter_issue_id, subscriber_email
+        select newsletter_issue_id, subscriber_email, request_id
         from issue_delivery_queue
         for update
         skip locked
@@ -118,7 +172,12 @@ async fn dequeue_task(
     .fetch_optional(&mut tx)
     .await?;
     if let Some(r) = r {
-        Ok(Some((tx, r.newsletter_issue_id, r.subscriber_email)))
+        let task = DeliveryTask {
+            issue_id: r.newsletter_issue_id,
+            subscriber_email: r.subscriber_email,
+            request_id: r.request_id,
+        };
+        Ok(Some((tx, task)))
     } else {
         Ok(None)
     }
@@ -3,7 +3,7 @@ use std::fmt::{Debug, Display};
 use anyhow::Result;
 use poem::{
     listener::TcpListener,
-    session::{CookieConfig, RedisStorage, ServerSession},
+    session::{RedisStorage, ServerSession},
     EndpointExt, Server,
 };
 use redis::{aio::ConnectionManager, Client};
@@ -11,26 +11,53 @@ use secrecy::ExposeSecret;
 use tokio::task::JoinError;
 use tracing::info;
 use zero2prod_api::{
-    configuration::get_configuration, context::StateContext,
-    issue_delivery_worker::run_worker_until_stop, routes::default_route, setup_logger,
+    auth::{bootstrap_admin, BootstrapOutcome},
+    configuration::{environment, get_configuration, Configuration},
+    context::StateContext,
+    get_database_connection,
+    issue_delivery_worker::run_worker_until_stop,
+    prepare_schema,
+    routes::{default_route, metrics::metrics_route},
+    setup_logger, shutdown_telemetry,
 };
 
 #[tokio::main]
 async fn main() -> Result<()> {
-    let log_level = std::env::var("LOG_LEVEL").unwrap_or_else(|_| "DEBUG".into());
-    setup_logger(&log_level);
     let conf = get_configuration().expect("fail to read configuration");
+    let log_level = std::env::var("LOG_LEVEL").unwrap_or_else(|_| "DEBUG".into());
+    setup_logger(&log_level, &conf.telemetry);
+    // the configuration is read before there is a logger to report it
+    info!("using environment: {}", environment());
+
+    match std::env::args().nth(1).as_deref() {
+        None => {}
+        Some("create-admin") => return create_admin(conf).await,
+        Some(command) => anyhow::bail!("unknown command: {}", command),
+    }
 
     let app_port = conf.app.port;
+    let cookie_config = conf.session.cookie_config();
     let redis_uri = conf.redis_uri.expose_secret().clone();
     let context = StateContext::new(conf.clone()).await?;
+    prepare_schema(&context.db, conf.db.migrations).await?;
 
-    let worker = tokio::spawn(run_worker_until_stop(conf.clone()));
+    let worker = tokio::spawn(run_worker_until_stop(
+        conf.clone(),
+        context.worker_heartbeat.clone(),
+    ));
+    let metrics_server = match conf.metrics.port.filter(|_| conf.metrics.enabled) {
+        Some(port) => {
+            let addr = format!("0.0.0.0:{}", port);
+            info!(addr, "metrics listening on");
+            tokio::spawn(Server::new(TcpListener::bind(addr)).run(metrics_route(context.clone())))
+        }
+        None => tokio::spawn(std::future::pending()),
+    };
     // set routing
     let route = default_route(conf, context.clone()).await;
     let client = Client::open(redis_uri)?;
     let app = route.with(ServerSession::new(
-        CookieConfig::default(),
+        cookie_config,
         RedisStorage::new(ConnectionManager::new(client).await?),
     ));
 
@@ -41,6 +68,26 @@ async fn main() -> Result<()> {
     tokio::select! {
         o = server => {report_exit("api", o)},
         o = worker => {report_exit("worker", o)},
+        o = metrics_server => {report_exit("metrics", o)},
+    }
+    shutdown_telemetry().await;
+    Ok(())
+}
+
+async fn create_admin(conf: Configuration) -> Result<()> {
+    let migrations = conf.db.migrations;
+    let db = get_database_connection(conf.db).await?;
+    prepare_schema(&db, migrations).await?;
+    match bootstrap_admin(
+        &db,
+        &conf.app.admin_username,
+        &conf.app.admin_password,
+        &conf.password_hash,
+    )
+    .await?
+    {
+        BootstrapOutcome::Created => info!(conf.app.admin_username, "admin created"),
+        BootstrapOutcome::AlreadyExists => info!("an owner account already exists, nothing to do"),
     }
     Ok(())
 }
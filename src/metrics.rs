@@ -0,0 +1,96 @@
+use std::time::Duration;
+
+use metrics::{gauge, histogram, increment_counter};
+use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use once_cell::sync::Lazy;
+use sea_orm::DatabaseConnection;
+
+// seconds, from a redirect served out of memory to an email api call close to its timeout
+const LATENCY_BUCKETS: &[f64] = &[
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// the recorder is global, so every app of the process (e.g. all the tests) shares it
+static PROMETHEUS: Lazy<PrometheusHandle> = Lazy::new(|| {
+    PrometheusBuilder::new()
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_owned()), LATENCY_BUCKETS)
+        .and_then(PrometheusBuilder::install_recorder)
+        .expect("fail to install the prometheus recorder")
+});
+
+// nothing is recorded until this has been called, the `record_*` functions are no-ops before
+pub fn install_recorder() -> &'static PrometheusHandle {
+    &PROMETHEUS
+}
+
+pub fn record_http_request(method: &str, route: String, status: u16, elapsed: Duration) {
+    increment_counter!(
+        "http_requests_total",
+        "method" => method.to_owned(),
+        "route" => route.clone(),
+        "status" => status.to_string()
+    );
+    histogram!(
+        "http_request_duration_seconds",
+        elapsed,
+        "method" => method.to_owned(),
+        "route" => route
+    );
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LoginStep {
+    Password,
+    SecondFactor,
+}
+
+pub fn record_login(step: LoginStep, success: bool) {
+    let step = match step {
+        LoginStep::Password => "password",
+        LoginStep::SecondFactor => "second_factor",
+    };
+    increment_counter!("login_attempts_total", "step" => step, "outcome" => outcome(success));
+}
+
+pub fn record_subscription_created() {
+    increment_counter!("subscriptions_created_total");
+}
+
+pub fn record_subscription_confirmed() {
+    increment_counter!("subscriptions_confirmed_total");
+}
+
+pub fn record_delivery(success: bool) {
+    increment_counter!("issue_deliveries_total", "outcome" => outcome(success));
+}
+
+// `kind` is what the email provider reported, e.g. hard_bounce or delivery
+pub fn record_email_event(kind: &'static str) {
+    increment_counter!("email_events_total", "kind" => kind);
+}
+
+// `status` is the http status of the email api, or "error" when no response came back
+pub fn record_email_request(status: String, elapsed: Duration) {
+    histogram!("email_client_request_duration_seconds", elapsed, "status" => status);
+}
+
+// gauges are sampled when prometheus scrapes rather than kept up to date on every change
+pub fn record_queue_depth(depth: i64) {
+    gauge!("issue_delivery_queue_depth", depth as f64);
+}
+
+pub fn record_db_pool(db: &DatabaseConnection) {
+    let pool = db.get_postgres_connection_pool();
+    let idle = pool.num_idle() as f64;
+    let open = pool.size() as f64;
+    gauge!("db_pool_connections", idle, "state" => "idle");
+    gauge!("db_pool_connections", (open - idle).max(0.0), "state" => "in_use");
+}
+
+fn outcome(success: bool) -> &'static str {
+    if success {
+        "success"
+    } else {
+        "failure"
+    }
+}
@@ -0,0 +1,354 @@
+use anyhow::Context;
+use poem::{
+    handler,
+    http::{header, StatusCode},
+    web::{Data, Json, RemoteAddr},
+    Body, IntoResponse, Response,
+};
+use sea_orm::{prelude::DateTimeWithTimeZone, ActiveEnum};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{
+    dashboard::get_username,
+    newsletters::{publish_issue, NewsletterForm},
+    subscribers::{
+        resend_confirmation_email, run_data_export, run_erasure, run_import, suppressed_subscriber,
+        unknown_subscriber,
+    },
+};
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::ApiPrincipal,
+    context::StateContext,
+    entities::{sea_orm_active_enums::SubscriptionStatus, subscriptions},
+    routes::{
+        error::{AppError, ErrorCode},
+        request_id::RequestId,
+    },
+    subscribers::{
+        delete_subscriber, export_subscribers, find_subscriber, list_subscribers,
+        set_subscriber_status, ImportReport, StatusChange, SubscriberData, SubscriberFilter,
+        DEFAULT_PAGE_SIZE,
+    },
+    utils::client_ip,
+};
+
+type ApiResult<T> = std::result::Result<T, AppError>;
+
+// json variants of the admin pages for bearer token clients, e.g. ci publishing release notes
+
+#[derive(Debug, Serialize)]
+pub struct Me {
+    user_id: Uuid,
+    username: String,
+    token_id: Uuid,
+    scopes: Vec<String>,
+}
+
+#[handler]
+pub async fn get_me(
+    context: Data<&StateContext>,
+    principal: Data<&ApiPrincipal>,
+) -> ApiResult<Json<Me>> {
+    let username = get_username(principal.user_id, &context.db)
+        .await
+        .context("fail to get username from user_id")
+        .map_err(AppError::internal)?
+        .ok_or_else(|| AppError::unauthorized("no username found for id"))?;
+    Ok(Json(Me {
+        user_id: principal.user_id,
+        username,
+        token_id: principal.token_id,
+        scopes: principal
+            .scopes
+            .iter()
+            .map(|scope| scope.to_string())
+            .collect(),
+    }))
+}
+
+#[derive(Debug, Serialize)]
+struct PublishedIssue {
+    newsletter_issue_id: Uuid,
+}
+
+#[handler]
+pub async fn publish_newsletter_json(
+    context: Data<&StateContext>,
+    newsletter: Json<NewsletterForm>,
+    user_id: Data<&Uuid>,
+    request_id: Data<&RequestId>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<poem::Response> {
+    let ip = client_ip(remote_addr);
+    publish_issue(
+        &context,
+        user_id.0,
+        &ip,
+        &request_id,
+        newsletter.0,
+        |newsletter_issue_id| {
+            // the deliveries happen in the background worker
+            Json(PublishedIssue {
+                newsletter_issue_id,
+            })
+            .with_status(StatusCode::ACCEPTED)
+            .into_response()
+        },
+    )
+    .await
+}
+
+#[derive(Debug, Serialize)]
+pub struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTimeWithTimeZone,
+}
+
+impl From<subscriptions::Model> for Subscriber {
+    fn from(subscriber: subscriptions::Model) -> Self {
+        Subscriber {
+            id: subscriber.id,
+            email: subscriber.email,
+            name: subscriber.name,
+            status: subscriber.status.to_value(),
+            subscribed_at: subscriber.subscribed_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubscriberListing {
+    subscribers: Vec<Subscriber>,
+    page: u64,
+    per_page: u64,
+    total: u64,
+}
+
+// `since` and `until` are days, e.g. 2023-04-01, both included
+#[derive(Debug, Deserialize)]
+pub struct SubscriberQuery {
+    search: Option<String>,
+    status: Option<String>,
+    since: Option<String>,
+    until: Option<String>,
+    page: Option<u64>,
+    per_page: Option<u64>,
+}
+
+#[handler]
+pub async fn list_subscribers_json(
+    context: Data<&StateContext>,
+    query: poem::web::Query<SubscriberQuery>,
+) -> ApiResult<Json<SubscriberListing>> {
+    let query = query.0;
+    let filter = SubscriberFilter::parse(
+        query.search.filter(|search| !search.is_empty()),
+        query.status.as_deref(),
+        query.since.as_deref(),
+        query.until.as_deref(),
+    )
+    .map_err(|e| AppError::new(ErrorCode::ValidationFailed, e))?;
+    let listing = list_subscribers(
+        &context.db,
+        &filter,
+        query.page.unwrap_or(1),
+        query.per_page.unwrap_or(DEFAULT_PAGE_SIZE),
+    )
+    .await
+    .map_err(AppError::internal)?;
+    Ok(Json(SubscriberListing {
+        subscribers: listing.subscribers.into_iter().map(Into::into).collect(),
+        page: listing.page,
+        per_page: listing.per_page,
+        total: listing.total,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SubscriberAction {
+    id: Uuid,
+}
+
+#[handler]
+pub async fn confirm_subscriber_json(
+    context: Data<&StateContext>,
+    action: Json<SubscriberAction>,
+    principal: Data<&ApiPrincipal>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<Json<Subscriber>> {
+    change_status(
+        &context,
+        action.id,
+        SubscriptionStatus::Confirmed,
+        AuditAction::SubscriberConfirmed,
+        principal.user_id,
+        &client_ip(remote_addr),
+    )
+    .await
+}
+
+#[handler]
+pub async fn unsubscribe_subscriber_json(
+    context: Data<&StateContext>,
+    action: Json<SubscriberAction>,
+    principal: Data<&ApiPrincipal>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<Json<Subscriber>> {
+    change_status(
+        &context,
+        action.id,
+        SubscriptionStatus::Unsubscribed,
+        AuditAction::SubscriberUnsubscribed,
+        principal.user_id,
+        &client_ip(remote_addr),
+    )
+    .await
+}
+
+async fn change_status(
+    context: &StateContext,
+    id: Uuid,
+    status: SubscriptionStatus,
+    action: AuditAction,
+    actor_id: Uuid,
+    ip: &str,
+) -> ApiResult<Json<Subscriber>> {
+    let subscriber = match set_subscriber_status(&context.db, id, status)
+        .await
+        .map_err(AppError::internal)?
+    {
+        StatusChange::Changed(subscriber) => subscriber,
+        StatusChange::Unknown => return Err(unknown_subscriber()),
+        StatusChange::Suppressed => return Err(suppressed_subscriber()),
+    };
+    record_audit_event(&context.db, actor_id, action, Some(&id.to_string()), ip).await;
+    Ok(Json(subscriber.into()))
+}
+
+#[handler]
+pub async fn delete_subscriber_json(
+    context: Data<&StateContext>,
+    action: Json<SubscriberAction>,
+    principal: Data<&ApiPrincipal>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<StatusCode> {
+    let deleted = delete_subscriber(&context.db, action.id)
+        .await
+        .map_err(AppError::internal)?;
+    if !deleted {
+        return Err(unknown_subscriber());
+    }
+    record_audit_event(
+        &context.db,
+        principal.user_id,
+        AuditAction::SubscriberDeleted,
+        Some(&action.id.to_string()),
+        &client_ip(remote_addr),
+    )
+    .await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[handler]
+pub async fn resend_confirmation_json(
+    context: Data<&StateContext>,
+    action: Json<SubscriberAction>,
+    request_id: Data<&RequestId>,
+) -> ApiResult<StatusCode> {
+    let subscriber = find_subscriber(&context.db, action.id)
+        .await
+        .map_err(AppError::internal)?
+        .ok_or_else(unknown_subscriber)?;
+    resend_confirmation_email(&context, &subscriber, &request_id).await?;
+    Ok(StatusCode::ACCEPTED)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportQuery {
+    #[serde(default)]
+    send_confirmation: bool,
+}
+
+// the body is the csv file itself
+#[handler]
+pub async fn import_subscribers_json(
+    context: Data<&StateContext>,
+    query: poem::web::Query<ImportQuery>,
+    csv: String,
+    principal: Data<&ApiPrincipal>,
+    request_id: Data<&RequestId>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<Json<ImportReport>> {
+    let report = run_import(
+        &context,
+        &csv,
+        query.send_confirmation,
+        principal.user_id,
+        &client_ip(remote_addr),
+        &request_id,
+    )
+    .await?;
+    Ok(Json(report))
+}
+
+// also served to the admin pages, the rows are streamed as they are read
+#[handler]
+pub async fn export_subscribers_csv(context: Data<&StateContext>) -> Response {
+    let filename = format!("subscribers-{}.csv", chrono::Utc::now().format("%Y-%m-%d"));
+    Response::builder()
+        .content_type("text/csv; charset=utf-8")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(Body::from_bytes_stream(export_subscribers(
+            context.db.clone(),
+        )))
+}
+
+// data subject requests name an address, not a subscriber id
+#[derive(Debug, Deserialize)]
+pub struct DataSubject {
+    email: String,
+}
+
+#[handler]
+pub async fn export_subscriber_data_json(
+    context: Data<&StateContext>,
+    subject: Json<DataSubject>,
+    principal: Data<&ApiPrincipal>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<Json<SubscriberData>> {
+    let data = run_data_export(
+        &context,
+        &subject.email,
+        principal.user_id,
+        &client_ip(remote_addr),
+    )
+    .await?
+    .ok_or_else(|| AppError::new(ErrorCode::NotFound, "nothing is stored about the address"))?;
+    Ok(Json(data))
+}
+
+// succeeds for an unknown address too, its tombstone still keeps it from being imported
+#[handler]
+pub async fn erase_subscriber_json(
+    context: Data<&StateContext>,
+    subject: Json<DataSubject>,
+    principal: Data<&ApiPrincipal>,
+    remote_addr: &RemoteAddr,
+) -> ApiResult<StatusCode> {
+    run_erasure(
+        &context,
+        &subject.email,
+        principal.user_id,
+        &client_ip(remote_addr),
+    )
+    .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
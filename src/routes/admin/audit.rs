@@ -0,0 +1,131 @@
+use askama::Template;
+use chrono::{NaiveDateTime, TimeZone, Utc};
+use poem::session::Session;
+use poem_openapi::{param::Query, payload::Html, OpenApi, Tags};
+use sea_orm::prelude::DateTimeWithTimeZone;
+
+use crate::{
+    audit::{list_audit_events, AuditAction, AuditFilter},
+    auth::csrf_token,
+    context::StateContext,
+    entities::audit_events,
+    routes::{add_session_uid_check, error::AppError, flash::IncomingFlash, templates::render},
+};
+
+type AuditResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    Audit,
+}
+
+#[OpenApi(prefix_path = "/audit", tag = "MyTags::Audit")]
+impl Api {
+    // the filter form submits every field, the empty ones match everything
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn list_events(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        actor: Query<Option<String>>,
+        action: Query<Option<String>>,
+        since: Query<Option<String>>,
+        until: Query<Option<String>>,
+    ) -> AuditResult<Html<String>> {
+        let actor = non_empty(actor.0);
+        let action = non_empty(action.0);
+        let since = non_empty(since.0);
+        let until = non_empty(until.0);
+
+        let (events, error) = match parse_filter(&actor, &action, &since, &until) {
+            Ok(filter) => {
+                let events = list_audit_events(&self.context.db, &filter)
+                    .await
+                    .map_err(AppError::internal)?;
+                (events, None)
+            }
+            Err(e) => (Vec::new(), Some(e)),
+        };
+        let actions = AuditAction::ALL
+            .into_iter()
+            .map(|option| (option, action.as_deref() == Some(option.as_str())))
+            .collect();
+        render(&AuditPage {
+            flash,
+            csrf: csrf_token(session),
+            error,
+            actor: actor.unwrap_or_default(),
+            actions,
+            since: since.unwrap_or_default(),
+            until: until.unwrap_or_default(),
+            events,
+        })
+        .map(Html)
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/audit.html")]
+struct AuditPage {
+    flash: IncomingFlash,
+    csrf: String,
+    error: Option<String>,
+    actor: String,
+    // every action and whether it is the selected one
+    actions: Vec<(AuditAction, bool)>,
+    since: String,
+    until: String,
+    events: Vec<audit_events::Model>,
+}
+
+fn parse_filter(
+    actor: &Option<String>,
+    action: &Option<String>,
+    since: &Option<String>,
+    until: &Option<String>,
+) -> Result<AuditFilter, String> {
+    Ok(AuditFilter {
+        actor: actor.clone(),
+        action: action.as_deref().map(AuditAction::parse).transpose()?,
+        since: since.as_deref().map(parse_time).transpose()?,
+        until: until.as_deref().map(parse_time).transpose()?,
+    })
+}
+
+// what a datetime-local input submits, with or without seconds
+fn parse_time(input: &str) -> Result<DateTimeWithTimeZone, String> {
+    NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M")
+        .or_else(|_| NaiveDateTime::parse_from_str(input, "%Y-%m-%dT%H:%M:%S"))
+        .map(|time| Utc.from_utc_datetime(&time).into())
+        .map_err(|_| format!("invalid time: {input}"))
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn datetime_local_values_are_parsed_as_utc() {
+        let time = parse_time("2023-03-26T09:30").unwrap();
+        assert_eq!(time.to_rfc3339(), "2023-03-26T09:30:00+00:00");
+        let time = parse_time("2023-03-26T09:30:15").unwrap();
+        assert_eq!(time.to_rfc3339(), "2023-03-26T09:30:15+00:00");
+        assert!(parse_time("yesterday").is_err());
+    }
+}
@@ -1,20 +1,22 @@
-use poem::web::Data;
+use askama::Template;
+use poem::{session::Session, web::Data};
 use poem_openapi::{payload::Html, OpenApi};
 use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter};
 use uuid::Uuid;
 
 use super::super::add_session_uid_check;
 use crate::{
+    auth::csrf_token,
     context::StateContext,
     entities::user::{self, Entity as Users},
-    routes::error::BasicError,
+    routes::{error::AppError, flash::IncomingFlash, templates::render},
 };
 
 pub struct Api {
     context: StateContext,
 }
 
-type DashboardResult<T> = std::result::Result<T, BasicError>;
+type DashboardResult<T> = std::result::Result<T, AppError>;
 
 #[OpenApi]
 impl Api {
@@ -23,43 +25,40 @@ impl Api {
         method = "get",
         transform = "add_session_uid_check"
     )]
-    pub async fn admin_dashboard(&self, user_id: Data<&Uuid>) -> DashboardResult<Html<String>> {
+    pub async fn admin_dashboard(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        user_id: Data<&Uuid>,
+    ) -> DashboardResult<Html<String>> {
         let user_id = user_id.0;
         match get_username(*user_id, &self.context.db).await {
-            Ok(Some(username)) => Ok(Html(format!(
-                r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Admin dashboard</title>
-</head>
-<body>
-    <p>Welcome {username}!</p>
-    <p>Available actions:</p>
-    <ol>
-        <li><a href="/admin/password">Change password</a></li>
-        <li><a href="/admin/newsletters">Send a newsletter</li>
-        <li>
-          <form name="logoutForm" action="/logout" method="post">
-            <input type="submit" value="Logout">
-          </form>
-        </li>
-    </ol>
-</body>
-</html>"#
-            ))),
+            Ok(Some(username)) => render(&DashboardPage {
+                flash,
+                csrf: csrf_token(session),
+                username,
+            })
+            .map(Html),
             Ok(None) => {
                 let msg = format!("username not found for user_id: {}", user_id);
-                Err(BasicError::auth_error(msg))
+                Err(AppError::unauthorized(msg))
             }
             Err(e) => {
                 let msg = format!("fail to get username: {e}");
-                Err(BasicError::interval_error(msg))
+                Err(AppError::internal(msg))
             }
         }
     }
 }
 
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardPage {
+    flash: IncomingFlash,
+    csrf: String,
+    username: String,
+}
+
 impl Api {
     pub fn new(context: StateContext) -> Self {
         Self { context }
@@ -0,0 +1,185 @@
+use anyhow::Context;
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use sea_orm::{ActiveModelTrait, ActiveValue, EntityTrait};
+use secrecy::Secret;
+use serde::Deserialize;
+use tracing::Instrument;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::{csrf_token, validate_credentials, AuthError, Credentials},
+    context::StateContext,
+    domain::Email,
+    entities::user::{self, Entity as Users},
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        request_id::RequestId,
+        templates::render,
+    },
+    utils::client_ip,
+};
+
+type EmailResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    AccountEmail,
+}
+
+// the address password reset links are sent to
+#[OpenApi(prefix_path = "/email", tag = "MyTags::AccountEmail")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn email_form(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        user_id: Data<&Uuid>,
+    ) -> EmailResult<Html<String>> {
+        let current = Users::find_by_id(*user_id.0)
+            .one(&self.context.db)
+            .await
+            .context("fail to find the user")
+            .map_err(AppError::internal)?
+            .ok_or_else(|| AppError::unauthorized("no user found for id"))?
+            .email
+            .unwrap_or_default();
+        render(&EmailPage {
+            flash,
+            csrf: csrf_token(session),
+            current,
+        })
+        .map(Html)
+    }
+
+    // whoever holds the reset address can take the account over, so changing it
+    // takes the current password and the old address is told about it
+    #[oai(path = "/", method = "post", transform = "add_session_uid_check")]
+    pub async fn change_email(
+        &self,
+        form: Form<ChangeEmailForm>,
+        remote_addr: &RemoteAddr,
+        request_id: Data<&RequestId>,
+        user_id: Data<&Uuid>,
+    ) -> EmailResult<()> {
+        let email = Email::parse(form.0.email).map_err(|_| {
+            AppError::new(ErrorCode::ValidationFailed, "The email address is invalid")
+                .see_other("/admin/email")
+        })?;
+        let user = Users::find_by_id(*user_id.0)
+            .one(&self.context.db)
+            .await
+            .context("fail to find the user")
+            .map_err(AppError::internal)?
+            .ok_or_else(|| AppError::unauthorized("no user found for id"))?;
+        let credentials = Credentials {
+            username: user.user_name,
+            password: Secret::new(form.0.current_password),
+        };
+        if let Err(e) = validate_credentials(
+            &self.context.db,
+            credentials,
+            &self.context.password_hash,
+            &self.context.dummy_password_hash,
+        )
+        .await
+        {
+            return match e {
+                AuthError::InvalidCredentials(_) => Err(AppError::new(
+                    ErrorCode::InvalidCredentials,
+                    "The current password is incorrect",
+                )
+                .see_other("/admin/email")),
+                AuthError::UnexpectedError(_) => Err(AppError::internal(e)),
+            };
+        }
+        user::ActiveModel {
+            id: ActiveValue::Set(user.id),
+            email: ActiveValue::Set(Some(email.as_ref().to_owned())),
+            ..Default::default()
+        }
+        .update(&self.context.db)
+        .await
+        .context("fail to update the email")
+        .map_err(AppError::internal)?;
+        record_audit_event(
+            &self.context.db,
+            user.id,
+            AuditAction::EmailChanged,
+            None,
+            &client_ip(remote_addr),
+        )
+        .await;
+        if let Some(old) = user.email.filter(|old| old != email.as_ref()) {
+            let context = self.context.clone();
+            let request_id = request_id.0.clone();
+            tokio::spawn(
+                async move {
+                    if let Err(e) = send_change_notice(&context, old, &request_id).await {
+                        tracing::error!(error = %e, "fail to tell the old address about the change");
+                    }
+                }
+                .in_current_span(),
+            );
+        }
+        Err(AppError::see_other_info(
+            "/admin/email",
+            "Your email has been changed.",
+        ))
+    }
+}
+
+async fn send_change_notice(
+    context: &StateContext,
+    old: String,
+    request_id: &RequestId,
+) -> anyhow::Result<()> {
+    let recipient = Email::parse(old).map_err(anyhow::Error::msg)?;
+    context
+        .email_client
+        .with_request_id(request_id.as_str())
+        .send_email(
+            &recipient,
+            "your account email was changed",
+            "Password reset links are no longer sent to this address. If you didn't change it, reset your password and tell an administrator.",
+            "Password reset links are no longer sent to this address. If you didn't change it, reset your password and tell an administrator.",
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "admin/email.html")]
+struct EmailPage {
+    flash: IncomingFlash,
+    csrf: String,
+    current: String,
+}
+
+// Secret doesn't implement poem_openapi::types::Type
+#[derive(Debug, Object, Deserialize)]
+pub struct ChangeEmailForm {
+    email: String,
+    current_password: String,
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
@@ -0,0 +1,82 @@
+use askama::Template;
+use poem::session::Session;
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use serde::Deserialize;
+
+use crate::{
+    auth::{csrf_token, Lockout, LockoutKind},
+    context::StateContext,
+    routes::{add_session_uid_check, error::AppError, flash::IncomingFlash, templates::render},
+};
+
+type LockoutsResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    Lockouts,
+}
+
+#[OpenApi(prefix_path = "/lockouts", tag = "MyTags::Lockouts")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn list_lockouts(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> LockoutsResult<Html<String>> {
+        let lockouts = self
+            .context
+            .login_throttle
+            .list_lockouts()
+            .await
+            .map_err(AppError::internal)?;
+        render(&LockoutsPage {
+            flash,
+            csrf: csrf_token(session),
+            lockouts,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/clear", method = "post", transform = "add_session_uid_check")]
+    pub async fn clear_lockout(&self, form: Form<ClearLockoutForm>) -> LockoutsResult<()> {
+        let kind = LockoutKind::parse(&form.kind).map_err(AppError::bad_request)?;
+        self.context
+            .login_throttle
+            .clear_lockout(kind, &form.subject)
+            .await
+            .map_err(AppError::internal)?;
+        tracing::info!(%kind, subject = form.subject, "login lockout cleared");
+        Err(AppError::see_other_info(
+            "/admin/lockouts",
+            "The lockout has been cleared.",
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/lockouts.html")]
+struct LockoutsPage {
+    flash: IncomingFlash,
+    csrf: String,
+    lockouts: Vec<Lockout>,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct ClearLockoutForm {
+    kind: String,
+    subject: String,
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
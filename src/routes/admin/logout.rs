@@ -1,22 +1,53 @@
-use poem::{handler, session::Session, IntoResponse};
-use reqwest::{header::LOCATION, StatusCode};
+use poem::{
+    handler,
+    session::Session,
+    web::{Data, RemoteAddr},
+    IntoResponse,
+};
+use reqwest::{
+    header::{LOCATION, SET_COOKIE},
+    StatusCode,
+};
+use uuid::Uuid;
 
-use crate::session_state::FLASH_KEY;
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::{revoke_session, CurrentSession},
+    context::StateContext,
+    routes::flash::FlashLevel,
+    utils::client_ip,
+};
 
 // if we don't wrap with poem::Result here, the redirection won't work
 #[handler]
-pub async fn post_logout(session: &Session) -> poem::Result<impl IntoResponse> {
+pub async fn post_logout(
+    session: &Session,
+    remote_addr: &RemoteAddr,
+    context: Data<&StateContext>,
+    user_id: Data<&Uuid>,
+    current_session: Data<&CurrentSession>,
+) -> poem::Result<impl IntoResponse> {
     tracing::info!("logout success in pure handler");
+    if let Err(e) = revoke_session(&context.redis, *user_id.0, current_session.id()).await {
+        tracing::error!(error = %e, "fail to unregister the session");
+    }
     session.purge();
+    record_audit_event(
+        &context.db,
+        *user_id.0,
+        AuditAction::Logout,
+        None,
+        &client_ip(remote_addr),
+    )
+    .await;
     let resp = poem::Response::builder()
         .status(StatusCode::SEE_OTHER)
         .header(LOCATION, "/login")
         .header(
-            "Set-Cookie",
-            format!(
-                "{}={}; Max-Age=1; Secure; HttpOnly",
-                FLASH_KEY, "You have successfully logged out."
-            ),
+            SET_COOKIE,
+            context
+                .flash_key
+                .cookie(FlashLevel::Info, "You have successfully logged out."),
         )
         .finish();
     Ok(resp)
@@ -1,21 +1,38 @@
+pub mod api;
+mod audit;
 mod dashboard;
+mod email;
+mod lockouts;
 pub mod logout;
 pub mod newsletters;
 mod password;
+mod sessions;
+pub mod subscribers;
+mod suppressions;
+mod tokens;
+mod two_factor;
 
-use poem::{Endpoint, IntoEndpoint};
-use poem_openapi::OpenApiService;
+use poem::Endpoint;
+use poem_openapi::{OpenApi, OpenApiService};
 
 use crate::context::StateContext;
 
 pub fn get_api_service(
     context: StateContext,
     server_url: &str,
-) -> (impl IntoEndpoint, impl Endpoint) {
+) -> (OpenApiService<impl OpenApi, ()>, impl Endpoint) {
     let service = OpenApiService::new(
         (
+            audit::Api::new(context.clone()),
             dashboard::Api::new(context.clone()),
-            password::Api::new(context),
+            email::Api::new(context.clone()),
+            lockouts::Api::new(context.clone()),
+            password::Api::new(context.clone()),
+            sessions::Api::new(context.clone()),
+            subscribers::Api::new(context.clone()),
+            suppressions::Api::new(context.clone()),
+            tokens::Api::new(context.clone()),
+            two_factor::Api::new(context),
         ),
         "admin",
         "0.1",
@@ -1,26 +1,59 @@
 use anyhow::Context;
-use poem::{handler, web::cookie::CookieJar, IntoResponse};
+use askama::Template;
+use poem::{handler, session::Session, web::RemoteAddr, IntoResponse};
 use poem_openapi::Object;
 use serde::Deserialize;
 use sqlx::{Postgres, Transaction};
 use uuid::Uuid;
 
 use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::csrf_token,
     context::StateContext,
     domain::idempotency::{
         get_saved_response, save_response, try_processing, IdempotencyKey, NextAction,
     },
-    routes::error::{see_other_with_cookie, BasicError},
-    session_state::FLASH_KEY,
+    entities::sea_orm_active_enums::SubscriptionStatus,
+    routes::{
+        error::{see_other_with_cookie, AppError},
+        flash::IncomingFlash,
+        request_id::RequestId,
+        templates::render,
+    },
+    utils::client_ip,
 };
 
-type PublishResult<T> = std::result::Result<T, BasicError>;
+type PublishResult<T> = std::result::Result<T, AppError>;
 
 #[handler]
 pub async fn publish_newsletter(
     context: poem::web::Data<&StateContext>,
     form: poem::web::Form<NewsletterForm>,
     user_id: poem::web::Data<&Uuid>,
+    request_id: poem::web::Data<&RequestId>,
+    remote_addr: &RemoteAddr,
+) -> PublishResult<poem::Response> {
+    let ip = client_ip(remote_addr);
+    publish_issue(&context, user_id.0, &ip, &request_id, form.0, |_| {
+        see_other_with_cookie(
+            "/admin/newsletters",
+            &context.flash_key,
+            "The newsletter issue has been published!",
+        )
+        .into_response()
+    })
+    .await
+}
+
+// shared by the form and the json api, `success` builds the response that is
+// saved for the idempotency key out of the new issue id
+pub async fn publish_issue(
+    context: &StateContext,
+    user_id: &Uuid,
+    ip: &str,
+    request_id: &RequestId,
+    newsletter: NewsletterForm,
+    success: impl FnOnce(Uuid) -> poem::Response,
 ) -> PublishResult<poem::Response> {
     // list all confirmed subscribers
     // ideally, we should let some workers to handle all the confirmed subscribers
@@ -31,14 +64,12 @@ pub async fn publish_newsletter(
         text_content,
         html_content,
         idempotency_key,
-    } = form.0;
-    let idempotency_key: IdempotencyKey = idempotency_key
-        .try_into()
-        .map_err(BasicError::interval_error)?;
+    } = newsletter;
+    let idempotency_key: IdempotencyKey = idempotency_key.try_into().map_err(AppError::internal)?;
 
-    let mut tx = match try_processing(db, &idempotency_key, user_id.0)
+    let mut tx = match try_processing(db, &idempotency_key, user_id)
         .await
-        .map_err(BasicError::interval_error)?
+        .map_err(AppError::internal)?
     {
         NextAction::ContinueProcessing(tx) => tx,
         NextAction::ReturnSavedResponse(saved_resp) => {
@@ -48,44 +79,56 @@ pub async fn publish_newsletter(
 
     let issue_id = insert_newsletter_issue(&mut tx, &title, &text_content, &html_content)
         .await
-        .map_err(BasicError::interval_error)?;
+        .map_err(AppError::internal)?;
 
     // this will generate a task for each confirmed subscriber email
-    enqueue_delivery_tasks(&mut tx, issue_id)
+    enqueue_delivery_tasks(&mut tx, issue_id, request_id)
         .await
         .context("fail to enqueue email delivery task")
-        .map_err(BasicError::interval_error)?;
+        .map_err(AppError::internal)?;
 
-    if let Some(saved_resp) = get_saved_response(db, &idempotency_key, user_id.0)
+    if let Some(saved_resp) = get_saved_response(db, &idempotency_key, user_id)
         .await
         .context("fail to get saved response")
-        .map_err(BasicError::interval_error)?
+        .map_err(AppError::internal)?
     {
         return Ok(saved_resp);
     }
 
-    let resp = see_other_with_cookie(
-        "/admin/newsletters",
-        "The newsletter issue has been published!",
-    )
-    .into_response();
-    let resp = save_response(tx, &idempotency_key, user_id.0, resp)
+    let resp = success(issue_id);
+    let resp = save_response(tx, &idempotency_key, user_id, resp)
         .await
-        .map_err(BasicError::interval_error)?;
+        .map_err(AppError::internal)?;
+    record_audit_event(
+        db,
+        *user_id,
+        AuditAction::NewsletterPublished,
+        Some(&issue_id.to_string()),
+        ip,
+    )
+    .await;
     Ok(resp)
 }
 
 #[handler]
-pub async fn get_newsletter_submit_form(cookiejar: &CookieJar) -> poem::web::Html<String> {
-    let mut error = String::new();
-    if let Some(cookie) = cookiejar.get(FLASH_KEY) {
-        error = format!("<p><i>{}</i></p>", cookie.value_str().to_owned());
-    }
-    let idempotency_key = Uuid::new_v4().to_string();
-    poem::web::Html(format!(
-        include_str!("newsletter.html"),
-        error, idempotency_key
-    ))
+pub async fn get_newsletter_submit_form(
+    flash: IncomingFlash,
+    session: &Session,
+) -> PublishResult<poem::web::Html<String>> {
+    render(&NewsletterPage {
+        flash,
+        csrf: csrf_token(session),
+        idempotency_key: Uuid::new_v4().to_string(),
+    })
+    .map(poem::web::Html)
+}
+
+#[derive(Template)]
+#[template(path = "admin/newsletter.html")]
+struct NewsletterPage {
+    flash: IncomingFlash,
+    csrf: String,
+    idempotency_key: String,
 }
 
 // {
@@ -131,21 +174,34 @@ async fn insert_newsletter_issue(
     Ok(newsletter_issue_id)
 }
 
+// the request id lets the worker link every delivery back to the publishing request
 async fn enqueue_delivery_tasks(
     tx: &mut Transaction<'_, Postgres>,
     issue_id: Uuid,
+    request_id: &RequestId,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
         r#"
         INSERT INTO issue_delivery_queue (
             newsletter_issue_id,
-            subscriber_email
+            subscriber_email,
+            request_id
             )
-        SELECT $1, email
+        SELECT $1, email, $2
         FROM subscriptions
-        WHERE status = 'confirmed'
+        WHERE status = $3 AND NOT EXISTS (
+            SELECT 1 FROM suppressions
+            WHERE address IN (
+                lower(trim(email)),
+                lower(split_part(trim(email), '@', 2)),
+                -- an erased address is suppressed under its hash
+                encode(sha256(convert_to(lower(trim(email)), 'UTF8')), 'hex')
+            )
+        )
         "#,
-        issue_id
+        issue_id,
+        request_id.as_str(),
+        SubscriptionStatus::Confirmed as _
     )
     .execute(tx)
     .await?;
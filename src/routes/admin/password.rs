@@ -1,25 +1,36 @@
-use anyhow::{anyhow, Context};
-use poem::web::Data;
+use anyhow::Context;
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
 use poem_openapi::{
     payload::{Form, Html},
     Object, OpenApi, Tags,
 };
-use sea_orm::{ActiveModelTrait, ActiveValue, DatabaseConnection};
 use secrecy::Secret;
 use serde::Deserialize;
 use uuid::Uuid;
 
 use super::dashboard::get_username;
 use crate::{
-    auth::{get_hash, validate_credentials, AuthError, Credentials},
+    audit::{record_audit_event, AuditAction},
+    auth::{
+        change_password, csrf_token, revoke_other_sessions, validate_credentials, AuthError,
+        Credentials, CurrentSession,
+    },
     context::StateContext,
-    entities::user,
-    routes::{add_session_uid_check, error::BasicError},
-    session_state::FLASH_KEY,
-    utils::spawn_blocking_with_tracing,
+    domain::NewPassword,
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        templates::render,
+    },
+    utils::client_ip,
 };
 
-type PasswordResult<T> = std::result::Result<T, BasicError>;
+type PasswordResult<T> = std::result::Result<T, AppError>;
 
 pub struct Api {
     context: StateContext,
@@ -35,97 +46,82 @@ impl Api {
     #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
     pub async fn change_password_form(
         &self,
-        cookiejar: &poem::web::cookie::CookieJar,
-    ) -> Html<String> {
-        let mut msg_html = String::new();
-        if let Some(cookie) = cookiejar.get(FLASH_KEY) {
-            msg_html.push_str(&format!("<p><i>{}</i></p>", cookie.value_str()));
-        }
-        Html(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Change Password</title>
-</head>
-<body>
-    {msg_html}
-    <form action="/admin/password" method="post">
-        <label>Current password
-            <input
-                type="password"
-                placeholder="Enter current password"
-                name="current_password"
-            >
-        </label>
-        <br>
-        <label>New password
-            <input
-                type="password"
-                placeholder="Enter new password"
-                name="new_password"
-            >
-        </label>
-        <br>
-        <label>Confirm new password
-            <input
-                type="password"
-                placeholder="Type the new password again"
-                name="new_password_check"
-            >
-        </label>
-        <br>
-        <button type="submit">Change password</button>
-    </form>
-    <p><a href="/admin/dashboard">&lt;- Back</a></p>
-</body>
-</html>"#,
-        ))
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> PasswordResult<Html<String>> {
+        render(&ChangePasswordPage {
+            flash,
+            csrf: csrf_token(session),
+        })
+        .map(Html)
     }
 
     #[oai(path = "/", method = "post", transform = "add_session_uid_check")]
     pub async fn change_password(
         &self,
         form: Form<ChangePasswordForm>,
+        remote_addr: &RemoteAddr,
         user_id: Data<&Uuid>,
+        current_session: Data<&CurrentSession>,
     ) -> PasswordResult<()> {
-        if form.new_password.len() > 128 || form.new_password.len() < 12 {
-            return Err(BasicError::see_other(
-                "/admin/password",
-                "The password length must be between 12 to 128",
-            ));
-        }
+        let new_password = NewPassword::parse(form.new_password.clone()).map_err(|e| {
+            AppError::new(ErrorCode::ValidationFailed, e).see_other("/admin/password")
+        })?;
         if form.new_password != form.new_password_check {
-            return Err(BasicError::see_other(
-                "/admin/password",
+            return Err(AppError::new(
+                ErrorCode::ValidationFailed,
                 "You entered two different new passwords - the field values must match",
-            ));
+            )
+            .see_other("/admin/password"));
         }
         let user_id = user_id.0;
         let username = get_username(*user_id, &self.context.db)
             .await
             .context("fail to get username from user_id")
-            .map_err(BasicError::interval_error)?
-            .ok_or(BasicError::bad_request(
-                "no username found for id".to_owned(),
-            ))?;
+            .map_err(AppError::internal)?
+            .ok_or(AppError::bad_request("no username found for id".to_owned()))?;
         let credentials = Credentials {
             username,
             password: Secret::new(form.current_password.clone()),
         };
-        match validate_credentials(&self.context.db, credentials).await {
+        match validate_credentials(
+            &self.context.db,
+            credentials,
+            &self.context.password_hash,
+            &self.context.dummy_password_hash,
+        )
+        .await
+        {
             Err(e) => match e {
-                AuthError::InvalidCredentials(_) => Err(BasicError::see_other(
-                    "/admin/password",
+                AuthError::InvalidCredentials(_) => Err(AppError::new(
+                    ErrorCode::InvalidCredentials,
                     "The current password is incorrect",
-                )),
-                AuthError::UnexpectedError(_) => Err(BasicError::interval_error(e)),
+                )
+                .see_other("/admin/password")),
+                AuthError::UnexpectedError(_) => Err(AppError::internal(e)),
             },
             Ok(uid) => {
-                change_password(uid, form.new_password.clone(), &self.context.db)
+                change_password(
+                    uid,
+                    new_password,
+                    &self.context.db,
+                    &self.context.password_hash,
+                )
+                .await
+                .map_err(AppError::internal)?;
+                // whoever else knew the old password is logged out
+                revoke_other_sessions(&self.context.redis, uid, Some(current_session.id()))
                     .await
-                    .map_err(BasicError::interval_error)?;
-                Err(BasicError::see_other(
+                    .map_err(AppError::internal)?;
+                record_audit_event(
+                    &self.context.db,
+                    uid,
+                    AuditAction::PasswordChanged,
+                    None,
+                    &client_ip(remote_addr),
+                )
+                .await;
+                Err(AppError::see_other_info(
                     "/admin/password",
                     "Your password has been changed.",
                 ))
@@ -134,32 +130,11 @@ impl Api {
     }
 }
 
-pub async fn change_password(
-    uid: Uuid,
-    password: String,
-    db: &DatabaseConnection,
-) -> Result<(), anyhow::Error> {
-    // generate a random new salt
-    let password_hashed =
-        spawn_blocking_with_tracing(move || -> Result<String, argon2::password_hash::Error> {
-            // let salt = SaltString::generate(&mut rand::thread_rng()).to_string();
-            let salt = Uuid::new_v4().to_string();
-            get_hash(&password, &salt)
-        })
-        .await
-        .map_err(|e| {
-            anyhow!(format!(
-                "fail to join spawning tokio task for computing hash {e}"
-            ))
-        })??;
-
-    let active_user = user::ActiveModel {
-        id: ActiveValue::Set(uid),
-        password_hashed: ActiveValue::Set(password_hashed),
-        user_name: ActiveValue::NotSet,
-    };
-    active_user.update(db).await?;
-    Ok(())
+#[derive(Template)]
+#[template(path = "admin/password.html")]
+struct ChangePasswordPage {
+    flash: IncomingFlash,
+    csrf: String,
 }
 
 // Secret doesn't implement poem_openapi::types::Type
@@ -0,0 +1,140 @@
+use askama::Template;
+use poem::{session::Session, web::Data};
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    auth::{csrf_token, list_sessions, revoke_other_sessions, revoke_session, CurrentSession},
+    context::StateContext,
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        templates::render,
+    },
+};
+
+type SessionsResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    Sessions,
+}
+
+#[OpenApi(prefix_path = "/sessions", tag = "MyTags::Sessions")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn list_sessions(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        user_id: Data<&Uuid>,
+        current_session: Data<&CurrentSession>,
+    ) -> SessionsResult<Html<String>> {
+        let sessions = list_sessions(&self.context.redis, *user_id.0, &self.context.session)
+            .await
+            .map_err(AppError::internal)?
+            .into_iter()
+            .map(|session| SessionRow {
+                current: session.id == current_session.id(),
+                created_at: format_timestamp(session.metadata.created_at),
+                last_seen: format_timestamp(session.metadata.last_seen),
+                ip: session.metadata.ip,
+                user_agent: session.metadata.user_agent,
+                id: session.id,
+            })
+            .collect();
+        render(&SessionsPage {
+            flash,
+            csrf: csrf_token(session),
+            sessions,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/revoke", method = "post", transform = "add_session_uid_check")]
+    pub async fn revoke_session(
+        &self,
+        form: Form<RevokeSessionForm>,
+        user_id: Data<&Uuid>,
+    ) -> SessionsResult<()> {
+        let revoked = revoke_session(&self.context.redis, *user_id.0, &form.id)
+            .await
+            .map_err(AppError::internal)?;
+        if !revoked {
+            return Err(AppError::new(
+                ErrorCode::NotFound,
+                "The session doesn't exist or has already ended",
+            )
+            .see_other("/admin/sessions"));
+        }
+        tracing::info!(user_id = %user_id.0, session_id = form.id, "session revoked");
+        Err(AppError::see_other_info(
+            "/admin/sessions",
+            "The session has been revoked.",
+        ))
+    }
+
+    #[oai(
+        path = "/revoke_others",
+        method = "post",
+        transform = "add_session_uid_check"
+    )]
+    pub async fn revoke_other_sessions(
+        &self,
+        user_id: Data<&Uuid>,
+        current_session: Data<&CurrentSession>,
+    ) -> SessionsResult<()> {
+        revoke_other_sessions(&self.context.redis, *user_id.0, Some(current_session.id()))
+            .await
+            .map_err(AppError::internal)?;
+        tracing::info!(user_id = %user_id.0, "other sessions revoked");
+        Err(AppError::see_other_info(
+            "/admin/sessions",
+            "All other sessions have been revoked.",
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/sessions.html")]
+struct SessionsPage {
+    flash: IncomingFlash,
+    csrf: String,
+    sessions: Vec<SessionRow>,
+}
+
+struct SessionRow {
+    id: String,
+    // the session the page is viewed from can't be revoked here
+    current: bool,
+    created_at: String,
+    last_seen: String,
+    ip: String,
+    user_agent: String,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct RevokeSessionForm {
+    id: String,
+}
+
+fn format_timestamp(timestamp: i64) -> String {
+    chrono::NaiveDateTime::from_timestamp_opt(timestamp, 0)
+        .map(|time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
@@ -0,0 +1,543 @@
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
+use poem_openapi::{
+    param::Query,
+    payload::{Attachment, AttachmentType, Form, Html},
+    types::multipart::Upload,
+    Multipart, Object, OpenApi, Tags,
+};
+use sea_orm::{ActiveEnum, Iterable};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::csrf_token,
+    context::StateContext,
+    entities::{sea_orm_active_enums::SubscriptionStatus, subscriptions},
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        request_id::RequestId,
+        subscriptions::resend_subscription_email,
+        templates::render,
+    },
+    subscribers::{
+        delete_subscriber, drop_erased, erase_subscriber, export_subscriber_data, find_subscriber,
+        import_subscribers, list_subscribers, parse_import, set_subscriber_status, ImportReport,
+        RowError, StatusChange, SubscriberData, SubscriberFilter, DEFAULT_PAGE_SIZE,
+    },
+    utils::client_ip,
+};
+
+type SubscribersResult<T> = std::result::Result<T, AppError>;
+
+const SUBSCRIBERS_PAGE: &str = "/admin/subscribers";
+const IMPORT_PAGE: &str = "/admin/subscribers/import";
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    Subscribers,
+}
+
+#[OpenApi(prefix_path = "/subscribers", tag = "MyTags::Subscribers")]
+impl Api {
+    // the filter form submits every field, the empty ones match everything
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    #[allow(clippy::too_many_arguments)]
+    pub async fn list_subscribers(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        search: Query<Option<String>>,
+        status: Query<Option<String>>,
+        since: Query<Option<String>>,
+        until: Query<Option<String>>,
+        page: Query<Option<u64>>,
+    ) -> SubscribersResult<Html<String>> {
+        let search = non_empty(search.0);
+        let status = non_empty(status.0);
+        let since = non_empty(since.0);
+        let until = non_empty(until.0);
+
+        let filter = SubscriberFilter::parse(
+            search.clone(),
+            status.as_deref(),
+            since.as_deref(),
+            until.as_deref(),
+        );
+        let (listing, error) = match filter {
+            Ok(filter) => {
+                let listing = list_subscribers(
+                    &self.context.db,
+                    &filter,
+                    page.0.unwrap_or(1),
+                    DEFAULT_PAGE_SIZE,
+                )
+                .await
+                .map_err(AppError::internal)?;
+                (Some(listing), None)
+            }
+            Err(e) => (None, Some(e)),
+        };
+        let statuses = SubscriptionStatus::iter()
+            .map(|option| {
+                let option = option.to_value();
+                let selected = status.as_deref() == Some(option.as_str());
+                (option, selected)
+            })
+            .collect();
+        let (subscribers, page, last_page, total) = match listing {
+            Some(listing) => {
+                let last_page = listing.last_page();
+                (listing.subscribers, listing.page, last_page, listing.total)
+            }
+            None => (Vec::new(), 1, 1, 0),
+        };
+        render(&SubscribersPage {
+            flash,
+            csrf: csrf_token(session),
+            error,
+            search: search.unwrap_or_default(),
+            statuses,
+            status: status.unwrap_or_default(),
+            since: since.unwrap_or_default(),
+            until: until.unwrap_or_default(),
+            subscribers,
+            page,
+            last_page,
+            total,
+        })
+        .map(Html)
+    }
+
+    #[oai(
+        path = "/confirm",
+        method = "post",
+        transform = "add_session_uid_check"
+    )]
+    pub async fn confirm_subscriber(
+        &self,
+        form: Form<SubscriberForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SubscribersResult<()> {
+        let id = form.id()?;
+        match set_subscriber_status(&self.context.db, id, SubscriptionStatus::Confirmed)
+            .await
+            .map_err(AppError::internal)?
+        {
+            StatusChange::Changed(_) => {}
+            StatusChange::Unknown => return Err(unknown_subscriber().see_other(SUBSCRIBERS_PAGE)),
+            StatusChange::Suppressed => {
+                return Err(suppressed_subscriber().see_other(SUBSCRIBERS_PAGE))
+            }
+        }
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::SubscriberConfirmed,
+            Some(&id.to_string()),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            SUBSCRIBERS_PAGE,
+            "The subscriber has been confirmed.",
+        ))
+    }
+
+    #[oai(
+        path = "/unsubscribe",
+        method = "post",
+        transform = "add_session_uid_check"
+    )]
+    pub async fn unsubscribe_subscriber(
+        &self,
+        form: Form<SubscriberForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SubscribersResult<()> {
+        let id = form.id()?;
+        if let StatusChange::Unknown =
+            set_subscriber_status(&self.context.db, id, SubscriptionStatus::Unsubscribed)
+                .await
+                .map_err(AppError::internal)?
+        {
+            return Err(unknown_subscriber().see_other(SUBSCRIBERS_PAGE));
+        }
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::SubscriberUnsubscribed,
+            Some(&id.to_string()),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            SUBSCRIBERS_PAGE,
+            "The subscriber has been unsubscribed.",
+        ))
+    }
+
+    #[oai(path = "/delete", method = "post", transform = "add_session_uid_check")]
+    pub async fn delete_subscriber(
+        &self,
+        form: Form<SubscriberForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SubscribersResult<()> {
+        let id = form.id()?;
+        let deleted = delete_subscriber(&self.context.db, id)
+            .await
+            .map_err(AppError::internal)?;
+        if !deleted {
+            return Err(unknown_subscriber().see_other(SUBSCRIBERS_PAGE));
+        }
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::SubscriberDeleted,
+            Some(&id.to_string()),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            SUBSCRIBERS_PAGE,
+            "The subscriber has been deleted.",
+        ))
+    }
+
+    #[oai(path = "/resend", method = "post", transform = "add_session_uid_check")]
+    pub async fn resend_confirmation(
+        &self,
+        form: Form<SubscriberForm>,
+        request_id: Data<&RequestId>,
+    ) -> SubscribersResult<()> {
+        let id = form.id()?;
+        let subscriber = find_subscriber(&self.context.db, id)
+            .await
+            .map_err(AppError::internal)?
+            .ok_or_else(|| unknown_subscriber().see_other(SUBSCRIBERS_PAGE))?;
+        resend_confirmation_email(&self.context, &subscriber, &request_id)
+            .await
+            .map_err(|e| e.see_other(SUBSCRIBERS_PAGE))?;
+        Err(AppError::see_other_info(
+            SUBSCRIBERS_PAGE,
+            "The confirmation email has been sent again.",
+        ))
+    }
+
+    // a data subject request, everything stored about the subscriber's address
+    #[oai(path = "/data", method = "post", transform = "add_session_uid_check")]
+    pub async fn export_data(
+        &self,
+        form: Form<SubscriberForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SubscribersResult<Attachment<Vec<u8>>> {
+        let id = form.id()?;
+        let subscriber = find_subscriber(&self.context.db, id)
+            .await
+            .map_err(AppError::internal)?
+            .ok_or_else(|| unknown_subscriber().see_other(SUBSCRIBERS_PAGE))?;
+        let data = run_data_export(
+            &self.context,
+            &subscriber.email,
+            *user_id.0,
+            &client_ip(remote_addr),
+        )
+        .await?
+        .ok_or_else(|| unknown_subscriber().see_other(SUBSCRIBERS_PAGE))?;
+        let json = serde_json::to_vec_pretty(&data).map_err(AppError::internal)?;
+        Ok(Attachment::new(json)
+            .attachment_type(AttachmentType::Attachment)
+            .filename(format!("subscriber-{id}.json")))
+    }
+
+    #[oai(path = "/erase", method = "post", transform = "add_session_uid_check")]
+    pub async fn erase(
+        &self,
+        form: Form<SubscriberForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SubscribersResult<()> {
+        let id = form.id()?;
+        let subscriber = find_subscriber(&self.context.db, id)
+            .await
+            .map_err(AppError::internal)?
+            .ok_or_else(|| unknown_subscriber().see_other(SUBSCRIBERS_PAGE))?;
+        run_erasure(
+            &self.context,
+            &subscriber.email,
+            *user_id.0,
+            &client_ip(remote_addr),
+        )
+        .await?;
+        Err(AppError::see_other_info(
+            SUBSCRIBERS_PAGE,
+            "The subscriber's data has been erased.",
+        ))
+    }
+
+    #[oai(path = "/import", method = "get", transform = "add_session_uid_check")]
+    pub async fn import_form(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> SubscribersResult<Html<String>> {
+        render(&ImportPage {
+            flash,
+            csrf: csrf_token(session),
+            report: None,
+        })
+        .map(Html)
+    }
+
+    // the report is rendered right away, it doesn't fit in a flash message
+    #[oai(path = "/import", method = "post", transform = "add_session_uid_check")]
+    pub async fn import(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        upload: ImportUpload,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+        request_id: Data<&RequestId>,
+    ) -> SubscribersResult<Html<String>> {
+        let csv = upload
+            .file
+            .into_vec()
+            .await
+            .map_err(AppError::bad_request)?;
+        let csv = String::from_utf8(csv).map_err(|_| {
+            AppError::new(
+                ErrorCode::ValidationFailed,
+                "The file must be UTF-8 encoded",
+            )
+            .see_other(IMPORT_PAGE)
+        })?;
+        let report = run_import(
+            &self.context,
+            &csv,
+            upload.send_confirmation.is_some(),
+            *user_id.0,
+            &client_ip(remote_addr),
+            &request_id,
+        )
+        .await
+        .map_err(|e| e.see_other(IMPORT_PAGE))?;
+        render(&ImportPage {
+            flash,
+            csrf: csrf_token(session),
+            report: Some(report),
+        })
+        .map(Html)
+    }
+}
+
+// shared by the admin page and the json api, `None` when nothing is stored
+pub async fn run_data_export(
+    context: &StateContext,
+    email: &str,
+    actor_id: Uuid,
+    ip: &str,
+) -> SubscribersResult<Option<SubscriberData>> {
+    let data = export_subscriber_data(&context.db, email)
+        .await
+        .map_err(AppError::internal)?;
+    if let Some(data) = &data {
+        for subscription in &data.subscriptions {
+            record_audit_event(
+                &context.db,
+                actor_id,
+                AuditAction::SubscriberDataExported,
+                Some(&subscription.id.to_string()),
+                ip,
+            )
+            .await;
+        }
+    }
+    Ok(data)
+}
+
+// the audit events only name the erased subscriptions by id
+pub async fn run_erasure(
+    context: &StateContext,
+    email: &str,
+    actor_id: Uuid,
+    ip: &str,
+) -> SubscribersResult<()> {
+    let erased = erase_subscriber(&context.db, email)
+        .await
+        .map_err(AppError::internal)?;
+    for id in erased {
+        record_audit_event(
+            &context.db,
+            actor_id,
+            AuditAction::SubscriberErased,
+            Some(&id.to_string()),
+            ip,
+        )
+        .await;
+    }
+    Ok(())
+}
+
+// shared by the upload form and the json api, a file that can't be read at all
+// fails as a whole, otherwise every bad row is reported and the others are imported
+pub async fn run_import(
+    context: &StateContext,
+    csv: &str,
+    send_confirmation: bool,
+    actor_id: Uuid,
+    ip: &str,
+    request_id: &RequestId,
+) -> SubscribersResult<ImportReport> {
+    let (rows, mut errors) =
+        parse_import(csv).map_err(|e| AppError::new(ErrorCode::ValidationFailed, e))?;
+    let rows = drop_erased(&context.db, rows, &mut errors)
+        .await
+        .map_err(AppError::internal)?;
+    let valid_rows = rows.len();
+    let imported = import_subscribers(&context.db, rows)
+        .await
+        .map_err(AppError::internal)?;
+    let mut report = ImportReport {
+        imported: imported.len(),
+        skipped: valid_rows - imported.len(),
+        errors,
+        ..Default::default()
+    };
+    if send_confirmation {
+        for (line, subscriber) in imported
+            .iter()
+            .filter(|(_, subscriber)| subscriber.status == SubscriptionStatus::Pending)
+        {
+            match resend_subscription_email(context, subscriber, request_id).await {
+                Ok(true) => report.confirmations_sent += 1,
+                Ok(false) => report.errors.push(RowError::new(
+                    *line,
+                    "imported, but the address is suppressed and wasn't mailed",
+                )),
+                Err(e) => {
+                    tracing::error!(error = ?e, email = %subscriber.email, "fail to send the confirmation email");
+                    report.errors.push(RowError::new(
+                        *line,
+                        "imported, but the confirmation email couldn't be sent",
+                    ));
+                }
+            }
+        }
+        report.errors.sort_by_key(|error| error.line);
+    }
+    record_audit_event(
+        &context.db,
+        actor_id,
+        AuditAction::SubscribersImported,
+        Some(&format!("{} subscribers", report.imported)),
+        ip,
+    )
+    .await;
+    Ok(report)
+}
+
+// only a subscriber who hasn't confirmed yet has a use for the link
+pub async fn resend_confirmation_email(
+    context: &StateContext,
+    subscriber: &subscriptions::Model,
+    request_id: &RequestId,
+) -> SubscribersResult<()> {
+    if subscriber.status != SubscriptionStatus::Pending {
+        return Err(AppError::new(
+            ErrorCode::ValidationFailed,
+            "Only pending subscribers can be sent a confirmation email",
+        ));
+    }
+    let sent = resend_subscription_email(context, subscriber, request_id)
+        .await
+        .map_err(AppError::internal)?;
+    if !sent {
+        return Err(AppError::new(
+            ErrorCode::ValidationFailed,
+            "The address is suppressed, no email was sent",
+        ));
+    }
+    Ok(())
+}
+
+pub fn unknown_subscriber() -> AppError {
+    AppError::new(ErrorCode::NotFound, "The subscriber doesn't exist")
+}
+
+pub fn suppressed_subscriber() -> AppError {
+    AppError::new(
+        ErrorCode::Conflict,
+        "The subscriber bounced or complained, remove the address from the suppressions to mail it again",
+    )
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersPage {
+    flash: IncomingFlash,
+    csrf: String,
+    error: Option<String>,
+    search: String,
+    // every status and whether it is the selected one
+    statuses: Vec<(String, bool)>,
+    status: String,
+    since: String,
+    until: String,
+    subscribers: Vec<subscriptions::Model>,
+    page: u64,
+    last_page: u64,
+    total: u64,
+}
+
+#[derive(Template)]
+#[template(path = "admin/import.html")]
+struct ImportPage {
+    flash: IncomingFlash,
+    csrf: String,
+    report: Option<ImportReport>,
+}
+
+#[derive(Debug, Multipart)]
+pub struct ImportUpload {
+    file: Upload,
+    // a checkbox, only sent when it is ticked
+    send_confirmation: Option<String>,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct SubscriberForm {
+    // uuid doesn't impl poem_openapi::types::Type
+    id: String,
+}
+
+impl SubscriberForm {
+    fn id(&self) -> SubscribersResult<Uuid> {
+        Uuid::parse_str(&self.id).map_err(AppError::bad_request)
+    }
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty())
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
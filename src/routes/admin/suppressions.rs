@@ -0,0 +1,161 @@
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::csrf_token,
+    context::StateContext,
+    entities::suppressions,
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        templates::render,
+    },
+    suppressions::{
+        add_suppression, audited_address, list_suppressions, parse_suppressed_address,
+        remove_suppression, SuppressionSource,
+    },
+    utils::client_ip,
+};
+
+type SuppressionsResult<T> = std::result::Result<T, AppError>;
+
+const SUPPRESSIONS_PAGE: &str = "/admin/suppressions";
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    Suppressions,
+}
+
+#[OpenApi(prefix_path = "/suppressions", tag = "MyTags::Suppressions")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn list_suppressions(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> SuppressionsResult<Html<String>> {
+        let suppressions = list_suppressions(&self.context.db)
+            .await
+            .map_err(AppError::internal)?;
+        render(&SuppressionsPage {
+            flash,
+            csrf: csrf_token(session),
+            suppressions,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/", method = "post", transform = "add_session_uid_check")]
+    pub async fn add_suppression(
+        &self,
+        form: Form<AddSuppressionForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SuppressionsResult<()> {
+        let address = parse_suppressed_address(&form.address).map_err(|e| {
+            AppError::new(ErrorCode::ValidationFailed, e).see_other(SUPPRESSIONS_PAGE)
+        })?;
+        let reason = match form.reason.as_deref().map(str::trim) {
+            Some(reason) if !reason.is_empty() => reason,
+            _ => "added by an admin",
+        };
+        let added = add_suppression(&self.context.db, &address, reason, SuppressionSource::Admin)
+            .await
+            .map_err(AppError::internal)?;
+        if !added {
+            return Err(AppError::new(
+                ErrorCode::ValidationFailed,
+                format!("{address} is already suppressed"),
+            )
+            .see_other(SUPPRESSIONS_PAGE));
+        }
+        let audited = audited_address(&address);
+        tracing::info!(address = audited, "suppression added");
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::SuppressionAdded,
+            Some(&audited),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            SUPPRESSIONS_PAGE,
+            &format!("{address} won't be mailed anymore."),
+        ))
+    }
+
+    #[oai(path = "/remove", method = "post", transform = "add_session_uid_check")]
+    pub async fn remove_suppression(
+        &self,
+        form: Form<RemoveSuppressionForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> SuppressionsResult<()> {
+        let removed = remove_suppression(&self.context.db, &form.address)
+            .await
+            .map_err(AppError::internal)?;
+        if !removed {
+            return Err(AppError::new(
+                ErrorCode::NotFound,
+                "The address or domain isn't suppressed",
+            )
+            .see_other(SUPPRESSIONS_PAGE));
+        }
+        let audited = audited_address(&form.address);
+        tracing::info!(address = audited, "suppression removed");
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::SuppressionRemoved,
+            Some(&audited),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            SUPPRESSIONS_PAGE,
+            "The suppression has been removed.",
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/suppressions.html")]
+struct SuppressionsPage {
+    flash: IncomingFlash,
+    csrf: String,
+    suppressions: Vec<suppressions::Model>,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct AddSuppressionForm {
+    // an address, or a domain for every address at it
+    address: String,
+    reason: Option<String>,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct RemoveSuppressionForm {
+    address: String,
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
@@ -0,0 +1,234 @@
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use sea_orm::prelude::DateTimeWithTimeZone;
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::{
+        create_api_token, csrf_token, list_api_tokens, parse_scopes, revoke_api_token, ApiScope,
+    },
+    context::StateContext,
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        templates::render,
+    },
+    utils::client_ip,
+};
+
+type TokensResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    ApiTokens,
+}
+
+#[OpenApi(prefix_path = "/tokens", tag = "MyTags::ApiTokens")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn list_tokens(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        user_id: Data<&Uuid>,
+    ) -> TokensResult<Html<String>> {
+        let tokens = list_api_tokens(&self.context.db, *user_id.0)
+            .await
+            .map_err(AppError::internal)?
+            .into_iter()
+            .map(|token| TokenRow {
+                id: token.id,
+                scopes: parse_scopes(&token.scopes)
+                    .iter()
+                    .map(ApiScope::as_str)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                created_at: format_time(&token.created_at),
+                last_used_at: token
+                    .last_used_at
+                    .as_ref()
+                    .map(format_time)
+                    .unwrap_or_else(|| "never".to_owned()),
+                revoked: token.revoked_at.is_some(),
+                name: token.name,
+            })
+            .collect();
+        render(&TokensPage {
+            flash,
+            csrf: csrf_token(session),
+            tokens,
+            scopes: ApiScope::ALL
+                .into_iter()
+                .map(|scope| (scope_field(scope), scope))
+                .collect(),
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/", method = "post", transform = "add_session_uid_check")]
+    pub async fn create_token(
+        &self,
+        form: Form<CreateTokenForm>,
+        session: &Session,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> TokensResult<Html<String>> {
+        let name = form.name.trim();
+        if name.is_empty() {
+            return Err(
+                AppError::new(ErrorCode::ValidationFailed, "The token needs a name")
+                    .see_other("/admin/tokens"),
+            );
+        }
+        let scopes = form.scopes();
+        if scopes.is_empty() {
+            return Err(
+                AppError::new(ErrorCode::ValidationFailed, "Select at least one scope")
+                    .see_other("/admin/tokens"),
+            );
+        }
+        let (stored, token) = create_api_token(&self.context.db, *user_id.0, name, &scopes)
+            .await
+            .map_err(AppError::internal)?;
+        tracing::info!(user_id = %user_id.0, token_id = %stored.id, "api token created");
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::ApiTokenCreated,
+            Some(&stored.id.to_string()),
+            &client_ip(remote_addr),
+        )
+        .await;
+        render(&NewTokenPage {
+            flash: IncomingFlash::default(),
+            csrf: csrf_token(session),
+            name: name.to_owned(),
+            token,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/revoke", method = "post", transform = "add_session_uid_check")]
+    pub async fn revoke_token(
+        &self,
+        form: Form<RevokeTokenForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> TokensResult<()> {
+        let token_id = Uuid::parse_str(&form.id).map_err(AppError::bad_request)?;
+        let revoked = revoke_api_token(&self.context.db, *user_id.0, token_id)
+            .await
+            .map_err(AppError::internal)?;
+        if !revoked {
+            return Err(AppError::new(
+                ErrorCode::NotFound,
+                "The token doesn't exist or is already revoked",
+            )
+            .see_other("/admin/tokens"));
+        }
+        tracing::info!(user_id = %user_id.0, %token_id, "api token revoked");
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::ApiTokenRevoked,
+            Some(&token_id.to_string()),
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            "/admin/tokens",
+            "The token has been revoked.",
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/tokens.html")]
+struct TokensPage {
+    flash: IncomingFlash,
+    csrf: String,
+    tokens: Vec<TokenRow>,
+    // the checkbox field of every scope
+    scopes: Vec<(&'static str, ApiScope)>,
+}
+
+struct TokenRow {
+    id: Uuid,
+    name: String,
+    scopes: String,
+    created_at: String,
+    last_used_at: String,
+    revoked: bool,
+}
+
+#[derive(Template)]
+#[template(path = "admin/new_token.html")]
+struct NewTokenPage {
+    flash: IncomingFlash,
+    csrf: String,
+    name: String,
+    token: String,
+}
+
+// checkboxes are only submitted when ticked
+#[derive(Debug, Object, Deserialize)]
+pub struct CreateTokenForm {
+    name: String,
+    scope_account_read: Option<String>,
+    scope_newsletters_publish: Option<String>,
+    scope_subscribers_read: Option<String>,
+    scope_subscribers_write: Option<String>,
+}
+
+impl CreateTokenForm {
+    fn scopes(&self) -> Vec<ApiScope> {
+        ApiScope::ALL
+            .into_iter()
+            .filter(|scope| match scope {
+                ApiScope::ReadAccount => self.scope_account_read.is_some(),
+                ApiScope::PublishNewsletters => self.scope_newsletters_publish.is_some(),
+                ApiScope::ReadSubscribers => self.scope_subscribers_read.is_some(),
+                ApiScope::ManageSubscribers => self.scope_subscribers_write.is_some(),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct RevokeTokenForm {
+    // uuid doesn't impl poem_openapi::types::Type
+    id: String,
+}
+
+fn scope_field(scope: ApiScope) -> &'static str {
+    match scope {
+        ApiScope::ReadAccount => "scope_account_read",
+        ApiScope::PublishNewsletters => "scope_newsletters_publish",
+        ApiScope::ReadSubscribers => "scope_subscribers_read",
+        ApiScope::ManageSubscribers => "scope_subscribers_write",
+    }
+}
+
+fn format_time(time: &DateTimeWithTimeZone) -> String {
+    time.format("%Y-%m-%d %H:%M:%S %Z").to_string()
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
@@ -0,0 +1,207 @@
+use anyhow::Context;
+use askama::Template;
+use poem::{
+    session::Session,
+    web::{Data, RemoteAddr},
+};
+use poem_openapi::{
+    payload::{Form, Html},
+    Object, OpenApi, Tags,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::dashboard::get_username;
+use crate::{
+    audit::{record_audit_event, AuditAction},
+    auth::{
+        csrf_token, disable_two_factor, enable_two_factor, generate_totp_secret, get_totp_secret,
+        provisioning_uri, verify_second_factor, verify_totp_code,
+    },
+    context::StateContext,
+    routes::{
+        add_session_uid_check,
+        error::{AppError, ErrorCode},
+        flash::IncomingFlash,
+        templates::render,
+    },
+    session_state::TOTP_ENROLLMENT_SECRET_KEY,
+    utils::client_ip,
+};
+
+type TwoFactorResult<T> = std::result::Result<T, AppError>;
+
+pub struct Api {
+    context: StateContext,
+}
+
+#[derive(Tags)]
+enum MyTags {
+    TwoFactor,
+}
+
+#[OpenApi(prefix_path = "/2fa", tag = "MyTags::TwoFactor")]
+impl Api {
+    #[oai(path = "/", method = "get", transform = "add_session_uid_check")]
+    pub async fn two_factor_page(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        user_id: Data<&Uuid>,
+    ) -> TwoFactorResult<Html<String>> {
+        let db = &self.context.db;
+        let enabled = get_totp_secret(db, *user_id.0)
+            .await
+            .context("fail to get the totp secret")
+            .map_err(AppError::internal)?
+            .is_some();
+        let enrolment = if enabled {
+            None
+        } else {
+            let username = get_username(*user_id.0, db)
+                .await
+                .context("fail to get username from user_id")
+                .map_err(AppError::internal)?
+                .ok_or_else(|| AppError::unauthorized("no username found for id"))?;
+            // keep the same secret across reloads until the enrolment is confirmed
+            let secret = match session.get::<String>(TOTP_ENROLLMENT_SECRET_KEY) {
+                Some(secret) => secret,
+                None => {
+                    let secret = generate_totp_secret();
+                    session.set(TOTP_ENROLLMENT_SECRET_KEY, &secret);
+                    secret
+                }
+            };
+            let uri = provisioning_uri(&secret, &username).map_err(AppError::internal)?;
+            Some(Enrolment { uri, secret })
+        };
+        render(&TwoFactorPage {
+            flash,
+            csrf: csrf_token(session),
+            enrolment,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/enable", method = "post", transform = "add_session_uid_check")]
+    pub async fn enable(
+        &self,
+        form: Form<TwoFactorCodeForm>,
+        session: &Session,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> TwoFactorResult<Html<String>> {
+        let Some(secret) = session.get::<String>(TOTP_ENROLLMENT_SECRET_KEY) else {
+            return Err(AppError::new(
+                ErrorCode::ValidationFailed,
+                "The enrolment has expired, please try again",
+            )
+            .see_other("/admin/2fa"));
+        };
+        let Some(step) = verify_totp_code(&secret, &form.code).map_err(AppError::internal)? else {
+            return Err(AppError::new(
+                ErrorCode::InvalidCode,
+                "The authentication code is invalid",
+            )
+            .see_other("/admin/2fa"));
+        };
+        let recovery_codes = enable_two_factor(
+            &self.context.db,
+            *user_id.0,
+            secret,
+            step,
+            &self.context.password_hash,
+        )
+        .await
+        .map_err(AppError::internal)?;
+        session.remove(TOTP_ENROLLMENT_SECRET_KEY);
+        tracing::info!(user_id = %user_id.0, "two-factor authentication enabled");
+        record_audit_event(
+            &self.context.db,
+            *user_id.0,
+            AuditAction::TwoFactorEnabled,
+            None,
+            &client_ip(remote_addr),
+        )
+        .await;
+        render(&RecoveryCodesPage {
+            flash: IncomingFlash::default(),
+            csrf: csrf_token(session),
+            codes: recovery_codes,
+        })
+        .map(Html)
+    }
+
+    #[oai(
+        path = "/disable",
+        method = "post",
+        transform = "add_session_uid_check"
+    )]
+    pub async fn disable(
+        &self,
+        form: Form<TwoFactorCodeForm>,
+        remote_addr: &RemoteAddr,
+        user_id: Data<&Uuid>,
+    ) -> TwoFactorResult<()> {
+        let db = &self.context.db;
+        if !verify_second_factor(db, *user_id.0, &form.code)
+            .await
+            .map_err(AppError::internal)?
+        {
+            return Err(AppError::new(
+                ErrorCode::InvalidCode,
+                "The authentication code is invalid",
+            )
+            .see_other("/admin/2fa"));
+        }
+        disable_two_factor(db, *user_id.0)
+            .await
+            .map_err(AppError::internal)?;
+        tracing::info!(user_id = %user_id.0, "two-factor authentication disabled");
+        record_audit_event(
+            db,
+            *user_id.0,
+            AuditAction::TwoFactorDisabled,
+            None,
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            "/admin/2fa",
+            "Two-factor authentication has been disabled.",
+        ))
+    }
+}
+
+#[derive(Template)]
+#[template(path = "admin/two_factor.html")]
+struct TwoFactorPage {
+    flash: IncomingFlash,
+    csrf: String,
+    // only while two-factor authentication is disabled
+    enrolment: Option<Enrolment>,
+}
+
+struct Enrolment {
+    uri: String,
+    secret: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/recovery_codes.html")]
+struct RecoveryCodesPage {
+    flash: IncomingFlash,
+    csrf: String,
+    codes: Vec<String>,
+}
+
+#[derive(Debug, Object, Deserialize)]
+pub struct TwoFactorCodeForm {
+    code: String,
+}
+
+impl Api {
+    pub fn new(context: StateContext) -> Self {
+        Self { context }
+    }
+}
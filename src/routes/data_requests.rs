@@ -0,0 +1,175 @@
+use anyhow::Context;
+use askama::Template;
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Form, Html, Json, Query, RemoteAddr},
+};
+use serde::Deserialize;
+use tracing::Instrument;
+
+use super::{
+    error::{AppError, ErrorCode},
+    flash::IncomingFlash,
+    request_id::RequestId,
+    templates::render,
+};
+use crate::{
+    context::StateContext,
+    domain::Email,
+    entities::subscriptions,
+    subscribers::{
+        erase_subscriber, export_subscriber_data, find_by_data_request_token,
+        issue_data_request_token, SubscriberData,
+    },
+    utils::client_ip,
+};
+
+// self-service for data subject requests: a subscriber asks with their address and
+// follows the mailed link to download or erase what is stored about it
+
+type DataRequestResult<T> = std::result::Result<T, AppError>;
+
+#[derive(Debug, Deserialize)]
+pub struct DataRequestForm {
+    email: String,
+}
+
+// answers the same whether the address is subscribed or not, so it can't be used
+// to find out who is; the email is sent in the background so that the answer
+// takes as long either way, and the address and the client ip are throttled
+// so that it can't be used to flood a mailbox either
+#[handler]
+#[tracing::instrument(name = "request a subscriber's data", skip_all)]
+pub async fn request_data(
+    context: Data<&StateContext>,
+    form: Form<DataRequestForm>,
+    request_id: Data<&RequestId>,
+    remote_addr: &RemoteAddr,
+) -> DataRequestResult<StatusCode> {
+    let email =
+        Email::parse(form.0.email).map_err(|e| AppError::new(ErrorCode::ValidationFailed, e))?;
+    let allowed = context
+        .data_request_throttle
+        .allow(email.as_ref(), &client_ip(remote_addr))
+        .await
+        .map_err(AppError::internal)?;
+    if !allowed {
+        return Err(AppError::new(
+            ErrorCode::TooManyAttempts,
+            "Too many data requests, please try again later",
+        ));
+    }
+    let context = context.0.clone();
+    let request_id = request_id.0.clone();
+    tokio::spawn(
+        async move {
+            if let Err(e) = send_data_request_email(&context, &email, &request_id).await {
+                tracing::error!(error = %e, "fail to send the data request email");
+            }
+        }
+        .in_current_span(),
+    );
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn send_data_request_email(
+    context: &StateContext,
+    email: &Email,
+    request_id: &RequestId,
+) -> anyhow::Result<()> {
+    let Some((subscriber, token)) = issue_data_request_token(&context.db, email.as_ref()).await?
+    else {
+        return Ok(());
+    };
+    let data_link = format!("{}/subscriptions/data?token={}", context.base_url, token);
+    let erase_link = format!("{}/subscriptions/erase?token={}", context.base_url, token);
+    let recipient = Email::parse(subscriber.email).map_err(anyhow::Error::msg)?;
+    context
+        .email_client
+        .with_request_id(request_id.as_str())
+        .send_email(
+            &recipient,
+            "your data",
+            &format!(
+                "Download the data we store about you <a href=\"{data_link}\">here</a>, or erase it <a href=\"{erase_link}\">here</a>. The links expire in 24 hours."
+            ),
+            &format!(
+                "Download the data we store about you at {data_link}, or erase it at {erase_link}. The links expire in 24 hours."
+            ),
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TokenQuery {
+    token: String,
+}
+
+#[handler]
+pub async fn get_data(
+    context: Data<&StateContext>,
+    query: Query<TokenQuery>,
+) -> DataRequestResult<Json<SubscriberData>> {
+    let subscriber = subscriber_of(&context, &query.token).await?;
+    let data = export_subscriber_data(&context.db, &subscriber.email)
+        .await
+        .map_err(AppError::internal)?
+        .context("the subscriber has no data despite the token")
+        .map_err(AppError::internal)?;
+    Ok(Json(data))
+}
+
+#[derive(Template)]
+#[template(path = "erase.html")]
+struct ErasePage {
+    flash: IncomingFlash,
+    // `None` once the data is gone
+    token: Option<String>,
+}
+
+// a link only shows the button, mail scanners follow links but don't post forms
+#[handler]
+pub async fn get_erase_page(
+    context: Data<&StateContext>,
+    flash: IncomingFlash,
+    query: Query<TokenQuery>,
+) -> DataRequestResult<Html<String>> {
+    subscriber_of(&context, &query.token).await?;
+    render(&ErasePage {
+        flash,
+        token: Some(query.0.token),
+    })
+    .map(Html)
+}
+
+#[handler]
+#[tracing::instrument(name = "erase a subscriber on request", skip_all)]
+pub async fn post_erase(
+    context: Data<&StateContext>,
+    flash: IncomingFlash,
+    form: Form<TokenQuery>,
+) -> DataRequestResult<Html<String>> {
+    let subscriber = subscriber_of(&context, &form.token).await?;
+    let erased = erase_subscriber(&context.db, &subscriber.email)
+        .await
+        .map_err(AppError::internal)?;
+    tracing::info!(subscribers = ?erased, "erased on the subscriber's request");
+    render(&ErasePage { flash, token: None }).map(Html)
+}
+
+async fn subscriber_of(
+    context: &StateContext,
+    token: &str,
+) -> DataRequestResult<subscriptions::Model> {
+    find_by_data_request_token(&context.db, token)
+        .await
+        .map_err(AppError::internal)?
+        .ok_or_else(|| {
+            AppError::new(
+                ErrorCode::InvalidToken,
+                "the data request link is invalid or has expired",
+            )
+        })
+}
@@ -1,9 +1,20 @@
-use poem::{Error, Response};
-use poem_openapi::Object;
-use reqwest::{header::LOCATION, StatusCode};
+use askama::Template;
+use poem::{
+    error::ResponseError,
+    http::{
+        header::{ACCEPT, LOCATION, SET_COOKIE, WWW_AUTHENTICATE},
+        StatusCode,
+    },
+    Endpoint, Error, IntoResponse, Request, Response,
+};
+use poem_openapi::{
+    registry::{MetaResponse, MetaResponses, Registry},
+    ApiResponse,
+};
 use serde::Serialize;
+use uuid::Uuid;
 
-use crate::session_state::FLASH_KEY;
+use super::flash::{FlashKey, FlashLevel, IncomingFlash};
 
 pub fn see_other_error(uri: &str) -> Error {
     Error::from_response(
@@ -14,113 +25,482 @@ pub fn see_other_error(uri: &str) -> Error {
     )
 }
 
-pub fn see_other_with_cookie(uri: &str, cookie: &str) -> poem_openapi::payload::Response<()> {
+pub fn see_other_with_flash(
+    uri: &str,
+    flash_key: &FlashKey,
+    level: FlashLevel,
+    message: &str,
+) -> Error {
+    Error::from_response(redirect_with_flash(uri, flash_key, level, message))
+}
+
+pub fn see_other_with_cookie(
+    uri: &str,
+    flash_key: &FlashKey,
+    message: &str,
+) -> poem_openapi::payload::Response<()> {
     poem_openapi::payload::Response::new(())
         .status(StatusCode::SEE_OTHER)
         .header(LOCATION, uri)
-        .header(
-            "Set-Cookie",
-            format!("{}={}; Max-Age=1; Secure; HttpOnly", FLASH_KEY, cookie),
-        )
+        .header(SET_COOKIE, flash_key.cookie(FlashLevel::Info, message))
 }
 
-pub fn flash_message(value: &str) -> String {
-    format!("{}={}; Max-Age=1; Secure; HttpOnly", FLASH_KEY, value)
+fn redirect_with_flash(
+    uri: &str,
+    flash_key: &FlashKey,
+    level: FlashLevel,
+    message: &str,
+) -> Response {
+    Response::builder()
+        .status(StatusCode::SEE_OTHER)
+        .header(LOCATION, uri)
+        .header(SET_COOKIE, flash_key.cookie(level, message))
+        .finish()
 }
 
-#[derive(Debug, Serialize, Object)]
-pub struct MyError {
-    message: String,
+// the machine readable `code` of a problem response, clients match on these so
+// an existing code must never be renamed or change its status
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    BadRequest,
+    ValidationFailed,
+    InvalidToken,
+    Unauthorized,
+    InvalidCredentials,
+    InvalidCode,
+    Forbidden,
+    CsrfTokenInvalid,
+    NotFound,
+    Conflict,
+    MethodNotAllowed,
+    TooManyAttempts,
+    Internal,
 }
 
-impl MyError {
-    pub fn new_error<E: std::fmt::Display>(message: E) -> Self {
-        MyError {
-            message: format!("{}", message),
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::ValidationFailed => "validation_failed",
+            ErrorCode::InvalidToken => "invalid_token",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::InvalidCredentials => "invalid_credentials",
+            ErrorCode::InvalidCode => "invalid_code",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::CsrfTokenInvalid => "csrf_token_invalid",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::Conflict => "conflict",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::TooManyAttempts => "too_many_attempts",
+            ErrorCode::Internal => "internal_error",
         }
     }
-}
 
-impl ToString for MyError {
-    fn to_string(&self) -> String {
-        self.message.clone()
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest | ErrorCode::ValidationFailed | ErrorCode::InvalidToken => {
+                StatusCode::BAD_REQUEST
+            }
+            ErrorCode::Unauthorized | ErrorCode::InvalidCredentials | ErrorCode::InvalidCode => {
+                StatusCode::UNAUTHORIZED
+            }
+            ErrorCode::Forbidden | ErrorCode::CsrfTokenInvalid => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::Conflict => StatusCode::CONFLICT,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::Internal => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // for the errors poem itself raises, e.g. an unknown path or a malformed payload
+    fn from_status(status: StatusCode) -> Option<Self> {
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::UNSUPPORTED_MEDIA_TYPE => {
+                Some(ErrorCode::BadRequest)
+            }
+            StatusCode::UNAUTHORIZED => Some(ErrorCode::Unauthorized),
+            StatusCode::FORBIDDEN => Some(ErrorCode::Forbidden),
+            StatusCode::NOT_FOUND => Some(ErrorCode::NotFound),
+            StatusCode::METHOD_NOT_ALLOWED => Some(ErrorCode::MethodNotAllowed),
+            status if status.is_server_error() => Some(ErrorCode::Internal),
+            _ => None,
+        }
     }
 }
 
-#[macro_export]
-macro_rules! generate_error_response {
-    ($enum_name:ident, 303) => {
-        #[derive(Debug, poem_openapi::ApiResponse)]
-        pub enum $enum_name {
-            #[oai(status = 303)]
-            SeeOther(
-                #[oai(header = "LOCATION")] String,
-                #[oai(header = "Set-Cookie")] String,
-            ),
+// the single error type of the handlers, `render_errors` turns it into a problem
+// response, or a redirect with a flash message for browsers
+#[derive(Debug)]
+pub enum AppError {
+    // not a failure: a form post that succeeded sends the browser on with an info
+    // flash, it is returned as an error so that the handlers keep one return type
+    Redirect { location: String, message: String },
+    Failure(Box<Failure>),
+}
+
+#[derive(Debug)]
+pub struct Failure {
+    code: ErrorCode,
+    // shown to every client
+    message: String,
+    // the error chain behind an internal error, only shown with `app.expose_internal_errors`
+    internal: Option<String>,
+    // where html clients are sent back to, with the message as an error flash
+    location: Option<String>,
+    // the `WWW-Authenticate` scheme of an unauthorized response
+    challenge: Option<&'static str>,
+    // logged with the error and returned to the client so that both can be matched up
+    correlation_id: Uuid,
+}
+
+impl AppError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        AppError::Failure(Box::new(Failure {
+            code,
+            message: message.into(),
+            internal: None,
+            location: None,
+            challenge: None,
+            correlation_id: Uuid::new_v4(),
+        }))
+    }
+
+    pub fn bad_request<Err: std::fmt::Display>(err: Err) -> Self {
+        AppError::new(ErrorCode::BadRequest, err.to_string())
+    }
+
+    pub fn unauthorized<Err: std::fmt::Display>(err: Err) -> Self {
+        AppError::new(ErrorCode::Unauthorized, err.to_string())
+    }
+
+    // the cause is logged but never reaches the client unless explicitly configured
+    pub fn internal<Err: std::fmt::Display>(err: Err) -> Self {
+        let internal = format!("{err:#}");
+        let error = AppError::new(ErrorCode::Internal, "internal server error");
+        tracing::error!(
+            error = internal,
+            correlation_id = %error.correlation_id(),
+            "internal server error"
+        );
+        error.with_internal(internal)
+    }
+
+    // redirects with an informational flash message, e.g. after a successful change
+    pub fn see_other_info(location: &str, message: &str) -> Self {
+        AppError::Redirect {
+            location: location.to_owned(),
+            message: message.to_owned(),
         }
+    }
 
-        impl $enum_name {
-            pub fn see_other(location: &str, message: &str) -> Self {
-                $enum_name::SeeOther(location.to_owned(), message.to_owned())
-            }
+    // html clients are redirected to `location` where the message is flashed
+    pub fn see_other(mut self, location: &str) -> Self {
+        if let AppError::Failure(failure) = &mut self {
+            failure.location = Some(location.to_owned());
         }
-    };
-    ($enum_name:ident, $(($status:literal, $name:ident)),*) => {
-        #[derive(Debug, poem_openapi::ApiResponse)]
-        pub enum $enum_name {
-            #[oai(status = 303)]
-            SeeOther(
-                #[oai(header = "LOCATION")] String,
-                #[oai(header = "Set-Cookie")] String,
-            ),
-
-            #[oai(status = 500)]
-            InternalServerError(poem_openapi::payload::Json<$crate::routes::error::MyError>),
-
-            $(
-            #[oai(status = $status)]
-            $name(poem_openapi::payload::Json<$crate::routes::error::MyError>),
-            )*
-        }
-
-        impl $enum_name {
-            paste::paste!{
-                $(
-                pub fn [<$name:snake>]<Err: std::fmt::Display>(
-                    err: Err,
-                ) -> Self {
-                    $enum_name::$name(poem_openapi::payload::Json($crate::routes::error::MyError::new_error(err)))
-                }
-                )*
-            }
+        self
+    }
+
+    pub fn with_challenge(mut self, scheme: &'static str) -> Self {
+        if let AppError::Failure(failure) = &mut self {
+            failure.challenge = Some(scheme);
+        }
+        self
+    }
 
-            pub fn interval_error<Err: std::fmt::Display>(err: Err) -> Self {
-                let error_msg = format!("{}", err);
-                tracing::error!(error = error_msg, "internal server error");
-                $enum_name::InternalServerError(poem_openapi::payload::Json(
-                    $crate::routes::error::MyError::new_error(err),
-                ))
+    fn with_internal(mut self, internal: String) -> Self {
+        if let AppError::Failure(failure) = &mut self {
+            failure.internal = Some(internal);
+        }
+        self
+    }
+
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            AppError::Redirect { .. } => None,
+            AppError::Failure(failure) => Some(failure.code),
+        }
+    }
+
+    pub fn correlation_id(&self) -> Uuid {
+        match self {
+            AppError::Redirect { .. } => Uuid::nil(),
+            AppError::Failure(failure) => failure.correlation_id,
+        }
+    }
+
+    fn render(
+        self,
+        client: Client,
+        instance: Option<&str>,
+        expose_internal: bool,
+        flash_key: &FlashKey,
+    ) -> Response {
+        let failure = match self {
+            AppError::Redirect { location, message } => {
+                return redirect_with_flash(&location, flash_key, FlashLevel::Info, &message)
+            }
+            AppError::Failure(failure) => failure,
+        };
+        match (client, &failure.location) {
+            (Client::Html | Client::Unspecified, Some(location)) => {
+                redirect_with_flash(location, flash_key, FlashLevel::Error, &failure.message)
             }
+            (Client::Html, None) => failure.error_page(),
+            (Client::Api | Client::Unspecified, _) => failure.problem(instance, expose_internal),
+        }
+    }
+}
 
-            pub fn see_other(location: &str, message: &str) -> Self {
-                $enum_name::SeeOther(location.to_owned(), flash_message(message))
+impl Failure {
+    // RFC 7807 problem details, `code` and `correlation_id` are extension members
+    fn problem(&self, instance: Option<&str>, expose_internal: bool) -> Response {
+        let status = self.code.status();
+        let problem = Problem {
+            problem_type: "about:blank",
+            title: status.canonical_reason().unwrap_or_default(),
+            status: status.as_u16(),
+            detail: &self.message,
+            instance,
+            code: self.code.as_str(),
+            correlation_id: self.correlation_id,
+            internal: self.internal.as_deref().filter(|_| expose_internal),
+        };
+        let body = serde_json::to_string(&problem).unwrap_or_else(|_| String::from("{}"));
+        let mut resp = Response::builder()
+            .status(status)
+            .content_type("application/problem+json");
+        if let Some(scheme) = self.challenge {
+            resp = resp.header(WWW_AUTHENTICATE, scheme);
+        }
+        resp.body(body)
+    }
+
+    fn error_page(&self) -> Response {
+        let status = self.code.status();
+        let page = ErrorPage {
+            flash: IncomingFlash::default(),
+            status: status.as_u16(),
+            title: status.canonical_reason().unwrap_or_default(),
+            message: &self.message,
+            correlation_id: self.correlation_id,
+        };
+        match page.render() {
+            Ok(html) => html.with_status(status).into_response(),
+            Err(e) => {
+                tracing::error!(error = %e, "fail to render the error page");
+                self.message.clone().with_status(status).into_response()
             }
         }
+    }
+}
+
+#[derive(Serialize)]
+struct Problem<'a> {
+    #[serde(rename = "type")]
+    problem_type: &'static str,
+    title: &'static str,
+    status: u16,
+    detail: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    instance: Option<&'a str>,
+    code: &'static str,
+    correlation_id: Uuid,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    internal: Option<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "error.html")]
+struct ErrorPage<'a> {
+    flash: IncomingFlash,
+    status: u16,
+    title: &'static str,
+    message: &'a str,
+    correlation_id: Uuid,
+}
 
-        impl std::fmt::Display for $enum_name {
-            fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-                match self {
-                    $enum_name::SeeOther(location, cookie_value) => {
-                        write!(f, "redirect to {} with {}", location, cookie_value)
-                    }
-                    $enum_name::InternalServerError(_) => {
-                        write!(f, "internal server error")
-                    }
-                    _ => write!(f, "{:?}", self),
-                }
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Redirect { location, .. } => write!(f, "redirect to {location}"),
+            AppError::Failure(failure) => {
+                write!(f, "{}: {}", failure.code.as_str(), failure.message)
             }
         }
+    }
+}
+
+impl std::error::Error for AppError {}
+
+// only used when the error escapes `render_errors`
+impl ResponseError for AppError {
+    fn status(&self) -> StatusCode {
+        match self {
+            AppError::Redirect { .. } => StatusCode::SEE_OTHER,
+            AppError::Failure(failure) => failure.code.status(),
+        }
+    }
+
+    fn as_response(&self) -> Response {
+        match self {
+            // only `render_errors` has the key to sign the flash message with
+            AppError::Redirect { location, .. } => Response::builder()
+                .status(StatusCode::SEE_OTHER)
+                .header(LOCATION, location)
+                .finish(),
+            AppError::Failure(failure) => failure.problem(None, false),
+        }
+    }
+}
+
+impl ApiResponse for AppError {
+    fn meta() -> MetaResponses {
+        let responses = [
+            (303, "a redirect with a flash message for html clients"),
+            (400, "the request is invalid"),
+            (401, "the request isn't authenticated"),
+            (403, "the request isn't allowed"),
+            (404, "the resource doesn't exist"),
+            (429, "too many attempts"),
+            (500, "internal server error"),
+        ];
+        MetaResponses {
+            responses: responses
+                .into_iter()
+                .map(|(status, description)| MetaResponse {
+                    description,
+                    status: Some(status),
+                    content: vec![],
+                    headers: vec![],
+                })
+                .collect(),
+        }
+    }
+
+    fn register(_registry: &mut Registry) {}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Client {
+    // a browser, it prefers html
+    Html,
+    // asks for json or problem+json
+    Api,
+    // `*/*` or no `Accept` at all
+    Unspecified,
+}
+
+impl Client {
+    fn of(req: &Request) -> Self {
+        let accept = req
+            .headers()
+            .get(ACCEPT)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default();
+        if accept.contains("text/html") {
+            Client::Html
+        } else if accept.contains("json") {
+            Client::Api
+        } else {
+            Client::Unspecified
+        }
+    }
+}
+
+// renders every error leaving the app according to the `Accept` header: problem
+// json for api clients, a redirect with an error flash or an error page for
+// browsers; clients that don't say are redirected when the error has somewhere
+// to send them and get problem json otherwise
+pub async fn render_errors<E: Endpoint>(
+    next: E,
+    req: Request,
+    expose_internal: bool,
+    flash_key: FlashKey,
+) -> poem::Result<Response> {
+    let client = Client::of(&req);
+    let instance = req.uri().path().to_owned();
+    let err = match next.call(req).await {
+        Ok(resp) => return Ok(resp.into_response()),
+        Err(err) => err,
+    };
+    let err = match err.downcast::<AppError>() {
+        Ok(err) => err,
+        // responses built on purpose, e.g. the redirect to the login page
+        Err(err) if err.is_from_response() => return Ok(err.into_response()),
+        Err(err) => match ErrorCode::from_status(err.status()) {
+            Some(ErrorCode::Internal) => AppError::internal(err),
+            Some(code) => AppError::new(code, err.to_string()),
+            None => return Ok(err.into_response()),
+        },
     };
+    Ok(err.render(client, Some(&instance), expose_internal, &flash_key))
 }
 
-generate_error_response!(BasicError, (400, BadRequest), (401, AuthError));
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key() -> FlashKey {
+        FlashKey::new(&secrecy::Secret::new("test-key".to_owned()), true)
+    }
+
+    async fn body(resp: Response) -> serde_json::Value {
+        serde_json::from_slice(&resp.into_body().into_vec().await.unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn internal_details_are_hidden_unless_exposed() {
+        let error = || AppError::internal(anyhow::anyhow!("connection to 10.0.0.7 refused"));
+
+        let resp = error().render(Client::Api, Some("/admin/me"), false, &key());
+        assert_eq!(resp.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(resp.content_type(), Some("application/problem+json"));
+        let problem = body(resp).await;
+        assert_eq!(problem["code"], "internal_error");
+        assert_eq!(problem["detail"], "internal server error");
+        assert_eq!(problem["instance"], "/admin/me");
+        assert!(problem.get("internal").is_none(), "{problem}");
+        assert!(!problem.to_string().contains("10.0.0.7"), "{problem}");
+
+        let problem = body(error().render(Client::Api, None, true, &key())).await;
+        assert_eq!(problem["internal"], "connection to 10.0.0.7 refused");
+    }
+
+    #[tokio::test]
+    async fn browsers_are_redirected_with_the_message() {
+        let error = || {
+            AppError::new(ErrorCode::InvalidCredentials, "Authentication failed")
+                .see_other("/login")
+        };
+
+        let resp = error().render(Client::Html, None, false, &key());
+        assert_eq!(resp.status(), StatusCode::SEE_OTHER);
+        assert_eq!(resp.headers()[LOCATION], "/login");
+
+        let resp = error().render(Client::Api, None, false, &key());
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+        let problem = body(resp).await;
+        assert_eq!(problem["code"], "invalid_credentials");
+        assert_eq!(problem["detail"], "Authentication failed");
+    }
+
+    #[test]
+    fn codes_keep_their_status() {
+        assert_eq!(
+            ErrorCode::ValidationFailed.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(ErrorCode::InvalidCode.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            ErrorCode::TooManyAttempts.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(
+            ErrorCode::from_status(StatusCode::NOT_FOUND),
+            Some(ErrorCode::NotFound)
+        );
+        assert_eq!(ErrorCode::from_status(StatusCode::CONFLICT), None);
+    }
+}
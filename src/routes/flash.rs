@@ -0,0 +1,175 @@
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use hmac::{Hmac, Mac};
+use poem::{FromRequest, Request, RequestBody};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+
+use crate::{context::StateContext, session_state::FLASH_KEY};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlashLevel {
+    Info,
+    Error,
+}
+
+impl FlashLevel {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FlashLevel::Info => "info",
+            FlashLevel::Error => "error",
+        }
+    }
+
+    fn parse(s: &str) -> Option<Self> {
+        [FlashLevel::Info, FlashLevel::Error]
+            .into_iter()
+            .find(|level| level.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FlashMessage {
+    pub level: FlashLevel,
+    pub message: String,
+}
+
+// signs and checks the flash cookies with `app.hmac_secret`, it is carried by the
+// state context; the cookies are only `Secure` when the session cookie is
+#[derive(Clone)]
+pub struct FlashKey {
+    secret: Secret<String>,
+    secure: bool,
+}
+
+impl FlashKey {
+    pub fn new(secret: &Secret<String>, secure: bool) -> Self {
+        Self {
+            secret: secret.clone(),
+            secure,
+        }
+    }
+
+    // the Set-Cookie value carrying a message to the page the user is redirected to,
+    // the cookie is `level.base64(message).base64(hmac)` so it can't be forged
+    pub fn cookie(&self, level: FlashLevel, message: &str) -> String {
+        let payload = format!("{}.{}", level.as_str(), URL_SAFE_NO_PAD.encode(message));
+        let tag = URL_SAFE_NO_PAD.encode(self.mac(&payload).finalize().into_bytes());
+        let secure = if self.secure { " Secure;" } else { "" };
+        format!("{FLASH_KEY}={payload}.{tag}; Max-Age=1; Path=/;{secure} HttpOnly; SameSite=Lax")
+    }
+
+    fn verify(&self, value: &str) -> Option<FlashMessage> {
+        let (payload, tag) = value.rsplit_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+        self.mac(payload).verify_slice(&tag).ok()?;
+        let (level, message) = payload.split_once('.')?;
+        let message = String::from_utf8(URL_SAFE_NO_PAD.decode(message).ok()?).ok()?;
+        Some(FlashMessage {
+            level: FlashLevel::parse(level)?,
+            message,
+        })
+    }
+
+    fn mac(&self, payload: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(self.secret.expose_secret().as_bytes())
+            .expect("hmac accepts keys of any length");
+        mac.update(payload.as_bytes());
+        mac
+    }
+}
+
+// the verified flash message of the request, cookies that fail the check are ignored
+#[derive(Debug, Default)]
+pub struct IncomingFlash(Option<FlashMessage>);
+
+impl IncomingFlash {
+    pub fn message(&self) -> Option<&FlashMessage> {
+        self.0.as_ref()
+    }
+}
+
+#[poem::async_trait]
+impl<'a> FromRequest<'a> for IncomingFlash {
+    async fn from_request(req: &'a Request, _body: &mut RequestBody) -> poem::Result<Self> {
+        // without the key no cookie can be trusted
+        let Some(context) = req.data::<StateContext>() else {
+            return Ok(Self(None));
+        };
+        let flash = req
+            .cookie()
+            .get(FLASH_KEY)
+            .and_then(|cookie| context.flash_key.verify(cookie.value_str()));
+        Ok(Self(flash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cookie_value(cookie: &str) -> &str {
+        let value = cookie.split(';').next().unwrap();
+        value.strip_prefix("_flash=").unwrap()
+    }
+
+    fn key() -> FlashKey {
+        FlashKey::new(&Secret::new("test-key".to_owned()), true)
+    }
+
+    #[test]
+    fn signed_messages_are_read_back() {
+        let key = key();
+        let cookie = key.cookie(FlashLevel::Error, "wrong password, try again");
+        assert_eq!(
+            key.verify(cookie_value(&cookie)),
+            Some(FlashMessage {
+                level: FlashLevel::Error,
+                message: "wrong password, try again".to_owned(),
+            })
+        );
+    }
+
+    #[test]
+    fn tampered_messages_are_rejected() {
+        let key = key();
+        let cookie = key.cookie(FlashLevel::Info, "hello");
+        let value = cookie_value(&cookie);
+        let (_, rest) = value.split_once('.').unwrap();
+        assert_eq!(key.verify(&format!("error.{rest}")), None);
+        assert_eq!(key.verify("info.PHNjcmlwdD4.AAAA"), None);
+        assert_eq!(key.verify("<script>alert(1)</script>"), None);
+        let other = FlashKey::new(&Secret::new("other-key".to_owned()), true);
+        assert_eq!(other.verify(value), None);
+    }
+
+    #[test]
+    fn the_cookie_is_secure_like_the_session_cookie() {
+        assert!(key().cookie(FlashLevel::Info, "hi").contains("Secure"));
+        let plain = FlashKey::new(&Secret::new("test-key".to_owned()), false);
+        let cookie = plain.cookie(FlashLevel::Info, "hi");
+        assert!(!cookie.contains("Secure"), "{cookie}");
+        assert!(plain.verify(cookie_value(&cookie)).is_some());
+    }
+
+    #[derive(askama::Template)]
+    #[template(path = "home.html")]
+    struct Page {
+        flash: IncomingFlash,
+    }
+
+    #[test]
+    fn messages_are_escaped_by_the_layout() {
+        let page = Page {
+            flash: IncomingFlash(Some(FlashMessage {
+                level: FlashLevel::Info,
+                message: "<b>hi</b>".to_owned(),
+            })),
+        };
+        let html = askama::Template::render(&page).unwrap();
+        assert!(
+            html.contains(r#"<p class="flash flash-info"><i>&lt;b&gt;hi&lt;/b&gt;</i></p>"#),
+            "{}",
+            html
+        );
+    }
+}
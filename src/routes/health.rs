@@ -1,6 +1,157 @@
-use poem::handler;
+use std::{
+    future::Future,
+    time::{Duration, Instant},
+};
 
+use anyhow::Context;
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json},
+    IntoResponse, Response,
+};
+use serde::Serialize;
+
+use crate::{context::StateContext, issue_delivery_worker::WorkerHeartbeat, pending_migrations};
+
+// a check that takes longer counts as down, so that the report comes back before the probe gives up
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+// the worker polls at least every 10 seconds, a send is bounded by the email client timeout
+const WORKER_HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// liveness, the process is up and serving requests
 #[handler]
 pub fn health_check() {
     // () -> 200 OK in poem
 }
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentStatus {
+    Up,
+    Down,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ComponentReport {
+    status: ComponentStatus,
+    latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    detail: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadinessReport {
+    status: ComponentStatus,
+    database: ComponentReport,
+    redis: ComponentReport,
+    migrations: ComponentReport,
+    worker: ComponentReport,
+}
+
+// readiness, 503 as soon as one component is down so that no traffic is routed here
+#[handler]
+pub async fn readiness(context: Data<&StateContext>) -> Response {
+    let (database, redis, migrations) = tokio::join!(
+        check("database", ping_database(&context)),
+        check("redis", ping_redis(&context)),
+        check("migrations", check_migrations(&context)),
+    );
+    let worker = worker_report(&context.worker_heartbeat);
+    let ready = [&database, &redis, &migrations, &worker]
+        .iter()
+        .all(|report| report.status == ComponentStatus::Up);
+    let (status, code) = if ready {
+        (ComponentStatus::Up, StatusCode::OK)
+    } else {
+        (ComponentStatus::Down, StatusCode::SERVICE_UNAVAILABLE)
+    };
+    Json(ReadinessReport {
+        status,
+        database,
+        redis,
+        migrations,
+        worker,
+    })
+    .with_status(code)
+    .into_response()
+}
+
+// the detail is the outermost context of the error, the whole chain only goes to the logs
+async fn check(
+    component: &str,
+    probe: impl Future<Output = anyhow::Result<()>>,
+) -> ComponentReport {
+    let started = Instant::now();
+    let outcome = tokio::time::timeout(CHECK_TIMEOUT, probe).await;
+    let latency_ms = started.elapsed().as_millis() as u64;
+    let detail = match outcome {
+        Ok(Ok(())) => {
+            return ComponentReport {
+                status: ComponentStatus::Up,
+                latency_ms,
+                detail: None,
+            }
+        }
+        Ok(Err(e)) => {
+            tracing::warn!(
+                error = format!("{e:#}"),
+                component,
+                "readiness check failed"
+            );
+            e.to_string()
+        }
+        Err(_) => format!("no answer within {}s", CHECK_TIMEOUT.as_secs()),
+    };
+    ComponentReport {
+        status: ComponentStatus::Down,
+        latency_ms,
+        detail: Some(detail),
+    }
+}
+
+async fn ping_database(context: &StateContext) -> anyhow::Result<()> {
+    sqlx::query("select 1")
+        .execute(context.db.get_postgres_connection_pool())
+        .await
+        .context("the database is unreachable")?;
+    Ok(())
+}
+
+async fn ping_redis(context: &StateContext) -> anyhow::Result<()> {
+    let mut redis = context.redis.clone();
+    redis::cmd("PING")
+        .query_async::<_, String>(&mut redis)
+        .await
+        .context("redis is unreachable")?;
+    Ok(())
+}
+
+async fn check_migrations(context: &StateContext) -> anyhow::Result<()> {
+    let pending = pending_migrations(&context.db)
+        .await
+        .context("the applied migrations cannot be read")?;
+    if !pending.is_empty() {
+        anyhow::bail!("pending migrations: {}", pending.len());
+    }
+    Ok(())
+}
+
+fn worker_report(heartbeat: &WorkerHeartbeat) -> ComponentReport {
+    let (status, detail) = match heartbeat.elapsed() {
+        Some(age) if age <= WORKER_HEARTBEAT_TIMEOUT => (
+            ComponentStatus::Up,
+            format!("last heartbeat {}s ago", age.as_secs()),
+        ),
+        Some(age) => (
+            ComponentStatus::Down,
+            format!("last heartbeat {}s ago", age.as_secs()),
+        ),
+        None => (ComponentStatus::Down, "no heartbeat yet".to_owned()),
+    };
+    ComponentReport {
+        status,
+        latency_ms: 0,
+        detail: Some(detail),
+    }
+}
@@ -0,0 +1,15 @@
+use askama::Template;
+use poem::{handler, web::Html};
+
+use super::{error::AppError, flash::IncomingFlash, templates::render};
+
+#[derive(Template)]
+#[template(path = "home.html")]
+struct HomePage {
+    flash: IncomingFlash,
+}
+
+#[handler]
+pub fn home(flash: IncomingFlash) -> Result<Html<String>, AppError> {
+    render(&HomePage { flash }).map(Html)
+}
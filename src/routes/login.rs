@@ -1,24 +1,44 @@
+use anyhow::Context;
+use askama::Template;
 use poem::{
     http::{header::LOCATION, status::StatusCode},
     session::Session,
+    web::{Data, RemoteAddr},
     Endpoint,
 };
 use poem_openapi::{
+    param::{Header, Query},
     payload::{Form, Html, Response},
     Object, OpenApi, OpenApiService,
 };
 use secrecy::Secret;
-use serde::Deserialize;
-use tracing::instrument;
+use serde::{Deserialize, Serialize};
+use tracing::{instrument, Instrument};
+use uuid::Uuid;
 
-use super::{add_tracing, error::BasicError};
+use super::{
+    add_tracing,
+    error::{AppError, ErrorCode},
+    flash::IncomingFlash,
+    request_id::RequestId,
+    templates::render,
+};
 use crate::{
-    auth::{validate_credentials, Credentials},
+    audit::{record_audit_event, AuditAction},
+    auth::{
+        change_password, consume_password_reset_token, csrf_token, get_totp_secret,
+        is_password_reset_token_valid, issue_password_reset_token, register_session,
+        revoke_other_sessions, rotate_csrf_token, validate_credentials, verify_second_factor,
+        AuthError, Credentials, LoginAttempt, PasswordResetRequest, ThrottleDecision,
+    },
     context::StateContext,
-    session_state::{FLASH_KEY, USER_ID_KEY},
+    domain::NewPassword,
+    metrics::{record_login, LoginStep},
+    session_state::{PENDING_2FA_KEY, SESSION_ID_KEY, USER_ID_KEY},
+    utils::client_ip,
 };
 
-type LoginResult<T> = std::result::Result<T, BasicError>;
+type LoginResult<T> = std::result::Result<T, AppError>;
 
 pub struct Api {
     context: StateContext,
@@ -35,46 +55,21 @@ pub fn get_api_service(
 
 #[OpenApi]
 impl Api {
-    #[instrument(name = "get login page", skip(self, cookiejar))]
+    #[instrument(name = "get login page", skip(self, flash, session))]
     #[oai(path = "/", method = "get", transform = "add_tracing")]
-    async fn get_login(&self, cookiejar: &poem::web::cookie::CookieJar) -> Html<String> {
+    async fn get_login(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> LoginResult<Html<String>> {
         // here the name of the cookie must be the same with that during Setting
         // see https://github.com/poem-web/poem/blob/74e6dd3d2badaca4fea44fb66568d7e37f13e3a5/poem-openapi/tests/operation_param.rs
         // espeically "cookie_rename"
-        let mut error = String::new();
-        if let Some(cookie) = cookiejar.get(FLASH_KEY) {
-            error = format!("<p><i>{}</i></p>", cookie.value_str().to_owned());
-        }
-
-        Html(format!(
-            r#"<!DOCTYPE html>
-<html lang="en">
-<head>
-    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-    <title>Login</title>
-</head>
-<body>
-    {error}
-    <form action="/login" method="post">
-        <label>Username
-            <input
-                type="text"
-                placeholder="Enter Username"
-                name="username"
-            >
-        </label>
-        <label>Password
-            <input
-                type="password"
-                placeholder="Enter Password"
-                name="password"
-            >
-        </label>
-        <button type="submit">Login</button>
-    </form>
-</body>
-</html>"#,
-        ))
+        render(&LoginPage {
+            flash,
+            csrf: csrf_token(session),
+        })
+        .map(Html)
     }
 
     // poem_openapi doesn't support Redirect directly
@@ -85,17 +80,55 @@ impl Api {
         &self,
         form: Form<LoginFrom>,
         session: &Session,
+        remote_addr: &RemoteAddr,
+        #[oai(name = "User-Agent")] user_agent: Header<Option<String>>,
     ) -> LoginResult<Response<()>> {
+        let ip = client_ip(remote_addr);
+        let throttle = &self.context.login_throttle;
+        let attempt = self
+            .enforce_throttle(&form.0.username, &ip, "/login")
+            .await?;
+
         let credentials = Credentials {
             username: form.0.username.clone(),
             password: Secret::new(form.0.password),
         };
-        match validate_credentials(&self.context.db, credentials).await {
+        match validate_credentials(
+            &self.context.db,
+            credentials,
+            &self.context.password_hash,
+            &self.context.dummy_password_hash,
+        )
+        .await
+        {
             Ok(user_id) => {
-                tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+                record_login(LoginStep::Password, true);
+                throttle
+                    .record_success(&attempt)
+                    .await
+                    .map_err(AppError::internal)?;
+                tracing::Span::current().record("user_id", tracing::field::display(&user_id));
                 // to avoid session fixation attacks
                 session.renew();
-                session.set(USER_ID_KEY, user_id);
+                if get_totp_secret(&self.context.db, user_id)
+                    .await
+                    .context("fail to check two-factor authentication")
+                    .map_err(AppError::internal)?
+                    .is_some()
+                {
+                    session.set(
+                        PENDING_2FA_KEY,
+                        PendingSecondFactor {
+                            user_id,
+                            username: form.0.username.clone(),
+                        },
+                    );
+                    return Ok(Response::new(())
+                        .status(StatusCode::SEE_OTHER)
+                        .header(LOCATION, "/login/2fa"));
+                }
+                self.start_session(session, user_id, &ip, user_agent.0.as_deref())
+                    .await?;
                 tracing::info!(
                     username = form.0.username,
                     user_id = user_id.to_string(),
@@ -105,9 +138,311 @@ impl Api {
                     .status(StatusCode::SEE_OTHER)
                     .header(LOCATION, "/admin/dashboard"))
             }
-            Err(e) => Err(BasicError::see_other("/login", &e.to_string())),
+            Err(e @ AuthError::InvalidCredentials(_)) => {
+                record_login(LoginStep::Password, false);
+                throttle
+                    .record_failure(&attempt)
+                    .await
+                    .map_err(AppError::internal)?;
+                Err(AppError::new(ErrorCode::InvalidCredentials, e.to_string()).see_other("/login"))
+            }
+            Err(e @ AuthError::UnexpectedError(_)) => Err(AppError::internal(e)),
+        }
+    }
+
+    #[instrument(name = "get the second factor page", skip(self, flash, session))]
+    #[oai(path = "/2fa", method = "get", transform = "add_tracing")]
+    async fn get_second_factor(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> LoginResult<Html<String>> {
+        if session
+            .get::<PendingSecondFactor>(PENDING_2FA_KEY)
+            .is_none()
+        {
+            return Err(
+                AppError::new(ErrorCode::Unauthorized, "Please log in first").see_other("/login"),
+            );
         }
+        render(&SecondFactorPage {
+            flash,
+            csrf: csrf_token(session),
+        })
+        .map(Html)
     }
+
+    #[oai(path = "/2fa", method = "post", transform = "add_tracing")]
+    async fn post_second_factor(
+        &self,
+        form: Form<SecondFactorForm>,
+        session: &Session,
+        remote_addr: &RemoteAddr,
+        #[oai(name = "User-Agent")] user_agent: Header<Option<String>>,
+    ) -> LoginResult<Response<()>> {
+        let Some(pending) = session.get::<PendingSecondFactor>(PENDING_2FA_KEY) else {
+            return Err(
+                AppError::new(ErrorCode::Unauthorized, "Please log in first").see_other("/login"),
+            );
+        };
+        let ip = client_ip(remote_addr);
+        let throttle = &self.context.login_throttle;
+        let attempt = self
+            .enforce_throttle(&pending.username, &ip, "/login/2fa")
+            .await?;
+
+        let verified = verify_second_factor(&self.context.db, pending.user_id, &form.0.code)
+            .await
+            .map_err(AppError::internal)?;
+        record_login(LoginStep::SecondFactor, verified);
+        if !verified {
+            throttle
+                .record_failure(&attempt)
+                .await
+                .map_err(AppError::internal)?;
+            return Err(AppError::new(
+                ErrorCode::InvalidCode,
+                "The authentication code is invalid",
+            )
+            .see_other("/login/2fa"));
+        }
+        throttle
+            .record_success(&attempt)
+            .await
+            .map_err(AppError::internal)?;
+        session.renew();
+        session.remove(PENDING_2FA_KEY);
+        self.start_session(session, pending.user_id, &ip, user_agent.0.as_deref())
+            .await?;
+        tracing::info!(
+            username = pending.username,
+            user_id = pending.user_id.to_string(),
+            "second factor verified"
+        );
+        Ok(Response::new(())
+            .status(StatusCode::SEE_OTHER)
+            .header(LOCATION, "/admin/dashboard"))
+    }
+
+    #[instrument(name = "get forgot password page", skip(self, flash, session))]
+    #[oai(path = "/forgot", method = "get", transform = "add_tracing")]
+    async fn get_forgot_password(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+    ) -> LoginResult<Html<String>> {
+        render(&ForgotPasswordPage {
+            flash,
+            csrf: csrf_token(session),
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/forgot", method = "post", transform = "add_tracing")]
+    async fn post_forgot_password(
+        &self,
+        form: Form<ForgotPasswordForm>,
+        request_id: Data<&RequestId>,
+        remote_addr: &RemoteAddr,
+    ) -> LoginResult<()> {
+        let username = form.0.username;
+        let allowed = self
+            .context
+            .password_reset_throttle
+            .allow(&username, &client_ip(remote_addr))
+            .await
+            .map_err(AppError::internal)?;
+        if !allowed {
+            return Err(AppError::new(
+                ErrorCode::TooManyAttempts,
+                "Too many password reset requests, please try again later",
+            )
+            .see_other("/login/forgot"));
+        }
+        // the lookup and the delivery happen off the request, an unknown username must
+        // get the same answer as a known one and just as fast
+        let context = self.context.clone();
+        let request_id = request_id.0.clone();
+        tokio::spawn(
+            async move {
+                if let Err(e) = send_password_reset_email(&context, &username, &request_id).await {
+                    tracing::error!(error = %e, "fail to send the password reset email");
+                }
+            }
+            .in_current_span(),
+        );
+        Err(AppError::see_other_info(
+            "/login",
+            "If the account has an email address, a password reset link has been sent to it.",
+        ))
+    }
+
+    #[instrument(name = "get reset password page", skip(self, flash, session, token))]
+    #[oai(path = "/reset", method = "get", transform = "add_tracing")]
+    async fn get_reset_password(
+        &self,
+        flash: IncomingFlash,
+        session: &Session,
+        token: Query<String>,
+    ) -> LoginResult<Html<String>> {
+        if !is_password_reset_token_valid(&self.context.db, &token.0)
+            .await
+            .map_err(AppError::internal)?
+        {
+            return Err(AppError::new(
+                ErrorCode::InvalidToken,
+                "The password reset link is invalid or has expired",
+            )
+            .see_other("/login/forgot"));
+        }
+        render(&ResetPasswordPage {
+            flash,
+            csrf: csrf_token(session),
+            token: token.0,
+        })
+        .map(Html)
+    }
+
+    #[oai(path = "/reset", method = "post", transform = "add_tracing")]
+    async fn post_reset_password(
+        &self,
+        form: Form<ResetPasswordForm>,
+        remote_addr: &RemoteAddr,
+    ) -> LoginResult<()> {
+        let form = form.0;
+        // the token ends up in the redirect location below
+        if !form.token.chars().all(|c| c.is_ascii_alphanumeric()) {
+            return Err(AppError::new(
+                ErrorCode::InvalidToken,
+                "The password reset link is invalid or has expired",
+            )
+            .see_other("/login/forgot"));
+        }
+        let retry_location = format!("/login/reset?token={}", form.token);
+        let new_password = NewPassword::parse(form.new_password.clone()).map_err(|e| {
+            AppError::new(ErrorCode::ValidationFailed, e).see_other(&retry_location)
+        })?;
+        if form.new_password != form.new_password_check {
+            return Err(AppError::new(
+                ErrorCode::ValidationFailed,
+                "You entered two different new passwords - the field values must match",
+            )
+            .see_other(&retry_location));
+        }
+        let Some(user_id) = consume_password_reset_token(&self.context.db, &form.token)
+            .await
+            .map_err(AppError::internal)?
+        else {
+            return Err(AppError::new(
+                ErrorCode::InvalidToken,
+                "The password reset link is invalid or has expired",
+            )
+            .see_other("/login/forgot"));
+        };
+        change_password(
+            user_id,
+            new_password,
+            &self.context.db,
+            &self.context.password_hash,
+        )
+        .await
+        .map_err(AppError::internal)?;
+        revoke_other_sessions(&self.context.redis, user_id, None)
+            .await
+            .map_err(AppError::internal)?;
+        tracing::info!(user_id = %user_id, "password reset");
+        record_audit_event(
+            &self.context.db,
+            user_id,
+            AuditAction::PasswordReset,
+            None,
+            &client_ip(remote_addr),
+        )
+        .await;
+        Err(AppError::see_other_info(
+            "/login",
+            "Your password has been reset, please log in.",
+        ))
+    }
+}
+
+async fn send_password_reset_email(
+    context: &StateContext,
+    username: &str,
+    request_id: &RequestId,
+) -> anyhow::Result<()> {
+    let Some(PasswordResetRequest { recipient, token }) =
+        issue_password_reset_token(&context.db, username).await?
+    else {
+        return Ok(());
+    };
+    let reset_link = format!("{}/login/reset?token={}", context.base_url, token);
+    context
+        .email_client
+        .with_request_id(request_id.as_str())
+        .send_email(
+            &recipient,
+            "reset your password",
+            &format!(
+                "Click <a href=\"{reset_link}\">here</a> to reset your password, the link expires in 30 minutes."
+            ),
+            &format!("Visit {reset_link} to reset your password, the link expires in 30 minutes."),
+        )
+        .await?;
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "login/login.html")]
+struct LoginPage {
+    flash: IncomingFlash,
+    csrf: String,
+}
+
+#[derive(Template)]
+#[template(path = "login/two_factor.html")]
+struct SecondFactorPage {
+    flash: IncomingFlash,
+    csrf: String,
+}
+
+#[derive(Template)]
+#[template(path = "login/forgot.html")]
+struct ForgotPasswordPage {
+    flash: IncomingFlash,
+    csrf: String,
+}
+
+#[derive(Template)]
+#[template(path = "login/reset.html")]
+struct ResetPasswordPage {
+    flash: IncomingFlash,
+    csrf: String,
+    token: String,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct ForgotPasswordForm {
+    username: String,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct ResetPasswordForm {
+    token: String,
+    new_password: String,
+    new_password_check: String,
+}
+
+#[derive(Debug, Deserialize, Object)]
+pub struct SecondFactorForm {
+    // either a totp code or a recovery code
+    code: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PendingSecondFactor {
+    user_id: Uuid,
+    username: String,
 }
 
 #[derive(Debug, Deserialize, Object)]
@@ -121,4 +456,62 @@ impl Api {
     fn new(context: StateContext) -> Self {
         Api { context }
     }
+
+    async fn start_session(
+        &self,
+        session: &Session,
+        user_id: Uuid,
+        ip: &str,
+        user_agent: Option<&str>,
+    ) -> LoginResult<()> {
+        let session_id = register_session(
+            &self.context.redis,
+            user_id,
+            ip,
+            user_agent.unwrap_or("unknown"),
+            &self.context.session,
+        )
+        .await
+        .map_err(AppError::internal)?;
+        session.set(USER_ID_KEY, user_id);
+        session.set(SESSION_ID_KEY, session_id);
+        rotate_csrf_token(session);
+        record_audit_event(&self.context.db, user_id, AuditAction::Login, None, ip).await;
+        Ok(())
+    }
+
+    async fn enforce_throttle(
+        &self,
+        username: &str,
+        ip: &str,
+        location: &str,
+    ) -> LoginResult<LoginAttempt> {
+        match self
+            .context
+            .login_throttle
+            .check(username, ip)
+            .await
+            .map_err(AppError::internal)?
+        {
+            ThrottleDecision::Locked { retry_after } => {
+                tracing::warn!(
+                    username,
+                    ip,
+                    retry_after = retry_after.as_secs(),
+                    "login rejected due to a lockout"
+                );
+                Err(AppError::new(
+                    ErrorCode::TooManyAttempts,
+                    "Too many failed login attempts, please try again later",
+                )
+                .see_other(location))
+            }
+            ThrottleDecision::Allowed { delay, attempt } => {
+                if !delay.is_zero() {
+                    tokio::time::sleep(delay).await;
+                }
+                Ok(attempt)
+            }
+        }
+    }
 }
@@ -0,0 +1,94 @@
+use std::{collections::HashSet, sync::Arc, time::Instant};
+
+use anyhow::Context;
+use poem::{
+    error::NotFoundError, get, handler, web::Data, Endpoint, EndpointExt, IntoResponse, Request,
+    Response, Route,
+};
+use poem_openapi::{OpenApi, OpenApiService};
+
+use super::error::AppError;
+use crate::{
+    context::StateContext,
+    metrics::{install_recorder, record_db_pool, record_http_request, record_queue_depth},
+};
+
+const UNMATCHED: &str = "unmatched";
+
+// what a nested api answered to, the path of one of its operations or `UNMATCHED`
+struct RouteLabel(String);
+
+// the paths of the operations of an api nested at `prefix`, e.g. `/admin/subscribers/confirm`
+pub fn nested_routes<T: OpenApi, W>(_: &OpenApiService<T, W>, prefix: &str) -> HashSet<String> {
+    T::meta()
+        .into_iter()
+        .flat_map(|api| api.paths)
+        .map(|path| match path.path {
+            "/" => prefix.to_owned(),
+            path => format!("{prefix}{path}"),
+        })
+        .collect()
+}
+
+// wraps a nested api with its middlewares, which answer any path under the prefix
+pub async fn label_nested_route<E: Endpoint>(
+    next: E,
+    req: Request,
+    routes: Arc<HashSet<String>>,
+) -> poem::Result<Response> {
+    let path = req.original_uri().path();
+    let label = match routes.contains(path) {
+        true => path.to_owned(),
+        false => UNMATCHED.to_owned(),
+    };
+    match next.call(req).await {
+        Ok(resp) => {
+            let mut resp = resp.into_response();
+            resp.set_data(RouteLabel(label));
+            Ok(resp)
+        }
+        Err(mut err) => {
+            err.set_data(RouteLabel(label));
+            Err(err)
+        }
+    }
+}
+
+// counts and times every request by the route it matched; there are no path parameters,
+// so outside the nested apis a path that got past the router is a route
+pub async fn track_http_metrics<E: Endpoint>(next: E, req: Request) -> poem::Result<Response> {
+    let method = req.method().to_string();
+    let path = req.uri().path().to_owned();
+    let started = Instant::now();
+    let result = next.call(req).await.map(IntoResponse::into_response);
+    let route = |label: Option<&RouteLabel>| label.map_or(path.clone(), |label| label.0.clone());
+    let (status, route) = match &result {
+        Ok(resp) => (resp.status(), route(resp.data())),
+        // unknown paths share one label so that scanners cannot blow up the number of series
+        Err(err) if err.is::<NotFoundError>() => (err.status(), UNMATCHED.to_owned()),
+        Err(err) => (err.status(), route(err.data())),
+    };
+    record_http_request(&method, route, status.as_u16(), started.elapsed());
+    result
+}
+
+#[handler]
+pub async fn get_metrics(context: Data<&StateContext>) -> Result<Response, AppError> {
+    let depth = sqlx::query!(r#"select count(*) as "depth!" from issue_delivery_queue"#)
+        .fetch_one(context.db.get_postgres_connection_pool())
+        .await
+        .context("fail to count the queued deliveries")
+        .map_err(AppError::internal)?
+        .depth;
+    record_queue_depth(depth);
+    record_db_pool(&context.db);
+    Ok(install_recorder()
+        .render()
+        .with_content_type("text/plain; version=0.0.4")
+        .into_response())
+}
+
+// served on `metrics.port` so that the public port does not expose it
+pub fn metrics_route(context: StateContext) -> impl Endpoint {
+    Route::new().at("/metrics", get(get_metrics)).data(context)
+}
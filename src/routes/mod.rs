@@ -1,37 +1,170 @@
+use std::sync::Arc;
+
 use poem::{
+    endpoint::BoxEndpoint,
     get,
-    middleware::{AddData, Tracing},
-    post, Endpoint, EndpointExt, Route,
+    http::{
+        header::{CONTENT_LENGTH, TRANSFER_ENCODING},
+        Method,
+    },
+    middleware::{AddData, SizeLimit, Tracing},
+    post, Endpoint, EndpointExt, IntoEndpoint, Middleware, Request, Route,
 };
 
 use self::{
     admin::{
+        api::{
+            confirm_subscriber_json, delete_subscriber_json, erase_subscriber_json,
+            export_subscriber_data_json, export_subscribers_csv, get_me, import_subscribers_json,
+            list_subscribers_json, publish_newsletter_json, resend_confirmation_json,
+            unsubscribe_subscriber_json,
+        },
         logout::post_logout,
         newsletters::{get_newsletter_submit_form, publish_newsletter},
     },
-    health::health_check,
+    data_requests::{get_data, get_erase_page, post_erase, request_data},
+    error::render_errors,
+    health::{health_check, readiness},
+    metrics::{get_metrics, label_nested_route, nested_routes, track_http_metrics},
+    request_id::propagate_request_id,
+    webhooks::email_webhook,
+};
+use crate::{
+    auth::{
+        reject_anoynmous_user, require_api_token, require_metrics_token, require_webhook_auth,
+        verify_csrf_token, ApiScope,
+    },
+    configuration::Configuration,
+    context::StateContext,
+    metrics::install_recorder,
+    subscribers::MAX_IMPORT_BYTES,
 };
-use crate::{auth::reject_anoynmous_user, configuration::Configuration, context::StateContext};
 
 mod admin;
+mod data_requests;
 pub mod error;
+pub mod flash;
 pub mod health;
 mod home;
 mod login;
+pub mod metrics;
+pub mod request_id;
 pub mod subscriptions;
+mod templates;
+mod webhooks;
+
+// the largest form the csrf check reads, a newsletter issue is the biggest one
+const MAX_FORM_BYTES: usize = 1024 * 1024;
 
-pub async fn default_route(conf: Configuration, context: StateContext) -> Route {
+pub async fn default_route(conf: Configuration, context: StateContext) -> BoxEndpoint<'static> {
     let mut route = Route::new()
         .at("/api/v1/health_check", get(health_check))
-        .at("/logout", post(post_logout).around(reject_anoynmous_user))
+        .at("/health/live", get(health_check))
+        .at("/health/ready", get(readiness))
+        .at(
+            "/logout",
+            post(post_logout)
+                .around(reject_anoynmous_user)
+                .around(verify_csrf_token)
+                .around(|ep, req| limit_body(ep, req, MAX_FORM_BYTES)),
+        )
         .at(
             "/admin/newsletters",
             post(publish_newsletter)
                 .get(get_newsletter_submit_form)
                 .around(reject_anoynmous_user)
+                .around(verify_csrf_token)
+                .around(|ep, req| limit_body(ep, req, MAX_FORM_BYTES))
                 .with(Tracing)
                 .with(AddData::new(context.clone())),
         )
+        .at(
+            "/api/v1/admin/me",
+            get(get_me)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ReadAccount))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/newsletters",
+            post(publish_newsletter_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::PublishNewsletters))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers",
+            get(list_subscribers_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ReadSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/confirm",
+            post(confirm_subscriber_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/unsubscribe",
+            post(unsubscribe_subscriber_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/delete",
+            post(delete_subscriber_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/resend",
+            post(resend_confirmation_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/import",
+            post(import_subscribers_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(SizeLimit::new(MAX_IMPORT_BYTES))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/export",
+            get(export_subscribers_csv)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ReadSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/data",
+            post(export_subscriber_data_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ReadSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/api/v1/admin/subscribers/erase",
+            post(erase_subscriber_json)
+                .around(|ep, req| require_api_token(ep, req, ApiScope::ManageSubscribers))
+                .with(Tracing),
+        )
+        .at(
+            "/subscriptions/data",
+            post(request_data).get(get_data).with(Tracing),
+        )
+        .at(
+            "/subscriptions/erase",
+            post(post_erase).get(get_erase_page).with(Tracing),
+        )
+        .at(
+            "/admin/subscribers/export",
+            get(export_subscribers_csv)
+                .around(reject_anoynmous_user)
+                .with(Tracing),
+        )
+        .at(
+            "/webhooks/email",
+            post(email_webhook)
+                .around(require_webhook_auth)
+                .with(Tracing),
+        )
         .at("/", get(home::home));
 
     let server_url = format!("http://127.0.0.1:{}", conf.app.port);
@@ -39,18 +172,86 @@ pub async fn default_route(conf: Configuration, context: StateContext) -> Route
     // load subscriptions routing
     let (subscriptions_service, ui) =
         subscriptions::get_api_service(context.clone(), &format!("{server_url}/subscriptions"));
+    let routes = Arc::new(nested_routes(&subscriptions_service, "/subscriptions"));
     route = route
-        .nest("/subscriptions", subscriptions_service)
+        .nest(
+            "/subscriptions",
+            subscriptions_service
+                .around(move |ep, req| label_nested_route(ep, req, routes.clone())),
+        )
         .nest("/subscriptions/docs", ui);
 
     let (login_service, ui) =
         login::get_api_service(context.clone(), &format!("{server_url}/login"));
-    route = route.nest("/login", login_service).nest("/login/docs", ui);
+    // every html form posts to /login, /logout or /admin, all of them check the csrf token
+    let routes = Arc::new(nested_routes(&login_service, "/login"));
+    route = route
+        .nest(
+            "/login",
+            login_service
+                .around(verify_csrf_token)
+                .around(|ep, req| limit_body(ep, req, MAX_FORM_BYTES))
+                .around(move |ep, req| label_nested_route(ep, req, routes.clone())),
+        )
+        .nest("/login/docs", ui);
 
-    let (admin_service, ui) = admin::get_api_service(context, &format!("{server_url}/admin"));
-    route = route.nest("/admin", admin_service).nest("/admin/docs", ui);
+    let (admin_service, ui) =
+        admin::get_api_service(context.clone(), &format!("{server_url}/admin"));
+    let routes = Arc::new(nested_routes(&admin_service, "/admin"));
+    route = route
+        .nest(
+            "/admin",
+            admin_service
+                .into_endpoint()
+                .around(verify_csrf_token)
+                // the subscriber import is uploaded as a form too
+                .around(|ep, req| limit_body(ep, req, MAX_IMPORT_BYTES))
+                .around(move |ep, req| label_nested_route(ep, req, routes.clone())),
+        )
+        .nest("/admin/docs", ui);
+
+    if conf.metrics.enabled {
+        install_recorder();
+        // otherwise it is served on its own port by `metrics_route`
+        if conf.metrics.port.is_none() {
+            let token = conf.metrics.token.clone();
+            route = route.at(
+                "/metrics",
+                get(get_metrics)
+                    .around(move |ep, req| require_metrics_token(ep, req, token.clone())),
+            );
+        }
+    }
 
+    // reject_anoynmous_user needs redis to check whether a session has been invalidated
+    let expose_internal = conf.app.expose_internal_errors;
+    let flash_key = context.flash_key.clone();
     route
+        .data(context)
+        .around(track_http_metrics)
+        .around(move |ep, req| render_errors(ep, req, expose_internal, flash_key.clone()))
+        .around(propagate_request_id)
+        .boxed()
+}
+
+// a body is refused before it is read when its declared length is over
+// `max_size`, or when it is chunked; the requests without a body go through
+async fn limit_body<E: Endpoint>(
+    next: E,
+    req: Request,
+    max_size: usize,
+) -> poem::Result<E::Output> {
+    let has_body =
+        req.headers().contains_key(CONTENT_LENGTH) || req.headers().contains_key(TRANSFER_ENCODING);
+    if !has_body
+        || matches!(
+            *req.method(),
+            Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+        )
+    {
+        return next.call(req).await;
+    }
+    SizeLimit::new(max_size).transform(next).call(req).await
 }
 
 fn add_tracing(ep: impl Endpoint) -> impl Endpoint {
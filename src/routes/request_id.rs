@@ -0,0 +1,81 @@
+use std::fmt::Display;
+
+use poem::{http::HeaderValue, Endpoint, IntoResponse, Request, Response};
+use tracing::Instrument;
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// the longest id accepted from a client, anything else is replaced with a fresh one
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+// ties the logs of a request to the work it causes later on, e.g. the issue
+// deliveries of a publish or the confirmation email of a subscribe
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    pub fn generate() -> Self {
+        RequestId(Uuid::new_v4().to_string())
+    }
+
+    // a client supplied id is only reused when it is safe to log and to echo back
+    pub fn parse(value: &str) -> Option<Self> {
+        let valid = !value.is_empty()
+            && value.len() <= MAX_REQUEST_ID_LEN
+            && value.chars().all(|c| c.is_ascii_graphic());
+        valid.then(|| RequestId(value.to_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for RequestId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// accepts the `X-Request-Id` of the client or generates one, every span of the
+// request is nested under one carrying it and the response echoes it back
+pub async fn propagate_request_id<E: Endpoint>(
+    next: E,
+    mut req: Request,
+) -> poem::Result<Response> {
+    let request_id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(RequestId::parse)
+        .unwrap_or_else(RequestId::generate);
+    req.extensions_mut().insert(request_id.clone());
+
+    let span = tracing::info_span!("http", request_id = %request_id);
+    let mut resp = match next.call(req).instrument(span).await {
+        Ok(resp) => resp.into_response(),
+        Err(err) => err.into_response(),
+    };
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        resp.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_printable_ids_of_a_sane_length_are_reused() {
+        assert_eq!(
+            RequestId::parse("ci-run-42").map(|id| id.to_string()),
+            Some("ci-run-42".to_owned())
+        );
+        assert_eq!(RequestId::parse(""), None);
+        assert_eq!(RequestId::parse("two words"), None);
+        assert_eq!(RequestId::parse("line\nbreak"), None);
+        assert_eq!(RequestId::parse(&"a".repeat(MAX_REQUEST_ID_LEN + 1)), None);
+    }
+}
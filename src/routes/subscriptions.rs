@@ -1,7 +1,7 @@
 use std::convert::TryFrom;
 
 use anyhow::Context;
-use poem::Endpoint;
+use poem::{web::Data, Endpoint};
 use poem_openapi::{
     param::Query,
     payload::{Form, Json},
@@ -12,14 +12,23 @@ use sea_orm::*;
 use serde::Deserialize;
 use uuid::Uuid;
 
-use super::{add_tracing, error::BasicError};
+use super::{
+    add_tracing,
+    error::{AppError, ErrorCode},
+    request_id::RequestId,
+};
 use crate::{
     context::StateContext,
     domain::{Email, UserName},
-    entities::{prelude::*, subscription_tokens, subscriptions},
+    entities::{
+        prelude::*, sea_orm_active_enums::SubscriptionStatus, subscription_tokens, subscriptions,
+    },
+    metrics::{record_subscription_confirmed, record_subscription_created},
+    subscribers::lift_erasure,
+    suppressions::find_suppression,
 };
 
-type SubscriptionResult<T> = std::result::Result<T, BasicError>;
+type SubscriptionResult<T> = std::result::Result<T, AppError>;
 
 pub struct Api {
     context: StateContext,
@@ -51,7 +60,7 @@ impl Api {
             id: ActiveValue::Set(Uuid::new_v4()),
             name: ActiveValue::Set(new_subscriber.username.inner()),
             email: ActiveValue::Set(new_subscriber.email.inner()),
-            status: ActiveValue::Set(ConfirmStatus::Pending.to_string()),
+            status: ActiveValue::Set(SubscriptionStatus::Pending),
             ..Default::default()
         };
         let res = Subscriptions::insert(new_subscription).exec(conn).await?;
@@ -73,27 +82,63 @@ impl Api {
             .await?;
         Ok(())
     }
+}
 
-    #[tracing::instrument(skip(self))]
-    async fn send_subscription_email(
-        &self,
-        recipient: Email,
-        token: &str,
-    ) -> Result<reqwest::StatusCode, reqwest::Error> {
-        let confirm_link = format!(
-            "{}/subscriptions/confirm?token={}",
-            self.context.base_url, token
+// `false` when the address is suppressed and nothing was sent
+#[tracing::instrument(skip(context, token))]
+async fn send_subscription_email(
+    context: &StateContext,
+    recipient: &Email,
+    token: &str,
+    request_id: &RequestId,
+) -> anyhow::Result<bool> {
+    if let Some(suppression) = find_suppression(&context.db, recipient.as_ref()).await? {
+        tracing::info!(
+            suppressed = suppression.address,
+            "the address is suppressed, no confirmation email is sent"
         );
-        self.context
-            .email_client
-            .send_email(
-                &recipient,
-                "welcome new subscriber",
-                &format!("<a href=\"{confirm_link}\">here</a>"),
-                &format!("{confirm_link}"),
-            )
-            .await
+        return Ok(false);
     }
+    let confirm_link = format!("{}/subscriptions/confirm?token={}", context.base_url, token);
+    context
+        .email_client
+        .with_request_id(request_id.as_str())
+        .send_email(
+            recipient,
+            "welcome new subscriber",
+            &format!("<a href=\"{confirm_link}\">here</a>"),
+            &confirm_link,
+        )
+        .await?;
+    Ok(true)
+}
+
+// a fresh link for a subscriber who lost the first one, which stops working;
+// `false` when the address is suppressed
+pub(crate) async fn resend_subscription_email(
+    context: &StateContext,
+    subscriber: &subscriptions::Model,
+    request_id: &RequestId,
+) -> anyhow::Result<bool> {
+    let recipient = Email::parse(subscriber.email.clone()).map_err(anyhow::Error::msg)?;
+    let subscription_token = generate_subscription_token();
+    let new_subscription_token = subscription_tokens::ActiveModel {
+        subscriber_id: ActiveValue::Set(subscriber.id),
+        subscription_token: ActiveValue::Set(subscription_token.clone()),
+    };
+    // a subscriber has a single token
+    SubscriptionTokens::insert(new_subscription_token)
+        .on_conflict(
+            sea_query::OnConflict::column(subscription_tokens::Column::SubscriberId)
+                .update_column(subscription_tokens::Column::SubscriptionToken)
+                .to_owned(),
+        )
+        .exec(&context.db)
+        .await
+        .context("fail to replace the subscription_token")?;
+    send_subscription_email(context, &recipient, &subscription_token, request_id)
+        .await
+        .context("fail to send subscription email")
 }
 
 #[OpenApi]
@@ -101,7 +146,7 @@ impl Api {
     // make a subscription
     #[oai(path = "/", method = "post", transform = "add_tracing")]
     #[tracing::instrument(
-        skip(self, form),
+        skip(self, form, request_id),
         name = "new subscription",
         fields(
             email=%form.0.email,
@@ -111,8 +156,10 @@ impl Api {
     async fn subscribe(
         &self,
         form: Form<SubscribeFormData>,
+        request_id: Data<&RequestId>,
     ) -> SubscriptionResult<Json<CreateSuccess>> {
-        let new_subscriber = NewSubscriber::try_from(form).map_err(BasicError::bad_request)?;
+        let new_subscriber = NewSubscriber::try_from(form)
+            .map_err(|e| AppError::new(ErrorCode::ValidationFailed, e))?;
         let recipient = new_subscriber.email.clone();
 
         let txn = self
@@ -121,21 +168,23 @@ impl Api {
             .begin()
             .await
             .context("fail to init db transaction")
-            .map_err(BasicError::interval_error)?;
+            .map_err(AppError::internal)?;
         let last_insert_id = Api::insert_subscriber(&txn, new_subscriber)
             .await
             .context("fail to insert a new subscriber")
-            .map_err(BasicError::interval_error)?;
+            .map_err(AppError::internal)?;
         let subscription_token = generate_subscription_token();
         Api::store_subscription_token(&txn, last_insert_id, subscription_token.clone())
             .await
             .context("fail to store new subscription_token")
-            .map_err(BasicError::interval_error)?;
-        self.send_subscription_email(recipient, &subscription_token)
+            .map_err(AppError::internal)?;
+        // the answer is the same for a suppressed address, it can't be probed
+        send_subscription_email(&self.context, &recipient, &subscription_token, &request_id)
             .await
             .context("fail to send subscription email")
-            .map_err(BasicError::interval_error)?;
-        txn.commit().await.map_err(BasicError::interval_error)?;
+            .map_err(AppError::internal)?;
+        txn.commit().await.map_err(AppError::internal)?;
+        record_subscription_created();
 
         Ok(Json(CreateSuccess {
             id: last_insert_id.to_string(),
@@ -151,10 +200,12 @@ impl Api {
             .one(&self.context.db)
             .await
             .context("fail to find the subscriber with the specified token")
-            .map_err(BasicError::interval_error)?;
+            .map_err(AppError::internal)?;
         if subscriber_status.is_none() {
-            let msg = format!("token {} not found in db", token.0);
-            return Err(BasicError::bad_request(msg));
+            return Err(AppError::new(
+                ErrorCode::InvalidToken,
+                "the confirmation token is unknown",
+            ));
         }
         let subscriber_status = subscriber_status.unwrap();
         let subscriber_id = subscriber_status.subscriber_id;
@@ -162,16 +213,33 @@ impl Api {
             .one(&self.context.db)
             .await
             .context("fail to find subscriber with id")
-            .map_err(BasicError::interval_error)?;
+            .map_err(AppError::internal)?;
         match subscriber {
+            // an old link must not bring back an address that bounced or left
+            Some(subscriber) if subscriber.status != SubscriptionStatus::Pending => Ok(()),
             Some(subscriber) => {
+                let txn = self
+                    .context
+                    .db
+                    .begin()
+                    .await
+                    .context("fail to init db transaction")
+                    .map_err(AppError::internal)?;
+                // confirming is a fresh consent, an erased address may be imported again.
+                // subscribing alone doesn't lift it, anyone can post any address
+                lift_erasure(&txn, &subscriber.email)
+                    .await
+                    .context("fail to lift the erasure of the address")
+                    .map_err(AppError::internal)?;
                 let mut subscriber: subscriptions::ActiveModel = subscriber.into();
-                subscriber.status = Set(ConfirmStatus::Confirmed.to_string());
+                subscriber.status = Set(SubscriptionStatus::Confirmed);
                 subscriber
-                    .update(&self.context.db)
+                    .update(&txn)
                     .await
                     .context("fail to update the subscriber status")
-                    .map_err(BasicError::interval_error)?;
+                    .map_err(AppError::internal)?;
+                txn.commit().await.map_err(AppError::internal)?;
+                record_subscription_confirmed();
                 Ok(())
             }
             None => {
@@ -179,7 +247,7 @@ impl Api {
                     "fail to find subscriber despite foreign key contraint: {}",
                     subscriber_id
                 );
-                Err(BasicError::interval_error(msg))
+                Err(AppError::internal(msg))
             }
         }
     }
@@ -212,20 +280,6 @@ struct CreateSuccess {
     id: String,
 }
 
-pub enum ConfirmStatus {
-    Pending,
-    Confirmed,
-}
-
-impl ToString for ConfirmStatus {
-    fn to_string(&self) -> String {
-        match self {
-            ConfirmStatus::Pending => "pending_confirmed".to_owned(),
-            ConfirmStatus::Confirmed => "confirmed".to_owned(),
-        }
-    }
-}
-
 fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
@@ -0,0 +1,10 @@
+use askama::Template;
+
+use super::error::AppError;
+
+// the pages live in `templates/`, every one of them extends `base.html` which
+// holds the navigation bar and the flash message area, admin pages extend
+// `admin/layout.html` instead; values are html escaped unless marked `|safe`
+pub fn render<T: Template>(page: &T) -> Result<String, AppError> {
+    page.render().map_err(AppError::internal)
+}
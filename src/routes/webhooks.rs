@@ -0,0 +1,124 @@
+use poem::{
+    handler,
+    http::StatusCode,
+    web::{Data, Json},
+};
+use serde::Deserialize;
+
+use super::error::AppError;
+use crate::{
+    context::StateContext,
+    entities::sea_orm_active_enums::SubscriptionStatus,
+    metrics::record_email_event,
+    subscribers::mark_undeliverable,
+    suppressions::{add_suppression, SuppressionSource},
+};
+
+// the webhook payloads of postmark, only the fields we act on
+// https://postmarkapp.com/developer/webhooks/webhooks-overview
+#[derive(Debug, Deserialize)]
+#[serde(tag = "RecordType")]
+pub enum PostmarkEvent {
+    Bounce {
+        // HardBounce, SoftBounce, Transient, ...
+        #[serde(rename = "Type")]
+        kind: String,
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    SpamComplaint {
+        #[serde(rename = "Email")]
+        email: String,
+    },
+    Delivery {
+        #[serde(rename = "Recipient")]
+        recipient: String,
+    },
+    // opens, clicks, ... which we don't subscribe to
+    #[serde(other)]
+    Other,
+}
+
+// answers 200 to every event it could parse, postmark retries anything else
+#[handler]
+#[tracing::instrument(name = "email webhook", skip_all)]
+pub async fn email_webhook(
+    context: Data<&StateContext>,
+    event: Json<PostmarkEvent>,
+) -> Result<StatusCode, AppError> {
+    // the reason is postmark's name for the event
+    let (email, status, reason) = match event.0 {
+        PostmarkEvent::Bounce { kind, email } => match bounce_status(&kind) {
+            Some(status) => (email, status, kind),
+            None => {
+                record_email_event("soft_bounce");
+                tracing::info!(kind, "a temporary bounce, the address is kept");
+                return Ok(StatusCode::OK);
+            }
+        },
+        PostmarkEvent::SpamComplaint { email } => (
+            email,
+            SubscriptionStatus::Complained,
+            "SpamComplaint".to_owned(),
+        ),
+        PostmarkEvent::Delivery { recipient } => {
+            record_email_event("delivery");
+            tracing::debug!(recipient, "delivered");
+            return Ok(StatusCode::OK);
+        }
+        PostmarkEvent::Other => {
+            record_email_event("other");
+            return Ok(StatusCode::OK);
+        }
+    };
+    let source = match status {
+        SubscriptionStatus::Complained => SuppressionSource::Complaint,
+        _ => SuppressionSource::Bounce,
+    };
+    record_email_event(match source {
+        SuppressionSource::Complaint => "complaint",
+        _ => "hard_bounce",
+    });
+    let suppressed = mark_undeliverable(&context.db, &email, status)
+        .await
+        .map_err(AppError::internal)?;
+    // the address stays suppressed even if it subscribes again
+    add_suppression(&context.db, &email, &reason, source)
+        .await
+        .map_err(AppError::internal)?;
+    tracing::info!(?status, subscribers = ?suppressed, "the address is suppressed");
+    Ok(StatusCode::OK)
+}
+
+// `None` for the bounces after which the address may still accept mail
+fn bounce_status(kind: &str) -> Option<SubscriptionStatus> {
+    match kind {
+        "HardBounce" | "BadEmailAddress" => Some(SubscriptionStatus::Bounced),
+        // postmark reports some complaints as a bounce type
+        "SpamComplaint" => Some(SubscriptionStatus::Complained),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postmark_events_are_parsed() {
+        let event: PostmarkEvent = serde_json::from_str(
+            r#"{"RecordType": "Bounce", "Type": "HardBounce", "TypeCode": 1, "Email": "a@example.com", "Inactive": true}"#,
+        )
+        .unwrap();
+        assert!(matches!(event, PostmarkEvent::Bounce { kind, .. } if kind == "HardBounce"));
+        let event: PostmarkEvent =
+            serde_json::from_str(r#"{"RecordType": "Open", "Recipient": "a@example.com"}"#)
+                .unwrap();
+        assert!(matches!(event, PostmarkEvent::Other));
+        assert_eq!(bounce_status("SoftBounce"), None);
+        assert_eq!(
+            bounce_status("SpamComplaint"),
+            Some(SubscriptionStatus::Complained)
+        );
+    }
+}
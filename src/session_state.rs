@@ -1,2 +1,10 @@
 pub const USER_ID_KEY: &str = "user_id";
 pub const FLASH_KEY: &str = "_flash";
+// our own id of the session, see `auth::register_session`
+pub const SESSION_ID_KEY: &str = "session_id";
+// set once the password is verified but the second factor is still missing,
+// `reject_anoynmous_user` only looks at USER_ID_KEY so this doesn't count as logged in
+pub const PENDING_2FA_KEY: &str = "pending_2fa";
+pub const TOTP_ENROLLMENT_SECRET_KEY: &str = "totp_enrollment_secret";
+// see `auth::verify_csrf_token`
+pub const CSRF_TOKEN_KEY: &str = "csrf_token";
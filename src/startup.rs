@@ -1,14 +1,22 @@
 use anyhow::{Context, Result};
-use sea_orm::{DatabaseConnection, SqlxPostgresConnector};
+use migration::Migrator;
+use sea_orm::{
+    ConnectionTrait, DatabaseConnection, DbBackend, SqlxPostgresConnector, Statement,
+    TransactionTrait,
+};
 use sea_orm_migration::prelude::*;
 use sqlx::PgPool;
-use tracing::info;
+use tracing::{info, warn};
 
 use crate::{
-    configuration::{EmailClientSettings, RelationalDBSettings},
+    configuration::{EmailClientSettings, MigrationMode, RelationalDBSettings},
     email_client::EmailClient,
 };
 
+// the key of the advisory lock the replicas take turns on to apply migrations, any constant
+// that no other advisory lock of this database uses
+const MIGRATION_LOCK_KEY: i64 = 0x7a65_726f_3270_726f;
+
 pub async fn get_database_connection(sql: RelationalDBSettings) -> Result<DatabaseConnection> {
     let db_url = format!(
         "postgres://{}:{:?}@{}:{}/{}",
@@ -35,3 +43,90 @@ pub fn get_email_client(conf: EmailClientSettings) -> Result<EmailClient> {
     );
     Ok(email_client)
 }
+
+// applies the pending migrations or refuses to start when there are any, depending on the mode
+pub async fn prepare_schema(db: &DatabaseConnection, mode: MigrationMode) -> Result<()> {
+    match mode {
+        MigrationMode::Apply => apply_pending_migrations(db).await,
+        MigrationMode::Verify => {
+            let pending = pending_migrations(db).await?;
+            if !pending.is_empty() {
+                anyhow::bail!(
+                    "the database schema is behind, pending migrations: {}; apply them or set db.migrations to apply",
+                    pending.join(", ")
+                );
+            }
+            Ok(())
+        }
+    }
+}
+
+// the lock is taken in the transaction the migrations run in, so a pool of one connection
+// is enough; it is released with the transaction, on commit, on an error or if the process dies
+async fn apply_pending_migrations(db: &DatabaseConnection) -> Result<()> {
+    let txn = db
+        .begin()
+        .await
+        .context("fail to start the migration transaction")?;
+    txn.execute(Statement::from_sql_and_values(
+        DbBackend::Postgres,
+        "select pg_advisory_xact_lock($1)",
+        [MIGRATION_LOCK_KEY.into()],
+    ))
+    .await
+    .context("fail to take the migration lock")?;
+
+    // another replica may have applied them while this one was waiting for the lock
+    let pending = pending_migrations(&txn).await?;
+    if !pending.is_empty() {
+        info!(
+            migrations = pending.join(", "),
+            "applying pending migrations"
+        );
+        Migrator::up(&txn, None)
+            .await
+            .context("fail to apply the pending migrations")?;
+    }
+    txn.commit().await.context("fail to commit the migrations")
+}
+
+// the migrations the code knows but the database has not applied, oldest first; migrations
+// applied by a newer release are only logged so that a rollout can still start old replicas
+pub async fn pending_migrations<C: ConnectionTrait>(db: &C) -> Result<Vec<String>> {
+    let installed = db
+        .query_one(Statement::from_string(
+            DbBackend::Postgres,
+            "select to_regclass('seaql_migrations')::text as installed".to_owned(),
+        ))
+        .await
+        .context("fail to look up the migration table")?
+        .map(|row| row.try_get::<Option<String>>("", "installed"))
+        .transpose()?
+        .flatten();
+    let applied: Vec<String> = match installed {
+        Some(_) => db
+            .query_all(Statement::from_string(
+                DbBackend::Postgres,
+                "select version from seaql_migrations".to_owned(),
+            ))
+            .await
+            .context("fail to read the applied migrations")?
+            .iter()
+            .map(|row| row.try_get("", "version"))
+            .collect::<Result<_, _>>()?,
+        None => Vec::new(),
+    };
+    let known: Vec<String> = Migrator::migrations()
+        .iter()
+        .map(|migration| migration.name().to_owned())
+        .collect();
+
+    let unknown: Vec<&String> = applied.iter().filter(|v| !known.contains(v)).collect();
+    if !unknown.is_empty() {
+        warn!(
+            ?unknown,
+            "the database has migrations this release does not know"
+        );
+    }
+    Ok(known.into_iter().filter(|v| !applied.contains(v)).collect())
+}
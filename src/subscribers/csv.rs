@@ -0,0 +1,120 @@
+// just enough of RFC 4180 for the subscriber import and export: quoted fields,
+// doubled quotes inside them, and either line ending
+
+// the fields of a record and the line it starts on
+pub type Record = (usize, Vec<String>);
+
+// the records of `input`, a quote left open is an error on the line it was opened
+pub fn parse_records(input: &str) -> Result<Vec<Record>, (usize, String)> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut record_line = 1;
+    let mut quote_line = 1;
+    let mut chars = input.strip_prefix('\u{feff}').unwrap_or(input).chars();
+    let mut peeked = chars.next();
+    while let Some(c) = peeked {
+        peeked = chars.next();
+        if in_quotes {
+            match c {
+                '"' if peeked == Some('"') => {
+                    field.push('"');
+                    peeked = chars.next();
+                }
+                '"' => in_quotes = false,
+                c => {
+                    if c == '\n' {
+                        line += 1;
+                    }
+                    field.push(c);
+                }
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => {
+                in_quotes = true;
+                quote_line = line;
+            }
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if peeked == Some('\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                push_record(&mut records, record_line, std::mem::take(&mut record));
+                line += 1;
+                record_line = line;
+            }
+            c => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err((quote_line, "unterminated quoted field".to_owned()));
+    }
+    record.push(field);
+    push_record(&mut records, record_line, record);
+    Ok(records)
+}
+
+// blank lines, e.g. the one after the last line break, aren't records
+fn push_record(records: &mut Vec<Record>, line: usize, record: Vec<String>) {
+    if record.len() > 1 || !record[0].trim().is_empty() {
+        records.push((line, record));
+    }
+}
+
+// a single line of `fields`, ended with CRLF
+pub fn write_record<S: AsRef<str>>(out: &mut String, fields: &[S]) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let field = field.as_ref();
+        if field.contains([',', '"', '\r', '\n']) {
+            out.push('"');
+            out.push_str(&field.replace('"', "\"\""));
+            out.push('"');
+        } else {
+            out.push_str(field);
+        }
+    }
+    out.push_str("\r\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoted_fields_may_hold_separators() {
+        let records = parse_records("a,\"b, \"\"c\"\"\"\r\n\r\n\"d\ne\",f\ng,").unwrap();
+        assert_eq!(
+            records,
+            vec![
+                (1, vec!["a".to_owned(), "b, \"c\"".to_owned()]),
+                (3, vec!["d\ne".to_owned(), "f".to_owned()]),
+                (5, vec!["g".to_owned(), "".to_owned()]),
+            ]
+        );
+        assert_eq!(
+            parse_records("a,b\nc,\"d").unwrap_err(),
+            (2, "unterminated quoted field".to_owned())
+        );
+    }
+
+    #[test]
+    fn written_records_parse_back() {
+        let fields = ["plain", "with, comma", "with \"quotes\"", "two\r\nlines"];
+        let mut out = String::new();
+        write_record(&mut out, &fields);
+        assert_eq!(
+            out,
+            "plain,\"with, comma\",\"with \"\"quotes\"\"\",\"two\r\nlines\"\r\n"
+        );
+        assert_eq!(
+            parse_records(&out).unwrap(),
+            vec![(1, fields.map(String::from).to_vec())]
+        );
+    }
+}
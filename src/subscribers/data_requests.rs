@@ -0,0 +1,351 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use chrono::{Duration, Utc};
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::OnConflict, ActiveEnum, ActiveValue, ColumnTrait,
+    ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::same_email;
+use crate::{
+    entities::{
+        audit_events::{self, Entity as AuditEvents},
+        data_request_tokens::{self, Entity as DataRequestTokens},
+        erased_subscribers::{self, Entity as ErasedSubscribers},
+        issue_delivery_queue::{self, Entity as IssueDeliveryQueue},
+        newsletter_issues::Entity as NewsletterIssues,
+        subscription_tokens::{self, Entity as SubscriptionTokens},
+        subscriptions::{self, Entity as Subscriptions},
+        suppressions::{self, Entity as Suppressions},
+    },
+    utils::sha256_hex,
+};
+
+// how long the link mailed to a subscriber asking for their data works
+const DATA_REQUEST_TOKEN_TTL_HOURS: i64 = 24;
+// replaces the reason of an erased address' suppression, an admin may have named the person in it
+const ERASED_SUPPRESSION_REASON: &str = "the address was erased on request";
+
+// everything stored about an email address, an address differing only in case
+// is the same person
+#[derive(Debug, Serialize)]
+pub struct SubscriberData {
+    pub email: String,
+    pub exported_at: DateTimeWithTimeZone,
+    pub subscriptions: Vec<SubscriptionRecord>,
+    pub queued_deliveries: Vec<QueuedDelivery>,
+    // what the admins did to the subscriptions and to the suppression of the address
+    pub admin_actions: Vec<AdminAction>,
+    pub suppressions: Vec<SuppressionRecord>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SubscriptionRecord {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    pub status: String,
+    pub subscribed_at: DateTimeWithTimeZone,
+    // an unused confirmation link is stored
+    pub has_confirmation_token: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedDelivery {
+    pub newsletter_issue_id: Uuid,
+    pub title: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SuppressionRecord {
+    pub reason: String,
+    pub source: String,
+    pub created_at: DateTimeWithTimeZone,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AdminAction {
+    pub occurred_at: DateTimeWithTimeZone,
+    pub action: String,
+}
+
+// `None` when nothing is stored about the address
+#[tracing::instrument(name = "export a subscriber's data", skip(db))]
+pub async fn export_subscriber_data(
+    db: &DatabaseConnection,
+    email: &str,
+) -> anyhow::Result<Option<SubscriberData>> {
+    let subscribers = Subscriptions::find()
+        .filter(same_email(subscriptions::Column::Email, email))
+        .order_by_asc(subscriptions::Column::SubscribedAt)
+        .all(db)
+        .await
+        .context("fail to find the subscriptions")?;
+    let queued_deliveries = IssueDeliveryQueue::find()
+        .filter(same_email(
+            issue_delivery_queue::Column::SubscriberEmail,
+            email,
+        ))
+        .find_also_related(NewsletterIssues)
+        .all(db)
+        .await
+        .context("fail to find the queued deliveries")?;
+    // the entry of an erased address is kept under its hash
+    let hash = erasure_hash(email);
+    let suppressions = Suppressions::find()
+        .filter(suppressions::Column::Address.is_in([email.trim().to_lowercase(), hash.clone()]))
+        .order_by_asc(suppressions::Column::CreatedAt)
+        .all(db)
+        .await
+        .context("fail to find the suppressions")?;
+    if subscribers.is_empty() && queued_deliveries.is_empty() && suppressions.is_empty() {
+        return Ok(None);
+    }
+
+    let ids: Vec<_> = subscribers.iter().map(|subscriber| subscriber.id).collect();
+    let with_token: HashSet<_> = SubscriptionTokens::find()
+        .filter(subscription_tokens::Column::SubscriberId.is_in(ids.clone()))
+        .all(db)
+        .await
+        .context("fail to find the subscription tokens")?
+        .into_iter()
+        .map(|token| token.subscriber_id)
+        .collect();
+    let admin_actions = AuditEvents::find()
+        .filter(
+            audit_events::Column::Target.is_in(ids.iter().map(Uuid::to_string).chain(Some(hash))),
+        )
+        .order_by_asc(audit_events::Column::OccurredAt)
+        .all(db)
+        .await
+        .context("fail to find the audit events")?;
+
+    Ok(Some(SubscriberData {
+        email: email.to_owned(),
+        exported_at: Utc::now().into(),
+        subscriptions: subscribers
+            .into_iter()
+            .map(|subscriber| SubscriptionRecord {
+                has_confirmation_token: with_token.contains(&subscriber.id),
+                id: subscriber.id,
+                email: subscriber.email,
+                name: subscriber.name,
+                status: subscriber.status.to_value(),
+                subscribed_at: subscriber.subscribed_at,
+            })
+            .collect(),
+        queued_deliveries: queued_deliveries
+            .into_iter()
+            .map(|(delivery, issue)| QueuedDelivery {
+                newsletter_issue_id: delivery.newsletter_issue_id,
+                title: issue.map(|issue| issue.title),
+            })
+            .collect(),
+        admin_actions: admin_actions
+            .into_iter()
+            .map(|event| AdminAction {
+                occurred_at: event.occurred_at,
+                action: event.action,
+            })
+            .collect(),
+        suppressions: suppressions
+            .into_iter()
+            .map(|suppression| SuppressionRecord {
+                reason: suppression.reason,
+                source: suppression.source,
+                created_at: suppression.created_at,
+            })
+            .collect(),
+    }))
+}
+
+// deletes every row mentioning the address and leaves a tombstone, so that an
+// import doesn't bring it back; returns the ids of the erased subscriptions.
+// the audit log only holds their ids and the hash of the address, it is
+// append-only and stays as it is. a suppressed address stays suppressed, its
+// entry is kept under the same hash as the tombstone
+#[tracing::instrument(name = "erase a subscriber", skip(db, email))]
+pub async fn erase_subscriber(db: &DatabaseConnection, email: &str) -> anyhow::Result<Vec<Uuid>> {
+    let txn = db.begin().await?;
+    let ids: Vec<_> = Subscriptions::find()
+        .filter(same_email(subscriptions::Column::Email, email))
+        .all(&txn)
+        .await?
+        .into_iter()
+        .map(|subscriber| subscriber.id)
+        .collect();
+    SubscriptionTokens::delete_many()
+        .filter(subscription_tokens::Column::SubscriberId.is_in(ids.clone()))
+        .exec(&txn)
+        .await?;
+    DataRequestTokens::delete_many()
+        .filter(data_request_tokens::Column::SubscriberId.is_in(ids.clone()))
+        .exec(&txn)
+        .await?;
+    IssueDeliveryQueue::delete_many()
+        .filter(same_email(
+            issue_delivery_queue::Column::SubscriberEmail,
+            email,
+        ))
+        .exec(&txn)
+        .await?;
+    Subscriptions::delete_many()
+        .filter(subscriptions::Column::Id.is_in(ids.clone()))
+        .exec(&txn)
+        .await?;
+    let hash = erasure_hash(email);
+    if let Some(suppression) = Suppressions::find_by_id(email.trim().to_lowercase())
+        .one(&txn)
+        .await?
+    {
+        Suppressions::delete_by_id(suppression.address)
+            .exec(&txn)
+            .await?;
+        let hashed = suppressions::ActiveModel {
+            address: ActiveValue::Set(hash.clone()),
+            reason: ActiveValue::Set(ERASED_SUPPRESSION_REASON.to_owned()),
+            source: ActiveValue::Set(suppression.source),
+            created_at: ActiveValue::Set(suppression.created_at),
+        };
+        Suppressions::insert(hashed)
+            .on_conflict(
+                OnConflict::column(suppressions::Column::Address)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+    }
+    let tombstone = erased_subscribers::ActiveModel {
+        email_hash: ActiveValue::Set(hash),
+        erased_at: ActiveValue::Set(Utc::now().into()),
+    };
+    ErasedSubscribers::insert(tombstone)
+        .on_conflict(
+            OnConflict::column(erased_subscribers::Column::EmailHash)
+                .update_column(erased_subscribers::Column::ErasedAt)
+                .to_owned(),
+        )
+        .exec(&txn)
+        .await?;
+    txn.commit().await?;
+    Ok(ids)
+}
+
+// the subset of `emails` that were erased
+pub async fn erased_emails<'a>(
+    db: &DatabaseConnection,
+    emails: impl IntoIterator<Item = &'a str>,
+) -> anyhow::Result<HashSet<String>> {
+    let mut by_hash: Vec<_> = emails
+        .into_iter()
+        .map(|email| (erasure_hash(email), email))
+        .collect();
+    let erased: HashSet<_> = ErasedSubscribers::find()
+        .filter(
+            erased_subscribers::Column::EmailHash
+                .is_in(by_hash.iter().map(|(hash, _)| hash.clone())),
+        )
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|tombstone| tombstone.email_hash)
+        .collect();
+    by_hash.retain(|(hash, _)| erased.contains(hash));
+    Ok(by_hash
+        .into_iter()
+        .map(|(_, email)| email.to_owned())
+        .collect())
+}
+
+// confirming a subscription again is a fresh consent, the tombstone goes
+pub async fn lift_erasure<C: ConnectionTrait>(conn: &C, email: &str) -> Result<(), sea_orm::DbErr> {
+    ErasedSubscribers::delete_by_id(erasure_hash(email))
+        .exec(conn)
+        .await?;
+    Ok(())
+}
+
+// the link mailed to a subscriber who asks for their data, `None` when no
+// subscription has the address
+#[tracing::instrument(name = "issue a data request token", skip(db))]
+pub async fn issue_data_request_token(
+    db: &DatabaseConnection,
+    email: &str,
+) -> anyhow::Result<Option<(subscriptions::Model, String)>> {
+    let Some(subscriber) = Subscriptions::find()
+        .filter(same_email(subscriptions::Column::Email, email))
+        .order_by_asc(subscriptions::Column::SubscribedAt)
+        .one(db)
+        .await
+        .context("fail to find the subscriber")?
+    else {
+        return Ok(None);
+    };
+    let token = generate_data_request_token();
+    let new_token = data_request_tokens::ActiveModel {
+        token_hash: ActiveValue::Set(sha256_hex(&token)),
+        subscriber_id: ActiveValue::Set(subscriber.id),
+        expires_at: ActiveValue::Set(
+            (Utc::now() + Duration::hours(DATA_REQUEST_TOKEN_TTL_HOURS)).into(),
+        ),
+    };
+    DataRequestTokens::insert(new_token)
+        .exec(db)
+        .await
+        .context("fail to store the data request token")?;
+    Ok(Some((subscriber, token)))
+}
+
+// the subscriber a data request link was mailed to, while it hasn't expired
+pub async fn find_by_data_request_token(
+    db: &DatabaseConnection,
+    token: &str,
+) -> anyhow::Result<Option<subscriptions::Model>> {
+    let now: DateTimeWithTimeZone = Utc::now().into();
+    let Some(token) = DataRequestTokens::find()
+        .filter(data_request_tokens::Column::TokenHash.eq(sha256_hex(token)))
+        .filter(data_request_tokens::Column::ExpiresAt.gt(now))
+        .one(db)
+        .await
+        .context("fail to look up the data request token")?
+    else {
+        return Ok(None);
+    };
+    Subscriptions::find_by_id(token.subscriber_id)
+        .one(db)
+        .await
+        .context("fail to find the subscriber")
+}
+
+// the only trace of an erased address, in the tombstone, its suppression and the audit log;
+// unsalted so that it is found again from the address alone
+pub fn erasure_hash(email: &str) -> String {
+    sha256_hex(&email.trim().to_lowercase())
+}
+
+fn generate_data_request_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_tombstone_ignores_case_and_spaces() {
+        assert_eq!(
+            erasure_hash(" Alice@Example.com"),
+            erasure_hash("alice@example.com")
+        );
+        assert_ne!(erasure_hash("alice@example.com"), "alice@example.com");
+    }
+}
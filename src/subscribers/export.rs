@@ -0,0 +1,67 @@
+use futures_util::{stream, Stream};
+use sea_orm::{
+    ActiveEnum, ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect,
+};
+use uuid::Uuid;
+
+use super::csv::write_record;
+use crate::entities::subscriptions::{self, Entity as Subscriptions};
+
+// the rows fetched by a single query
+const BATCH_SIZE: u64 = 500;
+
+pub const EXPORT_HEADER: [&str; 4] = ["email", "name", "status", "subscribed_at"];
+
+enum Cursor {
+    Header,
+    // the id of the last subscriber written
+    After(Option<Uuid>),
+    Done,
+}
+
+// every subscriber as csv, a chunk per batch so the table is never held in memory,
+// the output can be imported back
+pub fn export_subscribers(db: DatabaseConnection) -> impl Stream<Item = Result<String, DbErr>> {
+    stream::unfold(Cursor::Header, move |cursor| {
+        let db = db.clone();
+        async move {
+            let after = match cursor {
+                Cursor::Header => {
+                    let mut out = String::new();
+                    write_record(&mut out, &EXPORT_HEADER);
+                    return Some((Ok(out), Cursor::After(None)));
+                }
+                Cursor::After(after) => after,
+                Cursor::Done => return None,
+            };
+            let mut query = Subscriptions::find();
+            if let Some(after) = after {
+                query = query.filter(subscriptions::Column::Id.gt(after));
+            }
+            let batch = match query
+                .order_by_asc(subscriptions::Column::Id)
+                .limit(BATCH_SIZE)
+                .all(&db)
+                .await
+            {
+                Ok(batch) => batch,
+                Err(e) => return Some((Err(e), Cursor::Done)),
+            };
+            let last = batch.last()?.id;
+            let mut out = String::new();
+            for subscriber in &batch {
+                write_record(
+                    &mut out,
+                    &[
+                        subscriber.email.as_str(),
+                        subscriber.name.as_str(),
+                        subscriber.status.to_value().as_str(),
+                        subscriber.subscribed_at.to_rfc3339().as_str(),
+                    ],
+                );
+            }
+            Some((Ok(out), Cursor::After(Some(last))))
+        }
+    })
+}
@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone, sea_query::OnConflict, ActiveValue, ColumnTrait,
+    DatabaseConnection, EntityTrait, QueryFilter, TransactionTrait,
+};
+use serde::Serialize;
+use uuid::Uuid;
+
+use super::{csv::parse_records, erased_emails, parse_status};
+use crate::{
+    domain::{Email, UserName},
+    entities::{
+        sea_orm_active_enums::SubscriptionStatus,
+        subscriptions::{self, Entity as Subscriptions},
+    },
+};
+
+// the most rows a single file may hold
+pub const MAX_IMPORT_ROWS: usize = 10_000;
+// the largest file read, enough for the rows with long names and addresses
+pub const MAX_IMPORT_BYTES: usize = 4 * 1024 * 1024;
+// the rows inserted by a single statement
+const BATCH_SIZE: usize = 500;
+
+// a row that passed validation, `line` is where it starts in the file
+#[derive(Debug)]
+pub struct ImportRow {
+    pub line: usize,
+    pub email: Email,
+    pub name: UserName,
+    pub status: SubscriptionStatus,
+    // only set when importing an export
+    pub subscribed_at: Option<DateTimeWithTimeZone>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct RowError {
+    pub line: usize,
+    pub error: String,
+}
+
+impl RowError {
+    pub fn new(line: usize, error: impl Into<String>) -> Self {
+        RowError {
+            line,
+            error: error.into(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct ImportReport {
+    pub imported: usize,
+    // already subscribed, they are left untouched
+    pub skipped: usize,
+    pub confirmations_sent: usize,
+    pub errors: Vec<RowError>,
+}
+
+// rows are `email,name[,status[,subscribed_at]]` with an optional header line,
+// the error is for a file that can't be read at all
+pub fn parse_import(input: &str) -> Result<(Vec<ImportRow>, Vec<RowError>), String> {
+    let mut records =
+        parse_records(input).map_err(|(line, error)| format!("line {line}: {error}"))?;
+    if let Some((_, header)) = records.first() {
+        if header[0].trim().eq_ignore_ascii_case("email") {
+            records.remove(0);
+        }
+    }
+    if records.is_empty() {
+        return Err("the file has no subscribers".to_owned());
+    }
+    if records.len() > MAX_IMPORT_ROWS {
+        return Err(format!(
+            "the file has {} rows, at most {MAX_IMPORT_ROWS} can be imported at once",
+            records.len()
+        ));
+    }
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    // the line each email was first seen on
+    let mut seen = HashMap::new();
+    for (line, fields) in records {
+        match parse_row(&fields) {
+            Ok(row) => match seen.get(row.email.as_ref()) {
+                Some(first) => {
+                    errors.push(RowError::new(line, format!("duplicate of line {first}")))
+                }
+                None => {
+                    seen.insert(row.email.as_ref().to_owned(), line);
+                    rows.push(ImportRow { line, ..row });
+                }
+            },
+            Err(error) => errors.push(RowError::new(line, error)),
+        }
+    }
+    Ok((rows, errors))
+}
+
+fn parse_row(fields: &[String]) -> Result<ImportRow, String> {
+    if !(2..=4).contains(&fields.len()) {
+        return Err(format!("expected 2 to 4 fields, found {}", fields.len()));
+    }
+    let email = Email::parse(fields[0].trim().to_owned())?;
+    let name = UserName::parse(fields[1].trim())?;
+    let status = match fields.get(2).map(|status| status.trim()) {
+        Some(status) if !status.is_empty() => parse_status(status)?,
+        _ => SubscriptionStatus::Pending,
+    };
+    let subscribed_at = match fields.get(3).map(|at| at.trim()) {
+        Some(at) if !at.is_empty() => {
+            let subscribed_at = DateTime::parse_from_rfc3339(at)
+                .map_err(|_| format!("invalid subscribed_at: {at}"))?;
+            Some(subscribed_at)
+        }
+        _ => None,
+    };
+    Ok(ImportRow {
+        line: 0,
+        email,
+        name,
+        status,
+        subscribed_at,
+    })
+}
+
+// an address erased on request isn't imported again, whoever holds a copy of it
+pub async fn drop_erased(
+    db: &DatabaseConnection,
+    rows: Vec<ImportRow>,
+    errors: &mut Vec<RowError>,
+) -> anyhow::Result<Vec<ImportRow>> {
+    let erased = erased_emails(db, rows.iter().map(|row| row.email.as_ref())).await?;
+    let (rows, dropped): (Vec<_>, Vec<_>) = rows
+        .into_iter()
+        .partition(|row| !erased.contains(row.email.as_ref()));
+    errors.extend(
+        dropped
+            .into_iter()
+            .map(|row| RowError::new(row.line, "the address was erased on request")),
+    );
+    errors.sort_by_key(|error| error.line);
+    Ok(rows)
+}
+
+// inserts `rows` a batch at a time and returns the new subscribers with the line
+// they came from, an address that is already subscribed is skipped
+#[tracing::instrument(name = "import subscribers", skip_all, fields(rows = rows.len()))]
+pub async fn import_subscribers(
+    db: &DatabaseConnection,
+    rows: Vec<ImportRow>,
+) -> anyhow::Result<Vec<(usize, subscriptions::Model)>> {
+    let now: DateTimeWithTimeZone = Utc::now().into();
+    let mut imported = Vec::new();
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let mut lines = HashMap::new();
+        let batch: Vec<_> = rows
+            .by_ref()
+            .take(BATCH_SIZE)
+            .map(|row| {
+                let id = Uuid::new_v4();
+                lines.insert(id, row.line);
+                // every column is set, a batch must agree on the columns it inserts
+                subscriptions::ActiveModel {
+                    id: ActiveValue::Set(id),
+                    email: ActiveValue::Set(row.email.inner()),
+                    name: ActiveValue::Set(row.name.inner()),
+                    subscribed_at: ActiveValue::Set(row.subscribed_at.unwrap_or(now)),
+                    status: ActiveValue::Set(row.status),
+                }
+            })
+            .collect();
+        let txn = db.begin().await?;
+        Subscriptions::insert_many(batch)
+            .on_conflict(
+                OnConflict::column(subscriptions::Column::Email)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .exec_without_returning(&txn)
+            .await?;
+        // the conflicting rows keep the id of the existing subscriber
+        let inserted = Subscriptions::find()
+            .filter(subscriptions::Column::Id.is_in(lines.keys().copied()))
+            .all(&txn)
+            .await?;
+        txn.commit().await?;
+        imported.extend(
+            inserted
+                .into_iter()
+                .map(|subscriber| (lines[&subscriber.id], subscriber)),
+        );
+    }
+    imported.sort_by_key(|(line, _)| *line);
+    Ok(imported)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_bad_row_is_reported() {
+        let input = "Email,Name,Status\n\
+            alice@example.com,Alice\n\
+            bob@example.com,Bob,confirmed\n\
+            not-an-email,Carol\n\
+            dave@example.com,Dave,gone\n\
+            alice@example.com,Alice again\n\
+            erin@example.com\n";
+        let (rows, errors) = parse_import(input).unwrap();
+        let emails: Vec<_> = rows
+            .iter()
+            .map(|row| (row.line, row.email.as_ref()))
+            .collect();
+        assert_eq!(
+            emails,
+            vec![(2, "alice@example.com"), (3, "bob@example.com")]
+        );
+        assert_eq!(rows[1].status, SubscriptionStatus::Confirmed);
+        assert_eq!(
+            errors,
+            vec![
+                RowError::new(4, "invalid email"),
+                RowError::new(5, "unknown subscription status: gone"),
+                RowError::new(6, "duplicate of line 2"),
+                RowError::new(7, "expected 2 to 4 fields, found 1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn an_export_can_be_imported() {
+        let input = "email,name,status,subscribed_at\r\n\
+            alice@example.com,Alice,unsubscribed,2023-04-01T10:00:00+00:00\r\n";
+        let (rows, errors) = parse_import(input).unwrap();
+        assert!(errors.is_empty());
+        assert_eq!(rows[0].status, SubscriptionStatus::Unsubscribed);
+        assert_eq!(
+            rows[0].subscribed_at.unwrap().to_rfc3339(),
+            "2023-04-01T10:00:00+00:00"
+        );
+    }
+
+    #[test]
+    fn unreadable_files_are_rejected() {
+        assert!(parse_import("email,name\n").is_err());
+        assert!(parse_import("alice@example.com,\"Alice\n").is_err());
+        let too_many = "a@example.com,A\n".repeat(MAX_IMPORT_ROWS + 1);
+        assert!(parse_import(&too_many).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+pub mod csv;
+mod data_requests;
+mod export;
+mod import;
+
+pub use data_requests::{
+    erase_subscriber, erased_emails, erasure_hash, export_subscriber_data,
+    find_by_data_request_token, issue_data_request_token, lift_erasure, SubscriberData,
+};
+pub use export::{export_subscribers, EXPORT_HEADER};
+pub use import::{
+    drop_erased, import_subscribers, parse_import, ImportReport, ImportRow, RowError,
+    MAX_IMPORT_BYTES, MAX_IMPORT_ROWS,
+};
+
+use chrono::{Duration, NaiveDate, TimeZone, Utc};
+use sea_orm::{
+    prelude::DateTimeWithTimeZone,
+    sea_query::{extension::postgres::PgExpr, Expr, Func, SimpleExpr},
+    ActiveEnum, ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, EntityTrait,
+    PaginatorTrait, QueryFilter, QueryOrder, TransactionTrait,
+};
+use uuid::Uuid;
+
+use crate::entities::{
+    issue_delivery_queue::{self, Entity as IssueDeliveryQueue},
+    sea_orm_active_enums::SubscriptionStatus,
+    subscription_tokens::{self, Entity as SubscriptionTokens},
+    subscriptions::{self, Entity as Subscriptions},
+};
+
+// the page size of a listing unless another one is asked for
+pub const DEFAULT_PAGE_SIZE: u64 = 50;
+// the most subscribers a single page returns
+pub const MAX_PAGE_SIZE: u64 = 200;
+
+pub fn parse_status(s: &str) -> Result<SubscriptionStatus, String> {
+    SubscriptionStatus::try_from_value(&s.to_owned())
+        .map_err(|_| format!("unknown subscription status: {s}"))
+}
+
+#[derive(Debug, Default)]
+pub struct SubscriberFilter {
+    // a part of the email or of the name, case insensitive
+    pub search: Option<String>,
+    pub status: Option<SubscriptionStatus>,
+    pub since: Option<DateTimeWithTimeZone>,
+    pub until: Option<DateTimeWithTimeZone>,
+}
+
+impl SubscriberFilter {
+    // `since` and `until` are days in UTC, both of them included
+    pub fn parse(
+        search: Option<String>,
+        status: Option<&str>,
+        since: Option<&str>,
+        until: Option<&str>,
+    ) -> Result<Self, String> {
+        Ok(SubscriberFilter {
+            search,
+            status: status.map(parse_status).transpose()?,
+            since: since.map(|day| start_of_day(day, 0)).transpose()?,
+            until: until.map(|day| start_of_day(day, 1)).transpose()?,
+        })
+    }
+}
+
+fn start_of_day(input: &str, days_later: i64) -> Result<DateTimeWithTimeZone, String> {
+    let day = NaiveDate::parse_from_str(input, "%Y-%m-%d")
+        .map_err(|_| format!("invalid date: {input}"))?;
+    let start = (day + Duration::days(days_later))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time");
+    Ok(Utc.from_utc_datetime(&start).into())
+}
+
+#[derive(Debug)]
+pub struct SubscriberPage {
+    pub subscribers: Vec<subscriptions::Model>,
+    // starts at 1
+    pub page: u64,
+    pub per_page: u64,
+    pub total: u64,
+}
+
+impl SubscriberPage {
+    pub fn last_page(&self) -> u64 {
+        self.total.div_ceil(self.per_page).max(1)
+    }
+}
+
+// the most recent subscribers first, out of range pages are empty
+pub async fn list_subscribers(
+    db: &DatabaseConnection,
+    filter: &SubscriberFilter,
+    page: u64,
+    per_page: u64,
+) -> anyhow::Result<SubscriberPage> {
+    let page = page.max(1);
+    let per_page = per_page.clamp(1, MAX_PAGE_SIZE);
+    let mut query = Subscriptions::find();
+    if let Some(search) = &filter.search {
+        let pattern = format!("%{}%", escape_like(search));
+        query = query.filter(
+            Condition::any()
+                .add(Expr::col(subscriptions::Column::Email).ilike(pattern.as_str()))
+                .add(Expr::col(subscriptions::Column::Name).ilike(pattern.as_str())),
+        );
+    }
+    if let Some(status) = filter.status {
+        query = query.filter(subscriptions::Column::Status.eq(status));
+    }
+    if let Some(since) = filter.since {
+        query = query.filter(subscriptions::Column::SubscribedAt.gte(since));
+    }
+    if let Some(until) = filter.until {
+        query = query.filter(subscriptions::Column::SubscribedAt.lt(until));
+    }
+    let paginator = query
+        .order_by_desc(subscriptions::Column::SubscribedAt)
+        .order_by_asc(subscriptions::Column::Id)
+        .paginate(db, per_page);
+    let total = paginator.num_items().await?;
+    let subscribers = paginator.fetch_page(page - 1).await?;
+    Ok(SubscriberPage {
+        subscribers,
+        page,
+        per_page,
+        total,
+    })
+}
+
+// `%` and `_` typed by the admin are searched for literally
+fn escape_like(search: &str) -> String {
+    search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+// case insensitive, the same mailbox may have been typed differently
+fn same_email<C: ColumnTrait>(column: C, email: &str) -> SimpleExpr {
+    Expr::expr(Func::lower(Expr::col(column))).eq(email.trim().to_lowercase())
+}
+
+pub async fn find_subscriber(
+    db: &DatabaseConnection,
+    id: Uuid,
+) -> anyhow::Result<Option<subscriptions::Model>> {
+    Ok(Subscriptions::find_by_id(id).one(db).await?)
+}
+
+pub enum StatusChange {
+    Changed(subscriptions::Model),
+    Unknown,
+    // the address bounced or complained, only lifting its suppression mails it again
+    Suppressed,
+}
+
+// a bounced or complained subscriber isn't confirmed, the check and the update
+// are one statement so a webhook arriving in between can't be overwritten
+#[tracing::instrument(name = "set a subscriber status", skip(db))]
+pub async fn set_subscriber_status(
+    db: &DatabaseConnection,
+    id: Uuid,
+    status: SubscriptionStatus,
+) -> anyhow::Result<StatusChange> {
+    let mut query = Subscriptions::update_many()
+        .col_expr(
+            subscriptions::Column::Status,
+            subscriptions::Column::Status.save_as(Expr::val(status)),
+        )
+        .filter(subscriptions::Column::Id.eq(id));
+    if status == SubscriptionStatus::Confirmed {
+        query = query
+            .filter(subscriptions::Column::Status.ne(SubscriptionStatus::Bounced))
+            .filter(subscriptions::Column::Status.ne(SubscriptionStatus::Complained));
+    }
+    let updated = query.exec(db).await?.rows_affected;
+    Ok(match find_subscriber(db, id).await? {
+        None => StatusChange::Unknown,
+        Some(_) if updated == 0 => StatusChange::Suppressed,
+        Some(subscriber) => StatusChange::Changed(subscriber),
+    })
+}
+
+impl SubscriptionStatus {
+    // the address bounced or complained, nothing may be sent to it anymore
+    pub fn is_suppressed(&self) -> bool {
+        matches!(
+            self,
+            SubscriptionStatus::Bounced | SubscriptionStatus::Complained
+        )
+    }
+}
+
+// moves every subscription of the address to `status`, which is a suppressed one,
+// and returns their ids; a complaint isn't downgraded to a bounce
+#[tracing::instrument(name = "mark an address undeliverable", skip(db))]
+pub async fn mark_undeliverable(
+    db: &DatabaseConnection,
+    email: &str,
+    status: SubscriptionStatus,
+) -> anyhow::Result<Vec<Uuid>> {
+    let mut query = Subscriptions::update_many()
+        .col_expr(
+            subscriptions::Column::Status,
+            subscriptions::Column::Status.save_as(Expr::val(status)),
+        )
+        .filter(same_email(subscriptions::Column::Email, email));
+    if status == SubscriptionStatus::Bounced {
+        query = query.filter(subscriptions::Column::Status.ne(SubscriptionStatus::Complained));
+    }
+    query.exec(db).await?;
+    let ids = Subscriptions::find()
+        .filter(same_email(subscriptions::Column::Email, email))
+        .filter(subscriptions::Column::Status.eq(status))
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|subscriber| subscriber.id)
+        .collect();
+    Ok(ids)
+}
+
+// the suppression of the address was lifted, its bounced and complained
+// subscriptions are mailed again; only a delivered email bounces or gets
+// reported, so they were confirmed before
+#[tracing::instrument(name = "restore a deliverable address", skip(conn))]
+pub async fn restore_deliverable<C: ConnectionTrait>(
+    conn: &C,
+    email: &str,
+) -> Result<u64, sea_orm::DbErr> {
+    let restored = Subscriptions::update_many()
+        .col_expr(
+            subscriptions::Column::Status,
+            subscriptions::Column::Status.save_as(Expr::val(SubscriptionStatus::Confirmed)),
+        )
+        .filter(same_email(subscriptions::Column::Email, email))
+        .filter(
+            Condition::any()
+                .add(subscriptions::Column::Status.eq(SubscriptionStatus::Bounced))
+                .add(subscriptions::Column::Status.eq(SubscriptionStatus::Complained)),
+        )
+        .exec(conn)
+        .await?;
+    Ok(restored.rows_affected)
+}
+
+// the queued deliveries go too, the worker would otherwise still mail the address
+#[tracing::instrument(name = "delete a subscriber", skip(db))]
+pub async fn delete_subscriber(db: &DatabaseConnection, id: Uuid) -> anyhow::Result<bool> {
+    let txn = db.begin().await?;
+    let subscriber = match Subscriptions::find_by_id(id).one(&txn).await? {
+        Some(subscriber) => subscriber,
+        None => return Ok(false),
+    };
+    SubscriptionTokens::delete_many()
+        .filter(subscription_tokens::Column::SubscriberId.eq(id))
+        .exec(&txn)
+        .await?;
+    IssueDeliveryQueue::delete_many()
+        .filter(issue_delivery_queue::Column::SubscriberEmail.eq(subscriber.email))
+        .exec(&txn)
+        .await?;
+    Subscriptions::delete_by_id(id).exec(&txn).await?;
+    txn.commit().await?;
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates_cover_whole_days() {
+        let filter = SubscriberFilter::parse(
+            None,
+            Some("pending"),
+            Some("2023-04-01"),
+            Some("2023-04-02"),
+        )
+        .unwrap();
+        assert_eq!(filter.status, Some(SubscriptionStatus::Pending));
+        assert_eq!(
+            filter.since.unwrap().to_rfc3339(),
+            "2023-04-01T00:00:00+00:00"
+        );
+        assert_eq!(
+            filter.until.unwrap().to_rfc3339(),
+            "2023-04-03T00:00:00+00:00"
+        );
+        assert!(SubscriberFilter::parse(None, Some("gone"), None, None).is_err());
+        assert!(SubscriberFilter::parse(None, None, Some("yesterday"), None).is_err());
+    }
+
+    #[test]
+    fn like_wildcards_are_escaped() {
+        assert_eq!(escape_like(r"100%_a\b"), r"100\%\_a\\b");
+    }
+}
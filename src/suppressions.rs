@@ -0,0 +1,184 @@
+use chrono::Utc;
+use sea_orm::{
+    sea_query::OnConflict, ActiveValue, ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter,
+    QueryOrder, TransactionTrait,
+};
+
+use crate::{
+    domain::Email,
+    entities::suppressions::{self, Entity as Suppressions},
+    subscribers::{erasure_hash, restore_deliverable},
+};
+
+// addresses and domains that are never mailed, whatever the status of their
+// subscriptions; a domain entry has no `@` and covers every address at it, and
+// an erased address is only kept as its hash
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SuppressionSource {
+    // added on the admin page
+    Admin,
+    // a hard bounce reported by the email api
+    Bounce,
+    // the recipient marked an email as spam
+    Complaint,
+}
+
+impl SuppressionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SuppressionSource::Admin => "admin",
+            SuppressionSource::Bounce => "bounce",
+            SuppressionSource::Complaint => "complaint",
+        }
+    }
+}
+
+impl std::fmt::Display for SuppressionSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// an address or a domain as typed by an admin, `@example.com` is a domain too
+pub fn parse_suppressed_address(input: &str) -> Result<String, String> {
+    let input = input.trim().to_lowercase();
+    if let Some(domain) = input.strip_prefix('@') {
+        return parse_domain(domain);
+    }
+    if input.contains('@') {
+        Ok(Email::parse(input)?.inner())
+    } else {
+        parse_domain(&input)
+    }
+}
+
+fn parse_domain(domain: &str) -> Result<String, String> {
+    // a domain is valid when an address at it is
+    match Email::parse(format!("postmaster@{domain}")) {
+        Ok(_) if domain.contains('.') => Ok(domain.to_owned()),
+        _ => Err(format!("invalid address or domain: {domain}")),
+    }
+}
+
+// `false` when the address or domain is already suppressed, the first entry is kept
+#[tracing::instrument(name = "add a suppression", skip(db, address), fields(address = audited_address(address)))]
+pub async fn add_suppression(
+    db: &DatabaseConnection,
+    address: &str,
+    reason: &str,
+    source: SuppressionSource,
+) -> anyhow::Result<bool> {
+    let suppression = suppressions::ActiveModel {
+        address: ActiveValue::Set(address.trim().to_lowercase()),
+        reason: ActiveValue::Set(reason.to_owned()),
+        source: ActiveValue::Set(source.as_str().to_owned()),
+        created_at: ActiveValue::Set(Utc::now().into()),
+    };
+    let inserted = Suppressions::insert(suppression)
+        .on_conflict(
+            OnConflict::column(suppressions::Column::Address)
+                .do_nothing()
+                .to_owned(),
+        )
+        .exec_without_returning(db)
+        .await?;
+    Ok(inserted > 0)
+}
+
+// `false` when nothing was suppressed under `address`, nor under its hash.
+// the list is what keeps an address from being mailed, the bounced and
+// complained statuses of its subscriptions only tell why, so they are lifted too
+#[tracing::instrument(name = "remove a suppression", skip(db, address), fields(address = audited_address(address)))]
+pub async fn remove_suppression(db: &DatabaseConnection, address: &str) -> anyhow::Result<bool> {
+    let address = address.trim().to_lowercase();
+    let txn = db.begin().await?;
+    let deleted = Suppressions::delete_many()
+        .filter(suppressions::Column::Address.is_in([erasure_hash(&address), address.clone()]))
+        .exec(&txn)
+        .await?;
+    if deleted.rows_affected == 0 {
+        return Ok(false);
+    }
+    // a domain entry never changed a status
+    if address.contains('@') {
+        restore_deliverable(&txn, &address).await?;
+    }
+    txn.commit().await?;
+    Ok(true)
+}
+
+// how the audit log and the logs name an entry: an address by its hash, a domain
+// as it is; the entry of an erased address is its hash already
+pub fn audited_address(address: &str) -> String {
+    let address = address.trim().to_lowercase();
+    match address.strip_prefix('@') {
+        Some(domain) => domain.to_owned(),
+        None if address.contains('@') => erasure_hash(&address),
+        None => address,
+    }
+}
+
+// the most recent entries first
+pub async fn list_suppressions(
+    db: &DatabaseConnection,
+) -> anyhow::Result<Vec<suppressions::Model>> {
+    let suppressions = Suppressions::find()
+        .order_by_desc(suppressions::Column::CreatedAt)
+        .all(db)
+        .await?;
+    Ok(suppressions)
+}
+
+// the entry that keeps `email` from being mailed, its own, its domain's or
+// the one it left when it was erased
+pub async fn find_suppression(
+    db: &DatabaseConnection,
+    email: &str,
+) -> anyhow::Result<Option<suppressions::Model>> {
+    let email = email.trim().to_lowercase();
+    let domain = email.rsplit_once('@').map(|(_, domain)| domain.to_owned());
+    let hash = erasure_hash(&email);
+    let suppression = Suppressions::find()
+        .filter(
+            suppressions::Column::Address
+                .is_in([Some(email), domain, Some(hash)].into_iter().flatten()),
+        )
+        .one(db)
+        .await?;
+    Ok(suppression)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_and_domains_are_told_apart() {
+        assert_eq!(
+            parse_suppressed_address(" Alice@Example.com "),
+            Ok("alice@example.com".to_owned())
+        );
+        assert_eq!(
+            parse_suppressed_address("@Example.com"),
+            Ok("example.com".to_owned())
+        );
+        assert_eq!(
+            parse_suppressed_address("example.com"),
+            Ok("example.com".to_owned())
+        );
+        assert!(parse_suppressed_address("not an address").is_err());
+        assert!(parse_suppressed_address("localhost").is_err());
+        assert!(parse_suppressed_address("").is_err());
+    }
+
+    #[test]
+    fn the_audit_log_never_names_an_address() {
+        let hash = audited_address("Alice@Example.com");
+        assert_eq!(hash, erasure_hash("alice@example.com"));
+        assert!(!hash.contains("alice"));
+        assert_eq!(audited_address(&hash), hash);
+        assert_eq!(audited_address(" Example.com"), "example.com");
+        assert_eq!(audited_address("@example.com"), "example.com");
+    }
+}
@@ -0,0 +1,107 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use opentelemetry::{
+    global,
+    sdk::{
+        trace::{self, Sampler, TracerProvider},
+        Resource,
+    },
+    trace::TracerProvider as _,
+    KeyValue,
+};
+use opentelemetry_otlp::{SpanExporterBuilder, WithExportConfig};
+use tracing::subscriber::set_global_default;
+use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
+use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
+
+use crate::configuration::TelemetrySettings;
+
+// the bunyan logs always go to stdout, the spans are also exported to an otlp
+// collector when `telemetry.otlp_endpoint` is set
+pub fn setup_logger(log_level: &str, telemetry: &TelemetrySettings) {
+    let env_filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(log_level));
+    let formatting_layer = BunyanFormattingLayer::new("zero2prod".into(), std::io::stdout);
+    let otlp_layer = tracer_provider(telemetry)
+        .expect("fail to set up the otlp exporter")
+        .map(|provider| {
+            let tracer = provider.tracer("zero2prod");
+            // `shutdown_telemetry` flushes the global provider
+            global::set_tracer_provider(provider);
+            tracing_opentelemetry::layer().with_tracer(tracer)
+        });
+    let subscriber = Registry::default()
+        .with(env_filter)
+        .with(otlp_layer)
+        .with(JsonStorageLayer)
+        .with(formatting_layer);
+
+    set_global_default(subscriber).expect("Fail to set up traincg subscriber");
+}
+
+// must be called from within the tokio runtime, the spans are exported in batches by a background task
+fn tracer_provider(telemetry: &TelemetrySettings) -> Result<Option<TracerProvider>> {
+    let Some(endpoint) = &telemetry.otlp_endpoint else {
+        return Ok(None);
+    };
+    // a sampled parent keeps its children, so a trace is either complete or missing
+    let sampler = Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+        telemetry.sampling_ratio,
+    )));
+    let exporter = SpanExporterBuilder::from(
+        opentelemetry_otlp::new_exporter()
+            .tonic()
+            .with_endpoint(endpoint),
+    )
+    .build_span_exporter()
+    .with_context(|| format!("fail to build the otlp exporter for {endpoint}"))?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(exporter, opentelemetry::runtime::Tokio)
+        .with_config(
+            trace::config()
+                .with_sampler(sampler)
+                .with_resource(Resource::new(vec![KeyValue::new(
+                    "service.name",
+                    telemetry.service_name.clone(),
+                )])),
+        )
+        .build();
+    Ok(Some(provider))
+}
+
+// flushes the spans that are still buffered, call it before the process exits;
+// an unreachable collector must not keep the process alive, so the flush is given up after a while
+pub async fn shutdown_telemetry() {
+    let shutdown = tokio::task::spawn_blocking(opentelemetry::global::shutdown_tracer_provider);
+    if tokio::time::timeout(Duration::from_secs(5), shutdown)
+        .await
+        .is_err()
+    {
+        tracing::warn!("gave up flushing the spans to the otlp collector");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_not_exported_by_default() {
+        let provider = tracer_provider(&TelemetrySettings::default()).unwrap();
+        assert!(provider.is_none());
+    }
+
+    // the provider is only built, installing it is left to `setup_logger`; dropping it
+    // flushes the batch on the runtime, which needs a second worker thread
+    #[tokio::test(flavor = "multi_thread")]
+    async fn the_exporter_does_not_need_a_running_collector() {
+        let telemetry = TelemetrySettings {
+            otlp_endpoint: Some("http://127.0.0.1:4317".to_owned()),
+            sampling_ratio: 0.5,
+            ..Default::default()
+        };
+        let provider = tracer_provider(&telemetry).unwrap();
+        assert!(provider.is_some());
+    }
+}
@@ -1,3 +1,4 @@
+use sha2::{Digest, Sha256};
 use tokio::task::JoinHandle;
 
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
@@ -8,3 +9,17 @@ where
     let current_span = tracing::Span::current();
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
+
+// the peer ip without the port, so that reconnecting doesn't yield a new identity
+pub fn client_ip(remote_addr: &poem::web::RemoteAddr) -> String {
+    match remote_addr.as_socket_addr() {
+        Some(addr) => addr.ip().to_string(),
+        None => remote_addr.to_string(),
+    }
+}
+
+// for high-entropy random tokens that are looked up by their hash, a salted argon2
+// hash would make that lookup impossible and the entropy already defeats guessing
+pub fn sha256_hex(input: &str) -> String {
+    format!("{:x}", Sha256::digest(input.as_bytes()))
+}
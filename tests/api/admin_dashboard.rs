@@ -1,7 +1,25 @@
 use super::helpers::assert_is_redirect_to;
-use crate::cookie_test;
+use crate::{cookie_test, login_test};
 
 cookie_test!(must_be_logged_in_to_access_the_board, [app] {
     let resp = app.get_admin_dashboard().await;
     assert_is_redirect_to(&resp, "/login");
 });
+
+login_test!(admin_pages_share_the_navigation_bar, [app] {
+    let dashboard = app.get_admin_dashboard().await.text().await?;
+    let pages = [
+        dashboard,
+        app.get_change_password_html().await,
+        app.get_tokens_html().await,
+        app.get_sessions_html().await,
+        app.get_publish_newsletter_html().await,
+    ];
+    for html in pages {
+        assert!(html.contains(r#"<a href="/admin/audit">Audit log</a>"#), "{}", html);
+        assert!(html.contains(r#"<form name="logoutForm" action="/logout" method="post">"#), "{}", html);
+    }
+    // the login page only links back to the public pages
+    let html = app.get_login_html().await;
+    assert!(!html.contains("/admin/audit"), "{}", html);
+});
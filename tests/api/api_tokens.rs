@@ -0,0 +1,94 @@
+use uuid::Uuid;
+
+use super::helpers::{assert_is_redirect_to, TestAppWithCookie};
+use crate::login_test;
+
+pub async fn create_token(app: &TestAppWithCookie, scopes: &[&str]) -> String {
+    let mut body = serde_json::json!({ "name": "ci" });
+    for scope in scopes {
+        body[*scope] = serde_json::json!("on");
+    }
+    let resp = app.post_create_token(&body).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let html = resp.text().await.unwrap();
+    let start = html.find(r#"<code id="api-token">"#).unwrap() + r#"<code id="api-token">"#.len();
+    let end = html[start..].find("</code>").unwrap() + start;
+    html[start..end].to_owned()
+}
+
+fn revoke_form_id(html: &str) -> String {
+    let marker = r#"name="id" value=""#;
+    let start = html.find(marker).unwrap() + marker.len();
+    html[start..start + 36].to_owned()
+}
+
+login_test!(a_token_authenticates_json_requests, [app] {
+    let token = create_token(&app, &["scope_account_read"]).await;
+    assert!(app.get_tokens_html().await.contains("never"));
+
+    let resp = app.get_api_me(Some(&token)).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let me: serde_json::Value = resp.json().await?;
+    assert_eq!(me["username"], app.test_user.username);
+    assert_eq!(me["scopes"], serde_json::json!(["account:read"]));
+
+    // the usage is recorded
+    let html = app.get_tokens_html().await;
+    assert!(!html.contains("never"), "{}", html);
+});
+
+login_test!(requests_without_a_valid_token_are_rejected, [app] {
+    let resp = app.get_api_me(None).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    assert_eq!(resp.headers()["WWW-Authenticate"], "Bearer");
+    let resp = app.get_api_me(Some("z2p_not-a-real-token")).await;
+    assert_eq!(resp.status().as_u16(), 401);
+});
+
+login_test!(a_token_is_limited_to_its_scopes, [app] {
+    let token = create_token(&app, &["scope_account_read"]).await;
+    let resp = app
+        .post_api_newsletters(&token, &serde_json::json!({
+            "title": "title",
+            "text_content": "plain text",
+            "html_content": "<p>html body</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+        }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+});
+
+login_test!(a_token_can_publish_newsletters, [app] {
+    let token = create_token(&app, &["scope_newsletters_publish"]).await;
+    let body = serde_json::json!({
+        "title": "release notes",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let resp = app.post_api_newsletters(&token, &body).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let first: serde_json::Value = resp.json().await?;
+    assert!(first["newsletter_issue_id"].is_string());
+
+    // retrying with the same idempotency key doesn't publish twice
+    let resp = app.post_api_newsletters(&token, &body).await;
+    assert_eq!(resp.status().as_u16(), 202);
+    let second: serde_json::Value = resp.json().await?;
+    assert_eq!(first, second);
+});
+
+login_test!(revoked_tokens_stop_working, [app] {
+    let token = create_token(&app, &["scope_account_read"]).await;
+    let id = revoke_form_id(&app.get_tokens_html().await);
+    let resp = app.post_revoke_token(&serde_json::json!({ "id": id })).await;
+    assert_is_redirect_to(&resp, "/admin/tokens");
+    let resp = app.get_api_me(Some(&token)).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    assert!(app.get_tokens_html().await.contains("revoked"));
+});
+
+login_test!(a_token_needs_a_scope, [app] {
+    let resp = app.post_create_token(&serde_json::json!({ "name": "ci" })).await;
+    assert_is_redirect_to(&resp, "/admin/tokens");
+});
@@ -0,0 +1,71 @@
+use uuid::Uuid;
+
+use super::helpers::assert_is_redirect_to;
+use crate::login_test;
+
+fn count_rows(html: &str, action: &str) -> usize {
+    html.matches(&format!("<td>{action}</td>")).count()
+}
+
+login_test!(logins_and_logouts_are_recorded, [app] {
+    assert_is_redirect_to(&app.post_logout().await?, "/login");
+    app.post_login(&serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    }))
+    .await?;
+    let html = app.get_audit_html("").await;
+    assert_eq!(count_rows(&html, "login"), 2, "{}", html);
+    assert_eq!(count_rows(&html, "logout"), 1, "{}", html);
+    assert!(html.contains(&format!("<td>{}</td>", app.test_user.username)), "{}", html);
+    assert!(html.contains("<td>127.0.0.1</td>"), "{}", html);
+});
+
+login_test!(publishing_records_the_issue, [app] {
+    let resp = app.post_newsletters(serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    }))
+    .await;
+    assert_is_redirect_to(&resp, "/admin/newsletters");
+
+    let html = app.get_audit_html("action=newsletter_published").await;
+    assert_eq!(count_rows(&html, "newsletter_published"), 1, "{}", html);
+    assert_eq!(count_rows(&html, "login"), 0, "{}", html);
+});
+
+login_test!(changing_the_password_is_recorded, [app] {
+    let new_password = Uuid::new_v4().to_string();
+    app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+    let html = app.get_audit_html("action=password_changed").await;
+    assert_eq!(count_rows(&html, "password_changed"), 1, "{}", html);
+});
+
+login_test!(events_can_be_filtered_by_actor_and_time, [app] {
+    let html = app.get_audit_html(&format!("actor={}", app.test_user.username)).await;
+    assert_eq!(count_rows(&html, "login"), 1, "{}", html);
+
+    let html = app.get_audit_html("actor=somebody-else").await;
+    assert!(html.contains("No matching events"), "{}", html);
+
+    let html = app.get_audit_html("since=&until=2000-01-01T00:00").await;
+    assert!(html.contains("No matching events"), "{}", html);
+    let html = app.get_audit_html("since=2000-01-01T00:00&until=").await;
+    assert_eq!(count_rows(&html, "login"), 1, "{}", html);
+
+    let html = app.get_audit_html("since=yesterday").await;
+    assert!(html.contains("invalid time: yesterday"), "{}", html);
+});
+
+login_test!(audit_events_cannot_be_deleted, [app] {
+    assert!(app.delete_audit_events().await.is_err());
+    let html = app.get_audit_html("").await;
+    assert_eq!(count_rows(&html, "login"), 1, "{}", html);
+});
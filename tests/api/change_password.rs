@@ -1,7 +1,7 @@
 use uuid::Uuid;
 
 use super::helpers::assert_is_redirect_to;
-use crate::cookie_test;
+use crate::{cookie_test, login_test};
 
 cookie_test!(changing_password_works, [app] {
     let new_password = Uuid::new_v4().to_string();
@@ -73,7 +73,7 @@ cookie_test!(new_password_fields_must_match, [app]{
     assert_is_redirect_to(&resp, "/admin/password");
 
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>You entered two different new passwords - the field values must match</i></p>"), "{}", html_page);
+    assert!(html_page.contains(r#"<p class="flash flash-error"><i>You entered two different new passwords - the field values must match</i></p>"#), "{}", html_page);
 });
 
 cookie_test!(current_password_must_be_valid, [app]{
@@ -93,7 +93,7 @@ cookie_test!(current_password_must_be_valid, [app]{
 
     assert_is_redirect_to(&resp, "/admin/password");
     let html_page = app.get_change_password_html().await;
-    assert!(html_page.contains("<p><i>The current password is incorrect</i></p>"), "{}", html_page);
+    assert!(html_page.contains(r#"<p class="flash flash-error"><i>The current password is incorrect</i></p>"#), "{}", html_page);
 });
 
 cookie_test!(password_length_requirement, [app]{
@@ -127,3 +127,15 @@ cookie_test!(logout_clear_session_state, [app]{
     let resp = app.get_admin_dashboard().await;
     assert_is_redirect_to(&resp, "/login");
 });
+
+login_test!(common_passwords_are_rejected, [app] {
+    let resp = app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": "Password1234",
+        "new_password_check": "Password1234",
+    }))
+    .await;
+    assert_is_redirect_to(&resp, "/admin/password");
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page.contains(r#"<p class="flash flash-error"><i>The password is too common</i></p>"#), "{}", html_page);
+});
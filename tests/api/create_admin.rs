@@ -0,0 +1,54 @@
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{EntityTrait, PaginatorTrait, SqlxPostgresConnector};
+use sqlx::{Pool, Postgres};
+use zero2prod_api::{
+    auth::{bootstrap_admin, BootstrapOutcome},
+    configuration::PasswordHashSettings,
+    entities::user::Entity as Users,
+};
+
+use super::helpers::{register_test_user, TestUser};
+
+#[sqlx::test]
+async fn bootstrap_admin_is_idempotent(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    Migrator::refresh(&db).await?;
+
+    let outcome = bootstrap_admin(
+        &db,
+        "admin",
+        "a-strong-password",
+        &PasswordHashSettings::default(),
+    )
+    .await?;
+    assert_eq!(outcome, BootstrapOutcome::Created);
+    let outcome = bootstrap_admin(
+        &db,
+        "admin",
+        "a-strong-password",
+        &PasswordHashSettings::default(),
+    )
+    .await?;
+    assert_eq!(outcome, BootstrapOutcome::AlreadyExists);
+
+    assert_eq!(Users::find().count(&db).await?, 1);
+    Ok(())
+}
+
+#[sqlx::test]
+async fn bootstrap_admin_skipped_when_an_owner_exists(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    Migrator::refresh(&db).await?;
+    register_test_user(&db, &TestUser::generate()).await?;
+
+    let outcome = bootstrap_admin(
+        &db,
+        "admin",
+        "a-strong-password",
+        &PasswordHashSettings::default(),
+    )
+    .await?;
+    assert_eq!(outcome, BootstrapOutcome::AlreadyExists);
+    assert_eq!(Users::find().count(&db).await?, 1);
+    Ok(())
+}
@@ -0,0 +1,105 @@
+use uuid::Uuid;
+
+use super::helpers::assert_is_redirect_to;
+use crate::{cookie_test, login_test};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    })
+}
+
+login_test!(forms_embed_the_csrf_token, [app] {
+    let token = app.csrf_token_with(&app.cookie_cli).await;
+    let marker = format!(r#"name="csrf_token" value="{token}""#);
+    assert!(app.get_publish_newsletter_html().await.contains(&marker));
+    assert!(app.get_change_password_html().await.contains(&marker));
+    let dashboard = app.get_admin_dashboard().await.text().await?;
+    assert!(dashboard.contains(&marker), "{}", dashboard);
+});
+
+login_test!(publishing_without_a_csrf_token_is_rejected, [app] {
+    let resp = app
+        .post_form_without_csrf("/admin/newsletters", &newsletter_body())
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+});
+
+login_test!(a_token_of_another_session_is_rejected, [app] {
+    let other = app.another_browser("attacker");
+    let mut body = newsletter_body();
+    body["csrf_token"] = app.csrf_token_with(&other).await.into();
+    let resp = app.post_form_without_csrf("/admin/newsletters", &body).await;
+    assert_eq!(resp.status().as_u16(), 403);
+});
+
+login_test!(changing_the_password_without_a_csrf_token_is_rejected, [app] {
+    let new_password = Uuid::new_v4().to_string();
+    let resp = app
+        .post_form_without_csrf(
+            "/admin/password",
+            &serde_json::json!({
+                "current_password": &app.test_user.password,
+                "new_password": &new_password,
+                "new_password_check": &new_password,
+                "csrf_token": "forged",
+            }),
+        )
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+});
+
+login_test!(logging_out_without_a_csrf_token_is_rejected, [app] {
+    let resp = app
+        .post_form_without_csrf("/logout", &serde_json::json!({}))
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+});
+
+cookie_test!(logging_in_without_a_csrf_token_is_rejected, [app] {
+    let resp = app
+        .post_form_without_csrf(
+            "/login",
+            &serde_json::json!({
+                "username": &app.test_user.username,
+                "password": &app.test_user.password,
+            }),
+        )
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+});
+
+cookie_test!(the_token_changes_when_logging_in, [app] {
+    let before = app.csrf_token_with(&app.cookie_cli).await;
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await?;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    assert_ne!(app.csrf_token_with(&app.cookie_cli).await, before);
+});
+
+login_test!(the_token_can_be_sent_as_a_header, [app] {
+    let token = app.csrf_token_with(&app.cookie_cli).await;
+    let resp = app
+        .post_with_csrf_header("/admin/sessions/revoke_others", &token)
+        .await;
+    assert_is_redirect_to(&resp, "/admin/sessions");
+});
+
+login_test!(an_oversized_form_is_refused_before_it_is_read, [app] {
+    let mut body = newsletter_body();
+    // over the 1 MiB a form may take
+    body["html_content"] = "a".repeat(2 * 1024 * 1024).into();
+    let resp = app.post_form_without_csrf("/admin/newsletters", &body).await;
+    assert_eq!(resp.status().as_u16(), 413);
+    let resp = app.post_form_without_csrf("/login", &body).await;
+    assert_eq!(resp.status().as_u16(), 413);
+});
@@ -0,0 +1,187 @@
+use wiremock::{matchers::path, Mock, ResponseTemplate};
+
+use super::{
+    api_tokens::create_token,
+    helpers::{assert_is_redirect_to, flash_message},
+    subscribers::subscribe,
+};
+use crate::{cookie_test, login_test};
+
+login_test!(an_admin_exports_and_erases_a_subscriber_by_email, [app] {
+    let id = subscribe(&app, "alice", "Alice@example.com").await;
+    let read = create_token(&app, &["scope_subscribers_read"]).await;
+    let write = create_token(&app, &["scope_subscribers_write"]).await;
+    let subject = serde_json::json!({ "email": "alice@example.com" });
+
+    // the address is matched whatever its case
+    let resp = app.post_api_subscriber_action(&read, "data", &subject).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let data: serde_json::Value = resp.json().await?;
+    assert_eq!(data["subscriptions"][0]["id"], id.as_str());
+    assert_eq!(data["subscriptions"][0]["email"], "Alice@example.com");
+    assert_eq!(data["subscriptions"][0]["status"], "pending");
+    assert_eq!(data["subscriptions"][0]["has_confirmation_token"], true);
+
+    let resp = app.post_api_subscriber_action(&read, "erase", &subject).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let resp = app.post_api_subscriber_action(&write, "erase", &subject).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    let resp = app.post_api_subscriber_action(&read, "data", &subject).await;
+    assert_eq!(resp.status().as_u16(), 404);
+    assert!(!app.get_subscribers_html("").await.contains("example.com"));
+
+    // the tombstone keeps the address out of imports
+    let resp = app
+        .post_api_subscribers_import(&write, "ALICE@example.com,Alice\nbob@example.com,Bob\n", "")
+        .await;
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["errors"][0]["line"], 1);
+    assert_eq!(report["errors"][0]["error"], "the address was erased on request");
+
+    // anyone can subscribe an address, only its confirmation is a fresh consent
+    subscribe(&app, "alice", "alice@example.com").await;
+    let resp = app
+        .post_api_subscribers_import(&write, "alice@example.com,Alice\n", "")
+        .await;
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["errors"][0]["error"], "the address was erased on request");
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let link = app.get_confirmation_link(&email_request).html;
+    app.confirm_subscription(link.as_str()).await?;
+    let resp = app
+        .post_api_subscribers_import(&write, "alice@example.com,Alice\n", "")
+        .await;
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["skipped"], 1);
+    assert!(report["errors"].as_array().unwrap().is_empty());
+});
+
+login_test!(an_erased_address_stays_suppressed_under_its_hash, [app] {
+    subscribe(&app, "carol", "carol@example.com").await;
+    let resp = app
+        .post_suppressions("", &serde_json::json!({ "address": "carol@example.com", "reason": "carol asked" }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/suppressions");
+    let read = create_token(&app, &["scope_subscribers_read"]).await;
+    let write = create_token(&app, &["scope_subscribers_write"]).await;
+    let subject = serde_json::json!({ "email": "carol@example.com" });
+
+    let data: serde_json::Value = app
+        .post_api_subscriber_action(&read, "data", &subject)
+        .await
+        .json()
+        .await?;
+    assert_eq!(data["suppressions"][0]["reason"], "carol asked");
+    assert_eq!(data["admin_actions"][0]["action"], "suppression_added");
+
+    let resp = app.post_api_subscriber_action(&write, "erase", &subject).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    let html = app.get_suppressions_html().await;
+    assert!(!html.contains("<td>carol"), "{html}");
+    assert!(html.contains("the address was erased on request"), "{html}");
+    let data: serde_json::Value = app
+        .post_api_subscriber_action(&read, "data", &subject)
+        .await
+        .json()
+        .await?;
+    assert!(data["subscriptions"].as_array().unwrap().is_empty());
+    assert_eq!(data["suppressions"][0]["source"], "admin");
+
+    // subscribing again doesn't mail an address that was suppressed before its erasure
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_subscription("username=carol&email=Carol@example.com".to_owned())
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    // and removing the suppression by the address still works
+    let resp = app
+        .post_suppressions("/remove", &serde_json::json!({ "address": "carol@example.com" }))
+        .await;
+    assert_eq!(flash_message(&resp), "The suppression has been removed.");
+    assert!(!app.get_suppressions_html().await.contains("erased on request"));
+});
+
+login_test!(the_admin_pages_export_and_erase_a_subscriber, [app] {
+    let id = subscribe(&app, "carol", "carol@example.com").await;
+    let resp = app
+        .post_subscriber_action("data", &serde_json::json!({ "id": id }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(
+        resp.headers()["content-disposition"],
+        format!("attachment; filename=\"subscriber-{id}.json\"").as_str()
+    );
+    let data: serde_json::Value = resp.json().await?;
+    assert_eq!(data["email"], "carol@example.com");
+
+    let resp = app
+        .post_subscriber_action("erase", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(flash_message(&resp), "The subscriber's data has been erased.");
+    assert!(!app.get_subscribers_html("").await.contains("carol@example.com"));
+    for action in ["subscriber_data_exported", "subscriber_erased"] {
+        let audit = app.get_audit_html(&format!("action={action}")).await;
+        assert!(audit.contains(&id));
+    }
+});
+
+login_test!(a_subscriber_downloads_and_erases_their_own_data, [app] {
+    subscribe(&app, "dave", "dave@example.com").await;
+    // an unknown address gets the same answer and no email
+    let resp = app.post_data_request("nobody@example.com").await;
+    assert_eq!(resp.status().as_u16(), 202);
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = app.post_data_request("dave@example.com").await;
+    assert_eq!(resp.status().as_u16(), 202);
+    // the confirmation email of the subscription, then the data request one
+    let email_request = app.wait_for_emails(2).await.pop().unwrap();
+    let (data_link, erase_link) = app.get_data_request_links(&email_request);
+
+    let resp = app.cookie_cli.get(data_link.clone()).send().await?;
+    assert_eq!(resp.status().as_u16(), 200);
+    let data: serde_json::Value = resp.json().await?;
+    assert_eq!(data["subscriptions"][0]["name"], "dave");
+
+    let html = app.cookie_cli.get(erase_link.clone()).send().await?.text().await?;
+    assert!(html.contains("Erase my data"));
+    let token = erase_link.query_pairs().next().unwrap().1.into_owned();
+    let mut erase_form = erase_link.clone();
+    erase_form.set_query(None);
+    let resp = app
+        .cookie_cli
+        .post(erase_form)
+        .form(&[("token", token.as_str())])
+        .send()
+        .await?;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert!(resp.text().await?.contains("Your data has been erased."));
+    assert!(!app.get_subscribers_html("").await.contains("dave@example.com"));
+
+    // the token went with the subscriber
+    let resp = app.cookie_cli.get(data_link).send().await?;
+    assert_eq!(resp.status().as_u16(), 400);
+});
+
+cookie_test!(data_requests_are_throttled_by_address, [app] {
+    let email = format!("{}@example.com", uuid::Uuid::new_v4());
+    // config/test.yaml allows 3 requests per address within the window
+    for _ in 0..3 {
+        let resp = app.post_data_request(&email).await;
+        assert_eq!(resp.status().as_u16(), 202);
+    }
+    let resp = app.post_data_request(&email.to_uppercase()).await;
+    assert_eq!(resp.status().as_u16(), 429);
+    let resp = app.post_data_request("someone-else@example.com").await;
+    assert_eq!(resp.status().as_u16(), 202);
+});
@@ -0,0 +1,122 @@
+use migration::{Migrator, MigratorTrait};
+use sea_orm::{
+    sea_query::{Alias, IntoTableRef},
+    ActiveEnum, ConnectionTrait, DatabaseConnection, EntityTrait, Iterable, Schema,
+    SqlxPostgresConnector,
+};
+use sqlx::{Pool, Postgres};
+use zero2prod_api::entities::{prelude::*, sea_orm_active_enums::SubscriptionStatus};
+
+// the entities are turned back into tables in a schema of their own, both
+// schemas must then agree on every column, key and constraint
+const ENTITY_SCHEMA: &str = "entities";
+
+#[sqlx::test]
+async fn entities_match_the_migrated_schema(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool.clone());
+    Migrator::up(&db, None).await?;
+    db.execute_unprepared(&format!("CREATE SCHEMA {ENTITY_SCHEMA}"))
+        .await?;
+
+    create_table(&db, ApiTokens).await?;
+    create_table(&db, AuditEvents).await?;
+    create_table(&db, DataRequestTokens).await?;
+    create_table(&db, ErasedSubscribers).await?;
+    create_table(&db, Idempotency).await?;
+    create_table(&db, IssueDeliveryQueue).await?;
+    create_table(&db, NewsletterIssues).await?;
+    create_table(&db, PasswordResetTokens).await?;
+    create_table(&db, RecoveryCodes).await?;
+    create_table(&db, SubscriptionTokens).await?;
+    create_table(&db, Subscriptions).await?;
+    create_table(&db, Suppressions).await?;
+    create_table(&db, User).await?;
+
+    assert_eq!(
+        columns(&pool, ENTITY_SCHEMA).await?,
+        columns(&pool, "public").await?
+    );
+    assert_eq!(
+        constraints(&pool, ENTITY_SCHEMA).await?,
+        constraints(&pool, "public").await?
+    );
+    assert_eq!(
+        enum_labels(&pool, &SubscriptionStatus::name().to_string()).await?,
+        SubscriptionStatus::iter()
+            .map(|status| status.to_value())
+            .collect::<Vec<_>>()
+    );
+    Ok(())
+}
+
+async fn create_table<E: EntityTrait>(db: &DatabaseConnection, entity: E) -> anyhow::Result<()> {
+    let backend = db.get_database_backend();
+    let mut stmt = Schema::new(backend).create_table_from_entity(entity);
+    // foreign keys still point at the migrated tables, only their names are compared
+    stmt.table((Alias::new(ENTITY_SCHEMA), Alias::new(entity.table_name())).into_table_ref());
+    db.execute(backend.build(&stmt)).await?;
+    Ok(())
+}
+
+// (table, column, type, nullable)
+async fn columns(
+    pool: &Pool<Postgres>,
+    schema: &str,
+) -> sqlx::Result<Vec<(String, String, String, String)>> {
+    sqlx::query_as(
+        r#"
+        SELECT table_name::text, column_name::text, udt_name::text, is_nullable::text
+        FROM information_schema.columns
+        WHERE table_schema = $1 AND table_name <> 'seaql_migrations'
+        ORDER BY table_name, column_name
+        "#,
+    )
+    .bind(schema)
+    .fetch_all(pool)
+    .await
+}
+
+// (kind, table, columns, referenced table), constraint names differ between the two
+async fn constraints(
+    pool: &Pool<Postgres>,
+    schema: &str,
+) -> sqlx::Result<Vec<(String, String, Vec<String>, Option<String>)>> {
+    sqlx::query_as(
+        r#"
+        SELECT
+            c.contype::text,
+            source.relname::text,
+            ARRAY(
+                SELECT attname::text FROM pg_attribute
+                WHERE attrelid = c.conrelid AND attnum = ANY(c.conkey)
+                ORDER BY attname
+            ),
+            target.relname::text
+        FROM pg_constraint c
+        JOIN pg_class source ON source.oid = c.conrelid
+        JOIN pg_namespace n ON n.oid = source.relnamespace
+        LEFT JOIN pg_class target ON target.oid = c.confrelid
+        WHERE n.nspname = $1
+            AND c.contype IN ('p', 'u', 'f')
+            AND source.relname <> 'seaql_migrations'
+        ORDER BY 1, 2, 3
+        "#,
+    )
+    .bind(schema)
+    .fetch_all(pool)
+    .await
+}
+
+// in declaration order, which is also the order postgres sorts them in
+async fn enum_labels(pool: &Pool<Postgres>, name: &str) -> sqlx::Result<Vec<String>> {
+    sqlx::query_scalar(
+        r#"
+        SELECT enumlabel::text FROM pg_enum
+        WHERE enumtypid = $1::regtype
+        ORDER BY enumsortorder
+        "#,
+    )
+    .bind(name)
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,55 @@
+use poem::http::StatusCode;
+use uuid::Uuid;
+
+use super::helpers::{assert_is_redirect_to, post_subscription};
+use crate::{cookie_test, normal_test};
+
+cookie_test!(api_clients_get_problem_details, [app] {
+    let body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": "random-password",
+    });
+    let resp = app.post_form_accepting("/login", &body, "application/json").await;
+    assert_eq!(resp.status().as_u16(), 401);
+    assert_eq!(resp.headers()["Content-Type"], "application/problem+json");
+    let problem: serde_json::Value = resp.json().await?;
+    assert_eq!(problem["status"], 401);
+    assert_eq!(problem["code"], "invalid_credentials");
+    assert_eq!(problem["detail"], "Authentication failed");
+    assert_eq!(problem["instance"], "/login");
+    assert!(Uuid::parse_str(problem["correlation_id"].as_str().unwrap()).is_ok());
+    assert!(problem.get("internal").is_none(), "{}", problem);
+});
+
+cookie_test!(browsers_are_redirected_with_a_flash_message, [app] {
+    let body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": "random-password",
+    });
+    let resp = app
+        .post_form_accepting("/login", &body, "text/html,application/xhtml+xml,*/*;q=0.8")
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+cookie_test!(browsers_get_an_error_page_when_there_is_nowhere_to_go, [app] {
+    let resp = app.get_accepting("/no/such/page", "text/html").await;
+    assert_eq!(resp.status().as_u16(), 404);
+    let html = resp.text().await?;
+    assert!(html.contains(r#"<code id="correlation-id">"#), "{}", html);
+});
+
+cookie_test!(unknown_paths_are_problems_for_api_clients, [app] {
+    let resp = app.get_accepting("/no/such/page", "application/json").await;
+    assert_eq!(resp.status().as_u16(), 404);
+    let problem: serde_json::Value = resp.json().await?;
+    assert_eq!(problem["code"], "not_found");
+});
+
+normal_test!(invalid_subscriptions_have_a_stable_code, [app] {
+    let resp = post_subscription(&app.cli, "username=lzl&email=aaa").await;
+    resp.assert_status(StatusCode::BAD_REQUEST);
+    resp.assert_content_type("application/problem+json");
+    let problem = resp.json().await;
+    problem.value().object().get("code").assert_string("validation_failed");
+});
@@ -1,6 +1,8 @@
 use poem::{test::TestClient, Route};
 use zero2prod_api::routes::health::health_check;
 
+use crate::cookie_test;
+
 #[tokio::test]
 async fn test_health_check() {
     let app = Route::new().at("/health_check", health_check);
@@ -9,3 +11,40 @@ async fn test_health_check() {
     let resp = cli.get("/health_check").send().await;
     resp.assert_status_is_ok();
 }
+
+cookie_test!(ready_once_every_component_is_up, [app]{
+    app.worker_heartbeat.beat();
+    let resp = app.get_readiness().await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["status"], "up", "{report}");
+    for component in ["database", "redis", "migrations", "worker"] {
+        assert_eq!(report[component]["status"], "up", "{report}");
+        assert!(report[component]["latency_ms"].is_u64(), "{report}");
+    }
+});
+
+cookie_test!(not_ready_until_the_worker_has_started, [app]{
+    let resp = app.get_readiness().await;
+    assert_eq!(resp.status().as_u16(), 503);
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["status"], "down");
+    assert_eq!(report["worker"]["status"], "down");
+    assert_eq!(report["worker"]["detail"], "no heartbeat yet");
+    assert_eq!(report["database"]["status"], "up");
+});
+
+cookie_test!(not_ready_with_pending_migrations, [app]{
+    app.worker_heartbeat.beat();
+    app.rollback_last_migration().await?;
+    let resp = app.get_readiness().await;
+    assert_eq!(resp.status().as_u16(), 503);
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["migrations"]["status"], "down");
+    assert_eq!(report["migrations"]["detail"], "pending migrations: 1");
+});
+
+cookie_test!(liveness_does_not_depend_on_the_components, [app]{
+    let resp = app.get_accepting("/health/live", "application/json").await;
+    assert_eq!(resp.status().as_u16(), 200);
+});
@@ -4,11 +4,12 @@ use linkify::LinkFinder;
 use migration::{Migrator, MigratorTrait};
 use once_cell::sync::Lazy;
 use poem::{
+    endpoint::BoxEndpoint,
     listener::TcpListener,
     middleware::CookieJarManagerEndpoint,
-    session::{CookieConfig, RedisStorage, ServerSession, ServerSessionEndpoint},
+    session::{RedisStorage, ServerSession, ServerSessionEndpoint},
     test::{TestClient, TestResponse},
-    Body, EndpointExt, Route, Server,
+    Body, EndpointExt, Server,
 };
 use redis::{aio::ConnectionManager, Client};
 use sea_orm::{prelude::Uuid, DatabaseConnection, *};
@@ -17,12 +18,14 @@ use secrecy::ExposeSecret;
 use sqlx::{Pool, Postgres};
 use wiremock::MockServer;
 use zero2prod_api::{
-    configuration::get_test_configuration,
+    configuration::{
+        get_test_configuration, Configuration, PasswordHashSettings, TelemetrySettings,
+    },
     context::StateContext,
     domain::Email,
     email_client::EmailClient,
     get_email_client,
-    issue_delivery_worker::{try_execute_task, ExecutionOutcome},
+    issue_delivery_worker::{try_execute_task, ExecutionOutcome, WorkerHeartbeat},
     routes::default_route,
     setup_logger,
 };
@@ -44,6 +47,7 @@ pub fn email() -> Email {
 
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
+    #[allow(dead_code)]
     pub plain_text: reqwest::Url,
 }
 
@@ -65,17 +69,19 @@ pub fn get_first_link(text: &str, port: u16) -> String {
 static TRACING: Lazy<()> = Lazy::new(|| {
     let default_filter_level = "info";
     if std::env::var("TEST_LOG").is_ok() {
-        setup_logger(default_filter_level);
+        setup_logger(default_filter_level, &TelemetrySettings::default());
     }
 });
 
-type ClientType =
-    CookieJarManagerEndpoint<ServerSessionEndpoint<RedisStorage<ConnectionManager>, Route>>;
+type ClientType = CookieJarManagerEndpoint<
+    ServerSessionEndpoint<RedisStorage<ConnectionManager>, BoxEndpoint<'static>>,
+>;
 
 pub struct TestApp {
     pub cli: TestClient<ClientType>,
     pub db: DatabaseConnection,
     pub email_server: MockServer,
+    #[allow(dead_code)]
     pub test_user: TestUser,
 }
 
@@ -90,8 +96,9 @@ pub async fn get_test_app(pool: Pool<Postgres>) -> Result<TestApp> {
     context.db = db.clone();
 
     let client = Client::open(conf.redis_uri.expose_secret().clone())?;
+    let cookie_config = conf.session.cookie_config();
     let app = default_route(conf, context).await.with(ServerSession::new(
-        CookieConfig::default(),
+        cookie_config,
         RedisStorage::new(ConnectionManager::new(client).await?),
     ));
     let cli = TestClient::new(app);
@@ -131,6 +138,8 @@ pub async fn register_test_user(
         &test_user.username,
         &test_user.password,
         &test_user.salt,
+        // what every hash was created with before the parameters became configurable
+        &PasswordHashSettings::default(),
     )
     .await
 }
@@ -143,23 +152,143 @@ pub struct TestAppWithCookie {
     pub email_server: MockServer,
     pub email_client: EmailClient,
     port: u16,
+    // nothing runs the worker loop in the tests, beat it by hand
+    pub worker_heartbeat: WorkerHeartbeat,
 }
 
 impl TestAppWithCookie {
+    // the csrf token of the client's session, the login page embeds it like every other form
+    pub async fn csrf_token_with(&self, client: &reqwest::Client) -> String {
+        let html = client
+            .get(format!("{}/login", self.address))
+            .send()
+            .await
+            .expect("fail to get login page")
+            .text()
+            .await
+            .unwrap();
+        let marker = r#"name="csrf_token" value=""#;
+        let start = html.find(marker).expect("no csrf token in the login page") + marker.len();
+        let end = start + html[start..].find('"').unwrap();
+        html[start..end].to_owned()
+    }
+
+    // adds the token to a form body, the way the browser submits the hidden field
+    pub async fn with_csrf_token<Body>(
+        &self,
+        client: &reqwest::Client,
+        body: &Body,
+    ) -> serde_json::Value
+    where
+        Body: serde::Serialize,
+    {
+        let mut body = serde_json::to_value(body).unwrap();
+        body["csrf_token"] = self.csrf_token_with(client).await.into();
+        body
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> Result<reqwest::Response, reqwest::Error>
     where
         Body: serde::Serialize,
     {
         self.cookie_cli
             .post(format!("{}/login", self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+    }
+
+    // posts a form with its csrf token, announcing the content types the client accepts
+    pub async fn post_form_accepting<Body>(
+        &self,
+        path: &str,
+        body: &Body,
+        accept: &str,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}{}", self.address, path))
+            .header("Accept", accept)
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post the form")
+    }
+
+    pub async fn get_accepting(&self, path: &str, accept: &str) -> reqwest::Response {
+        self.cookie_cli
+            .get(format!("{}{}", self.address, path))
+            .header("Accept", accept)
+            .send()
+            .await
+            .expect("failed to get the page")
+    }
+
+    pub async fn get_metrics(&self) -> reqwest::Response {
+        self.get_metrics_with_token(Some("metrics-token")).await
+    }
+
+    pub async fn get_metrics_with_token(&self, token: Option<&str>) -> reqwest::Response {
+        let mut request = self.cookie_cli.get(format!("{}/metrics", self.address));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("failed to scrape the metrics")
+    }
+
+    pub async fn get_readiness(&self) -> reqwest::Response {
+        self.cookie_cli
+            .get(format!("{}/health/ready", self.address))
+            .send()
+            .await
+            .expect("failed to get the readiness report")
+    }
+
+    pub async fn rollback_last_migration(&self) -> Result<(), DbErr> {
+        Migrator::down(&self.db, Some(1)).await
+    }
+
+    pub async fn get_with_request_id(&self, path: &str, request_id: &str) -> reqwest::Response {
+        self.cookie_cli
+            .get(format!("{}{}", self.address, path))
+            .header("X-Request-Id", request_id)
+            .send()
+            .await
+            .expect("failed to get the page")
+    }
+
+    // posts a form as is, without adding the csrf token
+    pub async fn post_form_without_csrf<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}{}", self.address, path))
             .form(body)
             .send()
             .await
+            .expect("failed to post the form")
+    }
+
+    pub async fn post_with_csrf_header(&self, path: &str, token: &str) -> reqwest::Response {
+        self.cookie_cli
+            .post(format!("{}{}", self.address, path))
+            .header("X-CSRF-Token", token)
+            .send()
+            .await
+            .expect("failed to post")
     }
 
     pub async fn post_logout(&self) -> Result<reqwest::Response, reqwest::Error> {
         self.cookie_cli
             .post(format!("{}/logout", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&self.cookie_cli, &serde_json::json!({}))
+                    .await,
+            )
             .send()
             .await
     }
@@ -174,6 +303,18 @@ impl TestAppWithCookie {
         resp.text().await.expect("fail to get resp in text")
     }
 
+    // the login page as seen by a request carrying the given raw cookie header
+    pub async fn get_login_html_with_cookie(&self, cookie: &str) -> String {
+        let resp = self
+            .cookie_cli
+            .get(format!("{}/login", self.address))
+            .header("Cookie", cookie)
+            .send()
+            .await
+            .expect("fail to get login page");
+        resp.text().await.expect("fail to get resp in text")
+    }
+
     pub async fn get_admin_dashboard(&self) -> reqwest::Response {
         self.cookie_cli
             .get(format!("{}/admin/dashboard", self.address))
@@ -182,6 +323,443 @@ impl TestAppWithCookie {
             .expect("fail to get admin dashboard in test")
     }
 
+    pub async fn get_lockouts_html(&self) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/lockouts", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/lockouts")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_clear_lockout<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/lockouts/clear", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post lockout clearing")
+    }
+
+    pub async fn get_two_factor_html(&self) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/2fa", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/2fa")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_enable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/2fa/enable", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post 2fa enabling")
+    }
+
+    pub async fn post_disable_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/2fa/disable", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post 2fa disabling")
+    }
+
+    pub async fn post_login_two_factor<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/login/2fa", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post the second factor")
+    }
+
+    pub async fn post_change_email<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/email", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post email change")
+    }
+
+    pub async fn post_forgot_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/login/forgot", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post forgot password")
+    }
+
+    pub async fn post_reset_password<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/login/reset", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post password reset")
+    }
+
+    pub async fn stored_password_hash(&self) -> String {
+        zero2prod_api::entities::user::Entity::find()
+            .filter(zero2prod_api::entities::user::Column::UserName.eq(&self.test_user.username))
+            .one(&self.db)
+            .await
+            .unwrap()
+            .expect("the test user is missing")
+            .password_hashed
+    }
+
+    pub async fn get_tokens_html(&self) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/tokens", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/tokens")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_create_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/tokens", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post token creation")
+    }
+
+    pub async fn post_revoke_token<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/tokens/revoke", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post token revocation")
+    }
+
+    // bearer requests go through a client without the session cookie
+    pub async fn get_api_me(&self, token: Option<&str>) -> reqwest::Response {
+        let mut req = reqwest::Client::new().get(format!("{}/api/v1/admin/me", &self.address));
+        if let Some(token) = token {
+            req = req.bearer_auth(token);
+        }
+        req.send().await.expect("failed to get /api/v1/admin/me")
+    }
+
+    pub async fn post_api_newsletters<Body>(&self, token: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        reqwest::Client::new()
+            .post(format!("{}/api/v1/admin/newsletters", &self.address))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .expect("failed to post /api/v1/admin/newsletters")
+    }
+
+    pub async fn get_subscribers_html(&self, query: &str) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/subscribers?{}", &self.address, query))
+            .send()
+            .await
+            .expect("failed to get /admin/subscribers")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    // `action` is one of confirm, unsubscribe, delete and resend
+    pub async fn post_subscriber_action<Body>(&self, action: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/subscribers/{}", &self.address, action))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post a subscriber action")
+    }
+
+    pub async fn get_api_subscribers(&self, token: &str, query: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!(
+                "{}/api/v1/admin/subscribers?{}",
+                &self.address, query
+            ))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("failed to get /api/v1/admin/subscribers")
+    }
+
+    pub async fn post_api_subscriber_action<Body>(
+        &self,
+        token: &str,
+        action: &str,
+        body: &Body,
+    ) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/api/v1/admin/subscribers/{}",
+                &self.address, action
+            ))
+            .bearer_auth(token)
+            .json(body)
+            .send()
+            .await
+            .expect("failed to post a subscriber action to the json api")
+    }
+
+    // the upload form, reqwest is built without multipart support so the body is written by hand
+    pub async fn post_subscribers_import(
+        &self,
+        csv: &str,
+        send_confirmation: bool,
+    ) -> reqwest::Response {
+        let boundary = "zero2prod-import-boundary";
+        let mut fields = vec![(
+            r#"name="csrf_token""#.to_owned(),
+            self.csrf_token_with(&self.cookie_cli).await,
+        )];
+        if send_confirmation {
+            fields.push((r#"name="send_confirmation""#.to_owned(), "on".to_owned()));
+        }
+        fields.push((
+            "name=\"file\"; filename=\"subscribers.csv\"\r\nContent-Type: text/csv".to_owned(),
+            csv.to_owned(),
+        ));
+        let mut body = String::new();
+        for (disposition, value) in fields {
+            body.push_str(&format!(
+                "--{boundary}\r\nContent-Disposition: form-data; {disposition}\r\n\r\n{value}\r\n"
+            ));
+        }
+        body.push_str(&format!("--{boundary}--\r\n"));
+        self.cookie_cli
+            .post(format!("{}/admin/subscribers/import", &self.address))
+            .header(
+                "Content-Type",
+                format!("multipart/form-data; boundary={boundary}"),
+            )
+            .body(body)
+            .send()
+            .await
+            .expect("failed to post /admin/subscribers/import")
+    }
+
+    pub async fn get_subscribers_export(&self) -> reqwest::Response {
+        self.cookie_cli
+            .get(format!("{}/admin/subscribers/export", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/subscribers/export")
+    }
+
+    pub async fn post_api_subscribers_import(
+        &self,
+        token: &str,
+        csv: &str,
+        query: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!(
+                "{}/api/v1/admin/subscribers/import?{}",
+                &self.address, query
+            ))
+            .bearer_auth(token)
+            .header("Content-Type", "text/csv")
+            .body(csv.to_owned())
+            .send()
+            .await
+            .expect("failed to post /api/v1/admin/subscribers/import")
+    }
+
+    pub async fn get_api_subscribers_export(&self, token: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .get(format!("{}/api/v1/admin/subscribers/export", &self.address))
+            .bearer_auth(token)
+            .send()
+            .await
+            .expect("failed to get /api/v1/admin/subscribers/export")
+    }
+
+    pub async fn get_suppressions_html(&self) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/suppressions", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/suppressions")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    // `path` is empty to add an entry and `/remove` to remove one
+    pub async fn post_suppressions<Body>(&self, path: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/suppressions{}", &self.address, path))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post /admin/suppressions")
+    }
+
+    pub async fn post_email_webhook(
+        &self,
+        event: &serde_json::Value,
+        credentials: Option<(&str, &str)>,
+    ) -> reqwest::Response {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/webhooks/email", &self.address))
+            .json(event);
+        if let Some((username, password)) = credentials {
+            request = request.basic_auth(username, Some(password));
+        }
+        request
+            .send()
+            .await
+            .expect("failed to post /webhooks/email")
+    }
+
+    pub fn another_browser(&self, user_agent: &str) -> reqwest::Client {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .user_agent(user_agent)
+            .build()
+            .unwrap()
+    }
+
+    pub async fn login_with(&self, client: &reqwest::Client) -> reqwest::Response {
+        let body = serde_json::json!({
+            "username": self.test_user.username,
+            "password": self.test_user.password,
+        });
+        client
+            .post(format!("{}/login", self.address))
+            .form(&self.with_csrf_token(client, &body).await)
+            .send()
+            .await
+            .expect("fail to login in test")
+    }
+
+    pub async fn post_logout_with(&self, client: &reqwest::Client) -> reqwest::Response {
+        client
+            .post(format!("{}/logout", &self.address))
+            .form(&self.with_csrf_token(client, &serde_json::json!({})).await)
+            .send()
+            .await
+            .expect("fail to logout in test")
+    }
+
+    pub async fn get_admin_dashboard_with(&self, client: &reqwest::Client) -> reqwest::Response {
+        client
+            .get(format!("{}/admin/dashboard", self.address))
+            .send()
+            .await
+            .expect("fail to get admin dashboard in test")
+    }
+
+    pub async fn get_audit_html(&self, query: &str) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/audit?{}", &self.address, query))
+            .send()
+            .await
+            .expect("failed to get /admin/audit")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn delete_audit_events(&self) -> Result<(), DbErr> {
+        zero2prod_api::entities::audit_events::Entity::delete_many()
+            .exec(&self.db)
+            .await
+            .map(|_| ())
+    }
+
+    pub async fn get_sessions_html(&self) -> String {
+        self.cookie_cli
+            .get(format!("{}/admin/sessions", &self.address))
+            .send()
+            .await
+            .expect("failed to get /admin/sessions")
+            .text()
+            .await
+            .unwrap()
+    }
+
+    pub async fn post_revoke_session<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.cookie_cli
+            .post(format!("{}/admin/sessions/revoke", &self.address))
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
+            .send()
+            .await
+            .expect("failed to post session revocation")
+    }
+
+    pub async fn post_revoke_other_sessions(&self) -> reqwest::Response {
+        self.cookie_cli
+            .post(format!("{}/admin/sessions/revoke_others", &self.address))
+            .form(
+                &self
+                    .with_csrf_token(&self.cookie_cli, &serde_json::json!({}))
+                    .await,
+            )
+            .send()
+            .await
+            .expect("failed to post other sessions revocation")
+    }
+
     pub async fn get_change_password(&self) -> reqwest::Response {
         self.cookie_cli
             .get(format!("{}/admin/password", &self.address))
@@ -196,7 +774,7 @@ impl TestAppWithCookie {
     {
         self.cookie_cli
             .post(format!("{}/admin/password", &self.address))
-            .form(body)
+            .form(&self.with_csrf_token(&self.cookie_cli, body).await)
             .send()
             .await
             .expect("failed to post password change")
@@ -212,7 +790,7 @@ impl TestAppWithCookie {
     {
         self.cookie_cli
             .post(format!("{}/admin/newsletters", &self.address))
-            .form(&body)
+            .form(&self.with_csrf_token(&self.cookie_cli, &body).await)
             .send()
             .await
             .expect("fail to send resp to post newsletters")
@@ -247,6 +825,21 @@ impl TestAppWithCookie {
             .expect("fail to subscribe")
     }
 
+    pub async fn post_subscription_with_request_id(
+        &self,
+        data: &'static str,
+        request_id: &str,
+    ) -> reqwest::Response {
+        self.cookie_cli
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Request-Id", request_id)
+            .body(data)
+            .send()
+            .await
+            .expect("fail to subscribe")
+    }
+
     pub async fn confirm_subscription(&self, url: &str) -> Result<()> {
         let resp = self
             .cookie_cli
@@ -267,6 +860,46 @@ impl TestAppWithCookie {
         ConfirmationLinks { html, plain_text }
     }
 
+    // the emails sent in the background, once `count` of them arrived
+    pub async fn wait_for_emails(&self, count: usize) -> Vec<wiremock::Request> {
+        for _ in 0..50 {
+            let received = self.email_server.received_requests().await.unwrap();
+            if received.len() >= count {
+                return received;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        }
+        panic!("{count} emails were never sent");
+    }
+
+    pub async fn post_data_request(&self, email: &str) -> reqwest::Response {
+        self.cookie_cli
+            .post(format!("{}/subscriptions/data", &self.address))
+            .form(&[("email", email)])
+            .send()
+            .await
+            .expect("failed to post /subscriptions/data")
+    }
+
+    // the download link and the erase link, in that order
+    pub fn get_data_request_links(
+        &self,
+        email_request: &wiremock::Request,
+    ) -> (reqwest::Url, reqwest::Url) {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let mut links = LinkFinder::new()
+            .links(body["TextBody"].as_str().unwrap())
+            .filter(|l| *l.kind() == linkify::LinkKind::Url)
+            .map(|l| {
+                let mut link = reqwest::Url::parse(l.as_str()).unwrap();
+                link.set_port(Some(self.port)).unwrap();
+                link
+            });
+        let data = links.next().expect("no download link");
+        let erase = links.next().expect("no erase link");
+        (data, erase)
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
             if let ExecutionOutcome::EmptyQueue = try_execute_task(&self.db, &self.email_client)
@@ -280,19 +913,30 @@ impl TestAppWithCookie {
 }
 
 pub async fn get_test_app_with_cookie(pool: Pool<Postgres>) -> Result<TestAppWithCookie> {
+    get_test_app_with_cookie_and_config(pool, |_| {}).await
+}
+
+// for tests that need settings other than config/test.yaml, e.g. short session timeouts
+pub async fn get_test_app_with_cookie_and_config(
+    pool: Pool<Postgres>,
+    customize: impl FnOnce(&mut Configuration),
+) -> Result<TestAppWithCookie> {
     Lazy::force(&TRACING);
     let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
     Migrator::refresh(&db).await?;
     let email_server = MockServer::start().await;
     let mut conf = get_test_configuration("config/test").expect("fail to get conf");
     conf.email_client.api_base_url = email_server.uri();
+    customize(&mut conf);
     let email_client = get_email_client(conf.email_client.clone())?;
     let mut context = StateContext::new(conf.clone()).await?;
     context.db = db.clone();
+    let worker_heartbeat = context.worker_heartbeat.clone();
 
     let client = Client::open(conf.redis_uri.expose_secret().clone())?;
+    let cookie_config = conf.session.cookie_config();
     let app = default_route(conf, context).await.with(ServerSession::new(
-        CookieConfig::default(),
+        cookie_config,
         RedisStorage::new(ConnectionManager::new(client).await?),
     ));
     let app_port = std::net::TcpListener::bind(format!("127.0.0.1:{}", 0))?
@@ -323,10 +967,36 @@ pub async fn get_test_app_with_cookie(pool: Pool<Postgres>) -> Result<TestAppWit
         email_server,
         test_user,
         port: app_port,
+        worker_heartbeat,
     })
 }
 
+// the `X-Request-Id` an outbound email call was made with
+pub fn request_id_of(email_request: &wiremock::Request) -> Option<String> {
+    email_request
+        .headers
+        .iter()
+        .find(|(name, _)| name.as_str().eq_ignore_ascii_case("X-Request-Id"))
+        .map(|(_, values)| values.as_str().to_owned())
+}
+
 pub fn assert_is_redirect_to(resp: &reqwest::Response, uri: &str) {
     assert_eq!(resp.status().as_u16(), 303, "{}", uri);
     assert_eq!(resp.headers().get::<&str>("Location").unwrap(), uri);
 }
+
+// the text of the signed flash message the response sets
+pub fn flash_message(resp: &reqwest::Response) -> String {
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+    let cookie = resp
+        .cookies()
+        .find(|cookie| cookie.name() == "_flash")
+        .expect("no flash message is set");
+    let message = cookie
+        .value()
+        .split('.')
+        .nth(1)
+        .expect("the flash message isn't signed");
+    String::from_utf8(URL_SAFE_NO_PAD.decode(message).unwrap()).unwrap()
+}
@@ -1,7 +1,8 @@
 use anyhow::Context;
+use futures_util::future::join_all;
 
-use super::helpers::assert_is_redirect_to;
-use crate::cookie_test;
+use super::helpers::{assert_is_redirect_to, flash_message};
+use crate::{cookie_test, login_test};
 
 cookie_test!(an_error_flash_message_is_set_on_failure, [app] {
     let body = serde_json::json!(
@@ -12,11 +13,10 @@ cookie_test!(an_error_flash_message_is_set_on_failure, [app] {
     );
     let resp = app.post_login(&body).await?;
     assert_is_redirect_to(&resp, "/login");
-    let flash_cookie = resp.cookies().find(|c| c.name() == "_flash").unwrap();
-    assert_eq!(flash_cookie.value(), "Authentication failed");
+    assert_eq!(flash_message(&resp), "Authentication failed");
     let html_page = app.get_login_html().await;
     assert!(
-        html_page.contains(r#"<p><i>Authentication failed</i></p>"#),
+        html_page.contains(r#"<p class="flash flash-error"><i>Authentication failed</i></p>"#),
         "{}",
         html_page
     );
@@ -24,7 +24,7 @@ cookie_test!(an_error_flash_message_is_set_on_failure, [app] {
     tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
     let html_page = app.get_login_html().await;
     assert!(
-        !html_page.contains(r#"<p><i>Authentication failed</i></p>"#),
+        !html_page.contains(r#"<p class="flash flash-error"><i>Authentication failed</i></p>"#),
         "{}",
         html_page
     );
@@ -46,3 +46,107 @@ cookie_test!(redirect_to_admin_dashboard_after_login, [app] {
         html_page
     );
 });
+
+cookie_test!(repeated_failures_lock_the_account_out, [app] {
+    let wrong_body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": "random-password",
+    });
+    // config/test.yaml locks an account out after 5 failures
+    for _ in 0..5 {
+        let resp = app.post_login(&wrong_body).await?;
+        assert_is_redirect_to(&resp, "/login");
+    }
+
+    let body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    });
+    let resp = app.post_login(&body).await?;
+    assert_is_redirect_to(&resp, "/login");
+    assert_eq!(flash_message(&resp), "Too many failed login attempts, please try again later");
+});
+
+cookie_test!(concurrent_attempts_cannot_outrun_the_lockout, [app] {
+    let wrong_body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": "random-password",
+    });
+    let attempts = join_all((0..20).map(|_| app.post_login(&wrong_body))).await;
+    let checked = attempts
+        .into_iter()
+        .map(|resp| resp.map(|resp| flash_message(&resp)))
+        .collect::<Result<Vec<_>, _>>()?
+        .into_iter()
+        .filter(|flash| flash == "Authentication failed")
+        .count();
+    // config/test.yaml locks an account out after 5 failures
+    assert_eq!(checked, 5);
+});
+
+cookie_test!(lockouts_are_visible_to_admins, [app] {
+    let locked_username = uuid::Uuid::new_v4().to_string();
+    let wrong_body = serde_json::json!({
+        "username": locked_username,
+        "password": "random-password",
+    });
+    for _ in 0..5 {
+        app.post_login(&wrong_body).await?;
+    }
+
+    let body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    });
+    let resp = app.post_login(&body).await?;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let html_page = app.get_lockouts_html().await;
+    assert!(html_page.contains(&locked_username), "{}", html_page);
+
+    let resp = app.post_clear_lockout(&serde_json::json!({
+        "kind": "user",
+        "subject": locked_username,
+    })).await;
+    assert_is_redirect_to(&resp, "/admin/lockouts");
+    let html_page = app.get_lockouts_html().await;
+    assert!(!html_page.contains(&locked_username), "{}", html_page);
+});
+
+cookie_test!(outdated_password_hashes_are_upgraded_on_login, [app] {
+    // the test user is hashed with the default parameters, config/test.yaml uses cheaper ones
+    let before = app.stored_password_hash().await;
+    assert!(before.contains("m=15000,t=2,p=1"), "{}", before);
+    let body = serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    });
+    let resp = app.post_login(&body).await?;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let after = app.stored_password_hash().await;
+    assert!(after.contains("m=4096,t=1,p=1"), "{}", after);
+
+    // the upgraded hash still works
+    app.post_logout().await?;
+    let resp = app.post_login(&body).await?;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+});
+
+cookie_test!(forged_flash_messages_are_not_rendered, [app] {
+    let html_page = app
+        .get_login_html_with_cookie("_flash=<script>alert(1)</script>")
+        .await;
+    assert!(!html_page.contains("<script>"), "{}", html_page);
+    assert!(!html_page.contains(r#"class="flash"#), "{}", html_page);
+});
+
+login_test!(logging_out_shows_an_info_message, [app] {
+    let resp = app.post_logout().await?;
+    assert_is_redirect_to(&resp, "/login");
+    assert_eq!(flash_message(&resp), "You have successfully logged out.");
+    let html_page = app.get_login_html().await;
+    assert!(
+        html_page.contains(r#"<p class="flash flash-info"><i>You have successfully logged out.</i></p>"#),
+        "{}",
+        html_page
+    );
+});
@@ -0,0 +1,84 @@
+use super::helpers::get_test_app_with_cookie_and_config;
+use crate::cookie_test;
+
+cookie_test!(metrics_are_exposed_in_the_prometheus_format, [app]{
+    let body = serde_json::json!({
+        "username": "nobody",
+        "password": "wrong-password",
+    });
+    app.post_login(&body).await?;
+    app.get_login_html().await;
+
+    let resp = app.get_metrics().await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert!(resp.headers()["Content-Type"].to_str()?.starts_with("text/plain"));
+    let metrics = resp.text().await?;
+    // the recorder is shared by every test of the process, so only check what this one added
+    for expected in [
+        r#"login_attempts_total{step="password",outcome="failure"}"#,
+        r#"http_requests_total{method="GET",route="/login",status="200"}"#,
+        r#"http_request_duration_seconds_bucket{method="POST",route="/login""#,
+        "issue_delivery_queue_depth ",
+        r#"db_pool_connections{state="idle"}"#,
+        r#"db_pool_connections{state="in_use"}"#,
+    ] {
+        assert!(metrics.contains(expected), "{expected} is missing from\n{metrics}");
+    }
+});
+
+cookie_test!(unknown_paths_share_one_route_label, [app]{
+    app.get_accepting("/wp-login.php", "text/html").await;
+    let metrics = app.get_metrics().await.text().await?;
+    assert!(metrics.contains(r#"route="unmatched""#), "{metrics}");
+    assert!(!metrics.contains("wp-login"), "{metrics}");
+});
+
+cookie_test!(route_labels_are_the_route_templates, [app]{
+    let id = uuid::Uuid::new_v4();
+    for path in [
+        format!("/subscriptions/confirm?token={id}"),
+        format!("/admin/{id}"),
+        format!("/admin/docs/{id}"),
+        format!("/subscriptions/{id}"),
+    ] {
+        app.get_accepting(&path, "text/html").await;
+    }
+    // the csrf check answers before the admin api looks at the path
+    let resp = app
+        .post_form_without_csrf(&format!("/admin/{id}"), &serde_json::json!({}))
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let metrics = app.get_metrics().await.text().await?;
+    assert!(metrics.contains(r#"route="/subscriptions/confirm""#), "{metrics}");
+    assert!(!metrics.contains(&id.to_string()), "{metrics}");
+});
+
+cookie_test!(metrics_need_the_token_on_the_public_port, [app]{
+    for token in [None, Some("wrong-token")] {
+        let resp = app.get_metrics_with_token(token).await;
+        assert_eq!(resp.status().as_u16(), 401);
+        assert_eq!(resp.headers()["WWW-Authenticate"], "Bearer");
+    }
+});
+
+#[sqlx::test]
+async fn metrics_are_not_served_unless_enabled(
+    pool: sqlx::Pool<sqlx::Postgres>,
+) -> anyhow::Result<()> {
+    let app = get_test_app_with_cookie_and_config(pool, |conf| {
+        conf.metrics.enabled = false;
+    })
+    .await?;
+    assert_eq!(app.get_metrics().await.status().as_u16(), 404);
+    Ok(())
+}
+
+#[sqlx::test]
+async fn metrics_move_to_their_own_port(pool: sqlx::Pool<sqlx::Postgres>) -> anyhow::Result<()> {
+    let app = get_test_app_with_cookie_and_config(pool, |conf| {
+        conf.metrics.port = Some(9464);
+    })
+    .await?;
+    assert_eq!(app.get_metrics().await.status().as_u16(), 404);
+    Ok(())
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use migration::{Migrator, MigratorTrait};
+use sea_orm::SqlxPostgresConnector;
+use sqlx::{postgres::PgPoolOptions, Pool, Postgres};
+use zero2prod_api::{configuration::MigrationMode, pending_migrations, prepare_schema};
+
+#[sqlx::test]
+async fn verify_refuses_a_schema_that_is_behind(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    Migrator::up(&db, None).await?;
+    Migrator::down(&db, Some(1)).await?;
+
+    let err = prepare_schema(&db, MigrationMode::Verify)
+        .await
+        .expect_err("a schema with a pending migration must be refused");
+    let last = Migrator::migrations().last().unwrap().name().to_owned();
+    assert!(err.to_string().contains(&last), "{err}");
+
+    Migrator::up(&db, None).await?;
+    prepare_schema(&db, MigrationMode::Verify).await?;
+    Ok(())
+}
+
+#[sqlx::test]
+async fn apply_migrates_an_empty_database(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    assert_eq!(
+        pending_migrations(&db).await?.len(),
+        Migrator::migrations().len()
+    );
+
+    prepare_schema(&db, MigrationMode::Apply).await?;
+    assert!(pending_migrations(&db).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test]
+async fn replicas_starting_together_take_turns(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(pool);
+    let (first, second, third) = tokio::join!(
+        prepare_schema(&db, MigrationMode::Apply),
+        prepare_schema(&db, MigrationMode::Apply),
+        prepare_schema(&db, MigrationMode::Apply),
+    );
+    first?;
+    second?;
+    third?;
+    assert!(pending_migrations(&db).await?.is_empty());
+    Ok(())
+}
+
+#[sqlx::test]
+async fn one_connection_is_enough_to_apply(pool: Pool<Postgres>) -> anyhow::Result<()> {
+    let single = PgPoolOptions::new()
+        .max_connections(1)
+        .connect_with(pool.connect_options().clone())
+        .await?;
+    let db = SqlxPostgresConnector::from_sqlx_postgres_pool(single);
+    tokio::time::timeout(
+        Duration::from_secs(30),
+        prepare_schema(&db, MigrationMode::Apply),
+    )
+    .await??;
+    assert!(pending_migrations(&db).await?.is_empty());
+    Ok(())
+}
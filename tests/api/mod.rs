@@ -1,8 +1,24 @@
 mod admin_dashboard;
+mod api_tokens;
+mod audit;
 mod change_password;
+mod create_admin;
+mod csrf;
+mod data_requests;
+mod entities;
+mod errors;
 mod health_check;
 mod helpers;
 mod login;
+mod metrics;
+mod migrations;
 mod newsletter;
+mod password_reset;
+mod request_id;
+mod sessions;
+mod subscribers;
 mod subscriptions;
 mod subscriptions_confirm;
+mod suppressions;
+mod two_factor;
+mod webhooks;
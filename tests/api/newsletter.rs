@@ -8,7 +8,7 @@ use wiremock::{
     Mock, ResponseTemplate,
 };
 
-use super::helpers::{assert_is_redirect_to, ConfirmationLinks, TestAppWithCookie};
+use super::helpers::{assert_is_redirect_to, request_id_of, ConfirmationLinks, TestAppWithCookie};
 use crate::{cookie_test, login_test};
 
 //fn when_sending_an_email() -> MockBuilder {
@@ -137,6 +137,27 @@ login_test!(newsletters_delivered_to_confirmed_subscribers, [app]{
     app.dispatch_all_pending_emails().await;
 });
 
+login_test!(deliveries_carry_the_request_id_of_the_publish, [app]{
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let request = serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let resp = app.post_newsletters(request).await;
+    let request_id = resp.headers()["X-Request-Id"].to_str()?.to_owned();
+    app.dispatch_all_pending_emails().await;
+
+    let delivery = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    assert_eq!(request_id_of(&delivery), Some(request_id));
+});
+
 login_test!(newsletter_creation_is_idempotent, [app]{
     create_confirmed_subscriber(&app).await;
     Mock::given(path("/email"))
@@ -188,7 +209,7 @@ async fn create_unconfirmed_subscriber(app: &TestAppWithCookie) -> ConfirmationL
     let email: String = SafeEmail().fake();
     let resp = app
         .post_subscription(
-            serde_urlencoded::to_string(&serde_json::json!({
+            serde_urlencoded::to_string(serde_json::json!({
                 "username": username,
                 "email": email,
             }))
@@ -0,0 +1,198 @@
+use wiremock::{matchers::path, Mock, ResponseTemplate};
+
+use super::helpers::{assert_is_redirect_to, flash_message, TestAppWithCookie};
+use crate::{cookie_test, login_test};
+
+// asks for a reset link and returns the token it carries
+async fn request_reset_token(app: &TestAppWithCookie) -> String {
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let sent = app.email_server.received_requests().await.unwrap().len();
+    let resp = app
+        .post_forgot_password(&serde_json::json!({ "username": app.test_user.username }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+    // the link is mailed off the request
+    let email_request = &app.wait_for_emails(sent + 1).await[sent];
+    let link = app.get_confirmation_link(email_request).html;
+    assert_eq!(link.path(), "/login/reset");
+    let resp = app.cookie_cli.get(link.clone()).send().await.unwrap();
+    assert_eq!(resp.status().as_u16(), 200);
+    link.query_pairs()
+        .find(|(k, _)| k == "token")
+        .map(|(_, v)| v.into_owned())
+        .unwrap()
+}
+
+async fn set_email(app: &TestAppWithCookie) {
+    let resp = app
+        .post_change_email(&serde_json::json!({
+            "email": "admin@example.com",
+            "current_password": app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/email");
+    assert_eq!(flash_message(&resp), "Your email has been changed.");
+}
+
+login_test!(a_reset_link_sets_a_new_password, [app] {
+    set_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let resp = app
+        .post_reset_password(&serde_json::json!({
+            "token": token,
+            "new_password": new_password,
+            "new_password_check": new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+
+    // the old password is gone
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await?;
+    assert_is_redirect_to(&resp, "/login");
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": new_password,
+        }))
+        .await?;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+});
+
+login_test!(a_reset_invalidates_existing_sessions, [app] {
+    set_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    app.post_reset_password(&serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    }))
+    .await;
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+login_test!(a_reset_link_can_only_be_used_once, [app] {
+    set_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let body = serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    });
+    let resp = app.post_reset_password(&body).await;
+    assert_is_redirect_to(&resp, "/login");
+    let resp = app.post_reset_password(&body).await;
+    assert_is_redirect_to(&resp, "/login/forgot");
+});
+
+login_test!(the_new_password_must_be_long_enough, [app] {
+    set_email(&app).await;
+    let token = request_reset_token(&app).await;
+    let resp = app
+        .post_reset_password(&serde_json::json!({
+            "token": token,
+            "new_password": "short",
+            "new_password_check": "short",
+        }))
+        .await;
+    assert_is_redirect_to(&resp, &format!("/login/reset?token={token}"));
+    assert_eq!(flash_message(&resp), "The password length must be between 12 to 128");
+});
+
+cookie_test!(unknown_usernames_get_the_same_answer_without_an_email, [app] {
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_forgot_password(&serde_json::json!({ "username": uuid::Uuid::new_v4().to_string() }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+    // the test user has no email address yet
+    let resp = app
+        .post_forgot_password(&serde_json::json!({ "username": app.test_user.username }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+login_test!(only_the_latest_reset_link_works, [app] {
+    set_email(&app).await;
+    let first = request_reset_token(&app).await;
+    let second = request_reset_token(&app).await;
+    let new_password = uuid::Uuid::new_v4().to_string();
+    let body = |token: &str| serde_json::json!({
+        "token": token,
+        "new_password": new_password,
+        "new_password_check": new_password,
+    });
+    let resp = app.post_reset_password(&body(&first)).await;
+    assert_is_redirect_to(&resp, "/login/forgot");
+    let resp = app.post_reset_password(&body(&second)).await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+cookie_test!(reset_requests_are_throttled_per_username, [app] {
+    // an unknown username is counted like a known one
+    let unknown = serde_json::json!({ "username": uuid::Uuid::new_v4().to_string() });
+    for _ in 0..3 {
+        let resp = app.post_forgot_password(&unknown).await;
+        assert_is_redirect_to(&resp, "/login");
+    }
+    let resp = app.post_forgot_password(&unknown).await;
+    assert_is_redirect_to(&resp, "/login/forgot");
+    assert_eq!(
+        flash_message(&resp),
+        "Too many password reset requests, please try again later"
+    );
+    let resp = app
+        .post_forgot_password(&serde_json::json!({ "username": app.test_user.username }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+login_test!(changing_the_email_needs_the_current_password, [app] {
+    let resp = app
+        .post_change_email(&serde_json::json!({
+            "email": "intruder@example.com",
+            "current_password": "wrong-password",
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/email");
+    assert_eq!(flash_message(&resp), "The current password is incorrect");
+    let html = app.get_accepting("/admin/email", "text/html").await.text().await?;
+    assert!(!html.contains("intruder@example.com"));
+});
+
+login_test!(the_old_address_is_told_about_a_change, [app] {
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    // there is no old address the first time
+    set_email(&app).await;
+    let resp = app
+        .post_change_email(&serde_json::json!({
+            "email": "new@example.com",
+            "current_password": app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/email");
+    let sent = app.wait_for_emails(1).await;
+    assert_eq!(sent.len(), 1);
+    let body: serde_json::Value = serde_json::from_slice(&sent[0].body)?;
+    assert_eq!(body["To"], "admin@example.com");
+    let html = app.get_audit_html("action=email_changed").await;
+    assert_eq!(html.matches("<td>email_changed</td>").count(), 2, "{html}");
+});
@@ -0,0 +1,43 @@
+use wiremock::{
+    matchers::{method, path},
+    Mock, ResponseTemplate,
+};
+
+use super::helpers::request_id_of;
+use crate::cookie_test;
+
+cookie_test!(responses_echo_the_request_id_of_the_client, [app]{
+    let resp = app.get_with_request_id("/login", "ci-run-42").await;
+    assert_eq!(resp.headers()["X-Request-Id"], "ci-run-42");
+});
+
+cookie_test!(a_request_id_is_generated_when_missing_or_invalid, [app]{
+    let missing = app.get_accepting("/login", "text/html").await;
+    let invalid = app.get_with_request_id("/login", &"a".repeat(200)).await;
+    for resp in [missing, invalid] {
+        let request_id = resp.headers()["X-Request-Id"].to_str()?;
+        assert!(uuid::Uuid::parse_str(request_id).is_ok(), "{request_id}");
+    }
+});
+
+cookie_test!(error_responses_carry_the_request_id, [app]{
+    let resp = app.get_with_request_id("/admin/missing", "lost-1").await;
+    assert_eq!(resp.status().as_u16(), 404);
+    assert_eq!(resp.headers()["X-Request-Id"], "lost-1");
+});
+
+cookie_test!(the_confirmation_email_carries_the_subscribe_request_id, [app]{
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_subscription_with_request_id("username=lzl&email=lzl@example.com", "subscribe-7")
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    assert_eq!(request_id_of(&email_request).as_deref(), Some("subscribe-7"));
+});
@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use futures_util::future::join_all;
+use uuid::Uuid;
+use zero2prod_api::configuration::SameSiteSetting;
+
+use super::helpers::{
+    assert_is_redirect_to, get_test_app_with_cookie_and_config, TestAppWithCookie,
+};
+use crate::login_test;
+
+fn revoke_form_id(html: &str) -> String {
+    let marker = r#"name="id" value=""#;
+    let start = html.find(marker).expect("no revocable session") + marker.len();
+    html[start..start + 36].to_owned()
+}
+
+login_test!(active_sessions_are_listed, [app] {
+    let other = app.another_browser("second-browser");
+    app.login_with(&other).await;
+    let html = app.get_sessions_html().await;
+    assert!(html.contains("second-browser"), "{}", html);
+    assert!(html.contains("127.0.0.1"), "{}", html);
+    assert!(html.contains("this session"), "{}", html);
+});
+
+login_test!(a_revoked_session_is_logged_out, [app] {
+    let other = app.another_browser("second-browser");
+    app.login_with(&other).await;
+    let resp = app.get_admin_dashboard_with(&other).await;
+    assert_eq!(resp.status().as_u16(), 200);
+
+    let id = revoke_form_id(&app.get_sessions_html().await);
+    let resp = app.post_revoke_session(&serde_json::json!({ "id": id })).await;
+    assert_is_redirect_to(&resp, "/admin/sessions");
+    let resp = app.get_admin_dashboard_with(&other).await;
+    assert_is_redirect_to(&resp, "/login");
+    // the current session is untouched
+    let resp = app.get_admin_dashboard().await;
+    assert_eq!(resp.status().as_u16(), 200);
+});
+
+login_test!(a_session_revoked_while_its_requests_are_in_flight_stays_revoked, [app] {
+    let other = app.another_browser("second-browser");
+    app.login_with(&other).await;
+    let id = revoke_form_id(&app.get_sessions_html().await);
+    // every request of the other browser touches its session as the revoke lands
+    let requests = join_all((0..20).map(|_| app.get_admin_dashboard_with(&other)));
+    let form = serde_json::json!({ "id": id });
+    let revoke = app.post_revoke_session(&form);
+    let (_, resp) = tokio::join!(requests, revoke);
+    assert_is_redirect_to(&resp, "/admin/sessions");
+    assert_is_redirect_to(&app.get_admin_dashboard_with(&other).await, "/login");
+    let html = app.get_sessions_html().await;
+    assert!(!html.contains("second-browser"), "{}", html);
+});
+
+login_test!(all_other_sessions_can_be_revoked_at_once, [app] {
+    let first = app.another_browser("first-browser");
+    let second = app.another_browser("second-browser");
+    app.login_with(&first).await;
+    app.login_with(&second).await;
+    let resp = app.post_revoke_other_sessions().await;
+    assert_is_redirect_to(&resp, "/admin/sessions");
+    assert_is_redirect_to(&app.get_admin_dashboard_with(&first).await, "/login");
+    assert_is_redirect_to(&app.get_admin_dashboard_with(&second).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+});
+
+login_test!(changing_the_password_logs_out_other_sessions, [app] {
+    let other = app.another_browser("second-browser");
+    app.login_with(&other).await;
+    let new_password = Uuid::new_v4().to_string();
+    let resp = app.post_change_password(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+    }))
+    .await;
+    assert_is_redirect_to(&resp, "/admin/password");
+    assert_is_redirect_to(&app.get_admin_dashboard_with(&other).await, "/login");
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+});
+
+login_test!(logging_out_removes_the_session, [app] {
+    let other = app.another_browser("second-browser");
+    app.login_with(&other).await;
+    let resp = app.post_logout_with(&other).await;
+    assert_is_redirect_to(&resp, "/login");
+    let html = app.get_sessions_html().await;
+    assert!(!html.contains("second-browser"), "{}", html);
+});
+
+async fn login(app: &TestAppWithCookie) {
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await
+        .unwrap();
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+}
+
+#[sqlx::test]
+async fn an_idle_session_expires(pool: sqlx::Pool<sqlx::Postgres>) -> anyhow::Result<()> {
+    let app = get_test_app_with_cookie_and_config(pool, |conf| {
+        conf.session.idle_timeout_seconds = 1;
+    })
+    .await?;
+    login(&app).await;
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Your session has expired"), "{}", html);
+    // the session is gone rather than merely refused
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    Ok(())
+}
+
+#[sqlx::test]
+async fn an_active_session_still_expires_after_the_absolute_timeout(
+    pool: sqlx::Pool<sqlx::Postgres>,
+) -> anyhow::Result<()> {
+    let app = get_test_app_with_cookie_and_config(pool, |conf| {
+        conf.session.absolute_timeout_seconds = 2;
+    })
+    .await?;
+    login(&app).await;
+    tokio::time::sleep(Duration::from_millis(1000)).await;
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    tokio::time::sleep(Duration::from_millis(2500)).await;
+
+    assert_is_redirect_to(&app.get_admin_dashboard().await, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Your session has expired"), "{}", html);
+    Ok(())
+}
+
+#[sqlx::test]
+async fn the_session_cookie_follows_the_configuration(
+    pool: sqlx::Pool<sqlx::Postgres>,
+) -> anyhow::Result<()> {
+    let app = get_test_app_with_cookie_and_config(pool, |conf| {
+        conf.session.cookie_name = "z2p-session".to_owned();
+        conf.session.same_site = SameSiteSetting::Strict;
+        conf.session.cookie_max_age_seconds = Some(600);
+    })
+    .await?;
+    let resp = app
+        .post_login(&serde_json::json!({
+            "username": app.test_user.username,
+            "password": app.test_user.password,
+        }))
+        .await?;
+    let cookie = resp
+        .headers()
+        .get_all("Set-Cookie")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .find(|value| value.starts_with("z2p-session="))
+        .expect("no session cookie")
+        .to_owned();
+    assert!(cookie.contains("SameSite=Strict"), "{}", cookie);
+    assert!(cookie.contains("Max-Age=600"), "{}", cookie);
+    assert!(cookie.contains("HttpOnly"), "{}", cookie);
+    Ok(())
+}
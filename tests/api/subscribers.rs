@@ -0,0 +1,259 @@
+use wiremock::{matchers::path, Mock, ResponseTemplate};
+
+use super::{
+    api_tokens::create_token,
+    helpers::{assert_is_redirect_to, flash_message, TestAppWithCookie},
+};
+use crate::login_test;
+
+// a pending subscriber, the confirmation email goes to a mock that is dropped right after
+pub async fn subscribe(app: &TestAppWithCookie, username: &str, email: &str) -> String {
+    let _guard = Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let resp = app
+        .post_subscription(format!("username={username}&email={email}"))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let created: serde_json::Value = resp.json().await.unwrap();
+    created["id"].as_str().unwrap().to_owned()
+}
+
+login_test!(subscribers_are_listed_and_filtered, [app] {
+    subscribe(&app, "alice", "alice@example.com").await;
+    let bob = subscribe(&app, "bob", "bob@example.com").await;
+    let resp = app
+        .post_subscriber_action("confirm", &serde_json::json!({ "id": bob }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(flash_message(&resp), "The subscriber has been confirmed.");
+
+    let html = app.get_subscribers_html("").await;
+    assert!(html.contains("alice@example.com") && html.contains("bob@example.com"));
+    assert!(html.contains("2 subscribers"));
+
+    let html = app.get_subscribers_html("status=confirmed").await;
+    assert!(html.contains("bob@example.com"));
+    assert!(!html.contains("alice@example.com"));
+
+    // the search is case insensitive and covers the name too
+    let html = app.get_subscribers_html("search=ALI").await;
+    assert!(html.contains("alice@example.com"));
+    assert!(!html.contains("bob@example.com"));
+
+    let html = app.get_subscribers_html("since=2000-01-01&until=2000-12-31").await;
+    assert!(html.contains("No matching subscribers"));
+
+    let html = app.get_subscribers_html("since=yesterday").await;
+    assert!(html.contains("invalid date: yesterday"));
+});
+
+login_test!(an_admin_can_unsubscribe_and_delete_a_subscriber, [app] {
+    let id = subscribe(&app, "carol", "carol@example.com").await;
+    let resp = app
+        .post_subscriber_action("unsubscribe", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    let html = app.get_subscribers_html("status=unsubscribed").await;
+    assert!(html.contains("carol@example.com"));
+
+    let resp = app
+        .post_subscriber_action("delete", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert!(!app.get_subscribers_html("").await.contains("carol@example.com"));
+
+    let resp = app
+        .post_subscriber_action("delete", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(flash_message(&resp), "The subscriber doesn't exist");
+});
+
+login_test!(a_pending_subscriber_can_be_sent_the_confirmation_again, [app] {
+    let id = subscribe(&app, "dave", "dave@example.com").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = app
+        .post_subscriber_action("resend", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(flash_message(&resp), "The confirmation email has been sent again.");
+
+    // the new link replaces the first one
+    let mut email_requests = app.email_server.received_requests().await.unwrap();
+    let email_request = email_requests.pop().unwrap();
+    let first_link = app.get_confirmation_link(&email_requests.pop().unwrap());
+    let resp = app.cookie_cli.get(first_link.html).send().await?;
+    assert_eq!(resp.status().as_u16(), 400);
+    let links = app.get_confirmation_link(&email_request);
+    app.confirm_subscription(links.html.as_str()).await?;
+    let html = app.get_subscribers_html("status=confirmed").await;
+    assert!(html.contains("dave@example.com"));
+
+    // a confirmed subscriber isn't mailed again
+    let resp = app
+        .post_subscriber_action("resend", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(
+        flash_message(&resp),
+        "Only pending subscribers can be sent a confirmation email"
+    );
+});
+
+login_test!(the_json_api_pages_through_subscribers, [app] {
+    let token = create_token(&app, &["scope_subscribers_read"]).await;
+    for name in ["erin", "frank", "grace"] {
+        subscribe(&app, name, &format!("{name}@example.com")).await;
+    }
+
+    let resp = app.get_api_subscribers(&token, "per_page=2").await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let first: serde_json::Value = resp.json().await?;
+    assert_eq!(first["total"], 3);
+    assert_eq!(first["subscribers"].as_array().unwrap().len(), 2);
+    assert_eq!(first["subscribers"][0]["status"], "pending");
+
+    let second: serde_json::Value = app
+        .get_api_subscribers(&token, "per_page=2&page=2")
+        .await
+        .json()
+        .await?;
+    assert_eq!(second["page"], 2);
+    assert_eq!(second["subscribers"].as_array().unwrap().len(), 1);
+
+    let filtered: serde_json::Value = app
+        .get_api_subscribers(&token, "search=frank&status=pending")
+        .await
+        .json()
+        .await?;
+    assert_eq!(filtered["total"], 1);
+    assert_eq!(filtered["subscribers"][0]["email"], "frank@example.com");
+
+    let resp = app.get_api_subscribers(&token, "status=gone").await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let problem: serde_json::Value = resp.json().await?;
+    assert_eq!(problem["code"], "validation_failed");
+});
+
+login_test!(the_json_api_needs_the_write_scope_to_change_subscribers, [app] {
+    let id = subscribe(&app, "heidi", "heidi@example.com").await;
+    let body = serde_json::json!({ "id": id });
+
+    let read_only = create_token(&app, &["scope_subscribers_read"]).await;
+    let resp = app.post_api_subscriber_action(&read_only, "confirm", &body).await;
+    assert_eq!(resp.status().as_u16(), 403);
+
+    let token = create_token(&app, &["scope_subscribers_write"]).await;
+    let resp = app.post_api_subscriber_action(&token, "confirm", &body).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let subscriber: serde_json::Value = resp.json().await?;
+    assert_eq!(subscriber["status"], "confirmed");
+
+    let resp = app.post_api_subscriber_action(&token, "delete", &body).await;
+    assert_eq!(resp.status().as_u16(), 204);
+    let resp = app.post_api_subscriber_action(&token, "delete", &body).await;
+    assert_eq!(resp.status().as_u16(), 404);
+    let problem: serde_json::Value = resp.json().await?;
+    assert_eq!(problem["code"], "not_found");
+});
+
+login_test!(subscribers_are_imported_from_a_csv_upload, [app] {
+    subscribe(&app, "ivan", "ivan@example.com").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let csv = "email,name,status\n\
+        judy@example.com,Judy\n\
+        \"kim@example.com\",\"Kim, Jr.\",confirmed\n\
+        ivan@example.com,Ivan\n\
+        not-an-email,Leo\n\
+        mallory@example.com,Mallory,gone\n";
+    let resp = app.post_subscribers_import(csv, true).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let html = resp.text().await?;
+    assert!(html.contains("2 imported, 1 already subscribed"));
+    assert!(html.contains("1 confirmation emails sent"));
+    assert!(html.contains("<td>5</td><td>invalid email</td>"));
+    assert!(html.contains("unknown subscription status: gone"));
+
+    // only the pending subscriber is mailed
+    let email_request = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let links = app.get_confirmation_link(&email_request);
+    app.confirm_subscription(links.html.as_str()).await?;
+    let html = app.get_subscribers_html("status=confirmed").await;
+    assert!(html.contains("judy@example.com") && html.contains("kim@example.com"));
+
+    let resp = app.post_subscribers_import("", false).await;
+    assert_is_redirect_to(&resp, "/admin/subscribers/import");
+    assert_eq!(flash_message(&resp), "the file has no subscribers");
+});
+
+login_test!(an_export_can_be_imported_back, [app] {
+    let id = subscribe(&app, "nina", "nina@example.com").await;
+    app.post_subscriber_action("unsubscribe", &serde_json::json!({ "id": id }))
+        .await;
+    subscribe(&app, "oscar", "oscar@example.com").await;
+
+    let resp = app.get_subscribers_export().await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(resp.headers()["content-type"], "text/csv; charset=utf-8");
+    assert!(resp.headers()["content-disposition"]
+        .to_str()?
+        .starts_with("attachment"));
+    let csv = resp.text().await?;
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("email,name,status,subscribed_at"));
+    assert_eq!(lines.count(), 2);
+
+    // a subscriber lost since the backup comes back as it was
+    app.post_subscriber_action("delete", &serde_json::json!({ "id": id }))
+        .await;
+    let token = create_token(&app, &["scope_subscribers_write"]).await;
+    let resp = app.post_api_subscribers_import(&token, &csv, "").await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let report: serde_json::Value = resp.json().await?;
+    assert_eq!(report["imported"], 1);
+    assert_eq!(report["skipped"], 1);
+    assert_eq!(report["confirmations_sent"], 0);
+    assert!(report["errors"].as_array().unwrap().is_empty());
+    let html = app.get_subscribers_html("status=unsubscribed").await;
+    assert!(html.contains("nina@example.com"));
+});
+
+login_test!(an_oversized_import_is_refused_before_it_is_read, [app] {
+    let token = create_token(&app, &["scope_subscribers_write"]).await;
+    // over the 4 MiB an import may take
+    let csv = "a@example.com,A\n".repeat(300_000);
+    let resp = app.post_api_subscribers_import(&token, &csv, "").await;
+    assert_eq!(resp.status().as_u16(), 413);
+});
+
+login_test!(the_json_api_exports_and_imports_with_the_right_scopes, [app] {
+    let read_only = create_token(&app, &["scope_subscribers_read"]).await;
+    let resp = app
+        .post_api_subscribers_import(&read_only, "peggy@example.com,Peggy\n", "")
+        .await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let resp = app.get_api_subscribers_export(&read_only).await;
+    assert_eq!(resp.status().as_u16(), 200);
+    assert_eq!(resp.text().await?, "email,name,status,subscribed_at\r\n");
+
+    let token = create_token(&app, &["scope_subscribers_write"]).await;
+    let resp = app.get_api_subscribers_export(&token).await;
+    assert_eq!(resp.status().as_u16(), 403);
+    let resp = app
+        .post_api_subscribers_import(&token, "peggy@example.com,\"Peggy\n", "")
+        .await;
+    assert_eq!(resp.status().as_u16(), 400);
+    let problem: serde_json::Value = resp.json().await?;
+    assert_eq!(problem["code"], "validation_failed");
+});
@@ -3,7 +3,7 @@ use std::str::FromStr;
 use poem::http::StatusCode;
 use sea_orm::{prelude::Uuid, *};
 use wiremock::{matchers::path, Mock, ResponseTemplate};
-use zero2prod_api::{entities::subscriptions, routes::subscriptions::ConfirmStatus};
+use zero2prod_api::entities::{sea_orm_active_enums::SubscriptionStatus, subscriptions};
 
 use crate::{
     api::helpers::{email, get_first_link, post_subscription},
@@ -45,7 +45,7 @@ normal_test!(subscribe_persists_the_new_subscriber, [app]{
     let new_user = new_user.unwrap();
     assert_eq!(new_user.email, "bar@qq.com");
     assert_eq!(new_user.name, "lzl");
-    assert_eq!(new_user.status, ConfirmStatus::Pending.to_string());
+    assert_eq!(new_user.status, SubscriptionStatus::Pending);
 });
 
 normal_test!(subscribe_returns_400_for_invalid_data, [app] {
@@ -71,7 +71,7 @@ normal_test!(subscribe_returns_a_confirmation_email, [test_app] {
         .await;
 
     let cli = &test_app.cli;
-    let data = format!("username=lin&email={}", email().to_string());
+    let data = format!("username=lin&email={}", email());
     let resp = post_subscription(cli, data).await;
     resp.assert_status(StatusCode::OK);
     let email_request = test_app.email_server.received_requests().await.unwrap();
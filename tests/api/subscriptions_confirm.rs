@@ -3,7 +3,7 @@ use std::str::FromStr;
 use poem::http::StatusCode;
 use sea_orm::{prelude::Uuid, *};
 use wiremock::{matchers::path, Mock, ResponseTemplate};
-use zero2prod_api::{entities::subscriptions, routes::subscriptions::ConfirmStatus};
+use zero2prod_api::entities::{sea_orm_active_enums::SubscriptionStatus, subscriptions};
 
 use crate::{
     api::helpers::{email, get_first_link, post_subscription},
@@ -25,7 +25,7 @@ normal_test!(subscribe_and_then_confirm, [app] {
 
     let test_user = "lin";
     let test_email = email();
-    let data = format!("username={}&email={}", test_user, test_email.to_string());
+    let data = format!("username={}&email={}", test_user, test_email);
     let resp = post_subscription(&app.cli, data).await;
     resp.assert_status(StatusCode::OK);
     let resp_json = resp.json().await;
@@ -48,5 +48,31 @@ normal_test!(subscribe_and_then_confirm, [app] {
     let new_user = new_user.unwrap();
     assert_eq!(new_user.name, test_user);
     assert_eq!(new_user.email, test_email.to_string());
-    assert_eq!(new_user.status, ConfirmStatus::Confirmed.to_string());
+    assert_eq!(new_user.status, SubscriptionStatus::Confirmed);
+});
+
+normal_test!(an_old_link_does_not_confirm_a_bounced_subscriber, [app] {
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let resp = post_subscription(&app.cli, format!("username=lin&email={}", email())).await;
+    resp.assert_status(StatusCode::OK);
+    let resp_json = resp.json().await;
+    let id = Uuid::from_str(resp_json.value().object().get("id").string()).unwrap();
+
+    let subscriber = subscriptions::Entity::find_by_id(id).one(&app.db).await?.unwrap();
+    let mut subscriber: subscriptions::ActiveModel = subscriber.into();
+    subscriber.status = Set(SubscriptionStatus::Bounced);
+    subscriber.update(&app.db).await?;
+
+    let email_request = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request[0].body).unwrap();
+    let confirm_link = get_first_link(body["TextBody"].as_str().unwrap(), 123);
+    let confirm_link = reqwest::Url::parse(&confirm_link)?;
+    app.cli.get(confirm_link).send().await.assert_status(StatusCode::OK);
+
+    let subscriber = subscriptions::Entity::find_by_id(id).one(&app.db).await?.unwrap();
+    assert_eq!(subscriber.status, SubscriptionStatus::Bounced);
 });
@@ -0,0 +1,115 @@
+use uuid::Uuid;
+use wiremock::{matchers::path, Mock, ResponseTemplate};
+use zero2prod_api::suppressions::audited_address;
+
+use super::{
+    helpers::{assert_is_redirect_to, flash_message, TestAppWithCookie},
+    subscribers::subscribe,
+};
+use crate::login_test;
+
+async fn suppress(app: &TestAppWithCookie, address: &str) {
+    let resp = app
+        .post_suppressions(
+            "",
+            &serde_json::json!({ "address": address, "reason": "asked us" }),
+        )
+        .await;
+    assert_is_redirect_to(&resp, "/admin/suppressions");
+}
+
+login_test!(an_admin_adds_and_removes_suppressions, [app] {
+    let resp = app
+        .post_suppressions("", &serde_json::json!({ "address": " Alice@Example.com" }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/suppressions");
+    assert_eq!(flash_message(&resp), "alice@example.com won't be mailed anymore.");
+    suppress(&app, "@Blocked.test").await;
+
+    let html = app.get_suppressions_html().await;
+    assert!(html.contains("<td>alice@example.com</td>"));
+    assert!(html.contains("<td>added by an admin</td>"));
+    assert!(html.contains("<td>blocked.test</td>") && html.contains("<td>asked us</td>"));
+
+    let resp = app
+        .post_suppressions("", &serde_json::json!({ "address": "alice@example.com" }))
+        .await;
+    assert_eq!(flash_message(&resp), "alice@example.com is already suppressed");
+    let resp = app
+        .post_suppressions("", &serde_json::json!({ "address": "not a domain" }))
+        .await;
+    assert_eq!(flash_message(&resp), "invalid address or domain: not a domain");
+
+    let resp = app
+        .post_suppressions("/remove", &serde_json::json!({ "address": "blocked.test" }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/suppressions");
+    assert_eq!(flash_message(&resp), "The suppression has been removed.");
+    assert!(!app.get_suppressions_html().await.contains("blocked.test"));
+    let resp = app
+        .post_suppressions("/remove", &serde_json::json!({ "address": "blocked.test" }))
+        .await;
+    assert_eq!(flash_message(&resp), "The address or domain isn't suppressed");
+
+    // the audit log is append-only, it only names an address by its hash; a domain
+    // isn't personal data and is kept as it is
+    let hash = audited_address("alice@example.com");
+    let audit = app.get_audit_html("action=suppression_added").await;
+    assert!(audit.contains(&hash) && !audit.contains("alice@example.com"), "{audit}");
+    for action in ["suppression_added", "suppression_removed"] {
+        let audit = app.get_audit_html(&format!("action={action}")).await;
+        assert!(audit.contains("<td>blocked.test</td>"), "{audit}");
+    }
+});
+
+login_test!(a_suppressed_address_gets_no_confirmation_email, [app] {
+    suppress(&app, "blocked.test").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    // the answer doesn't tell that the address is suppressed
+    let resp = app
+        .post_subscription("username=dave&email=Dave@Blocked.test".to_owned())
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let created: serde_json::Value = resp.json().await?;
+
+    let resp = app
+        .post_subscriber_action("resend", &serde_json::json!({ "id": created["id"] }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert_eq!(flash_message(&resp), "The address is suppressed, no email was sent");
+});
+
+login_test!(issues_skip_suppressed_addresses_and_domains, [app] {
+    for (name, email) in [
+        ("alice", "alice@example.com"),
+        ("bob", "bob@blocked.test"),
+        ("carol", "carol@example.com"),
+    ] {
+        let id = subscribe(&app, name, email).await;
+        app.post_subscriber_action("confirm", &serde_json::json!({ "id": id }))
+            .await;
+    }
+    suppress(&app, "Alice@example.com").await;
+    suppress(&app, "blocked.test").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let request = serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let resp = app.post_newsletters(request).await;
+    assert_is_redirect_to(&resp, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    let sent = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&sent.body)?;
+    assert_eq!(body["To"], "carol@example.com");
+});
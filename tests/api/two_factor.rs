@@ -0,0 +1,152 @@
+use totp_rs::{Algorithm, Secret, TOTP};
+
+use super::helpers::{assert_is_redirect_to, flash_message, TestAppWithCookie};
+use crate::{cookie_test, login_test};
+
+fn totp(secret: &str) -> TOTP {
+    let secret = Secret::Encoded(secret.to_owned()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 1, 30, secret, None, String::new()).unwrap()
+}
+
+fn current_code(secret: &str) -> String {
+    totp(secret).generate_current().unwrap()
+}
+
+// the code of the coming step, still accepted, when the current one was used already
+fn next_code(secret: &str) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+    totp(secret).generate(now + 30)
+}
+
+fn extract_between<'a>(html: &'a str, start: &str, end: &str) -> &'a str {
+    let from = html.find(start).expect("start marker not found") + start.len();
+    let to = html[from..].find(end).expect("end marker not found") + from;
+    &html[from..to]
+}
+
+// enrol the logged-in test user, returning the totp secret and the recovery codes
+async fn enable_two_factor(app: &TestAppWithCookie) -> (String, Vec<String>) {
+    let html = app.get_two_factor_html().await;
+    let secret = extract_between(&html, r#"<code id="totp-secret">"#, "</code>").to_owned();
+    let resp = app
+        .post_enable_two_factor(&serde_json::json!({ "code": current_code(&secret) }))
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let html = resp.text().await.unwrap();
+    let codes = extract_between(&html, r#"<ul id="recovery-codes">"#, "</ul>")
+        .split("<code>")
+        .skip(1)
+        .map(|c| c.split("</code>").next().unwrap().to_owned())
+        .collect::<Vec<_>>();
+    (secret, codes)
+}
+
+async fn relogin(app: &TestAppWithCookie) -> reqwest::Response {
+    app.post_logout().await.unwrap();
+    app.post_login(&serde_json::json!({
+        "username": app.test_user.username,
+        "password": app.test_user.password,
+    }))
+    .await
+    .unwrap()
+}
+
+login_test!(enrolment_shows_a_secret_and_recovery_codes, [app] {
+    let html = app.get_two_factor_html().await;
+    assert!(html.contains("otpauth://totp/"), "{}", html);
+    let (_, codes) = enable_two_factor(&app).await;
+    assert_eq!(codes.len(), 10);
+    let html = app.get_two_factor_html().await;
+    assert!(html.contains("Two-factor authentication is <b>enabled</b>"), "{}", html);
+});
+
+login_test!(a_wrong_code_does_not_enable_two_factor, [app] {
+    app.get_two_factor_html().await;
+    let resp = app
+        .post_enable_two_factor(&serde_json::json!({ "code": "000000x" }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/2fa");
+    let html = app.get_two_factor_html().await;
+    assert!(html.contains(r#"<p class="flash flash-error"><i>The authentication code is invalid</i></p>"#), "{}", html);
+    assert!(html.contains("Two-factor authentication is <b>disabled</b>"), "{}", html);
+});
+
+login_test!(login_asks_for_the_second_factor_once_enabled, [app] {
+    let (secret, _) = enable_two_factor(&app).await;
+    let resp = relogin(&app).await;
+    assert_is_redirect_to(&resp, "/login/2fa");
+    // a password alone doesn't give access to the admin pages
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+
+    // the code of the enrolment was used already
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": next_code(&secret) }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    let resp = app.get_admin_dashboard().await;
+    assert_eq!(resp.status().as_u16(), 200);
+});
+
+login_test!(a_totp_code_is_only_accepted_once, [app] {
+    let (secret, _) = enable_two_factor(&app).await;
+    relogin(&app).await;
+    let code = next_code(&secret);
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": code }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+    relogin(&app).await;
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": code }))
+        .await;
+    assert_is_redirect_to(&resp, "/login/2fa");
+    assert_eq!(flash_message(&resp), "The authentication code is invalid");
+});
+
+login_test!(a_wrong_second_factor_is_rejected, [app] {
+    enable_two_factor(&app).await;
+    relogin(&app).await;
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": "123456789" }))
+        .await;
+    assert_is_redirect_to(&resp, "/login/2fa");
+    assert_eq!(flash_message(&resp), "The authentication code is invalid");
+    let resp = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&resp, "/login");
+});
+
+login_test!(recovery_codes_can_only_be_used_once, [app] {
+    let (_, codes) = enable_two_factor(&app).await;
+    relogin(&app).await;
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": codes[0] }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+
+    relogin(&app).await;
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": codes[0] }))
+        .await;
+    assert_is_redirect_to(&resp, "/login/2fa");
+});
+
+login_test!(disabling_two_factor_restores_password_only_login, [app] {
+    let (secret, _) = enable_two_factor(&app).await;
+    let resp = app
+        .post_disable_two_factor(&serde_json::json!({ "code": next_code(&secret) }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/2fa");
+    let resp = relogin(&app).await;
+    assert_is_redirect_to(&resp, "/admin/dashboard");
+});
+
+cookie_test!(the_second_factor_page_requires_a_password_first, [app] {
+    let resp = app
+        .post_login_two_factor(&serde_json::json!({ "code": "123456" }))
+        .await;
+    assert_is_redirect_to(&resp, "/login");
+});
@@ -0,0 +1,173 @@
+use uuid::Uuid;
+use wiremock::{matchers::path, Mock, ResponseTemplate};
+
+use super::{
+    api_tokens::create_token,
+    helpers::{assert_is_redirect_to, flash_message, TestAppWithCookie},
+    subscribers::subscribe,
+};
+use crate::login_test;
+
+const CREDENTIALS: Option<(&str, &str)> = Some(("postmark", "webhook-secret"));
+
+fn bounce(kind: &str, email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": kind,
+        "TypeCode": 1,
+        "Email": email,
+        "BouncedAt": "2023-04-05T10:00:00Z",
+    })
+}
+
+async fn confirmed(app: &TestAppWithCookie, name: &str, email: &str) -> String {
+    let id = subscribe(app, name, email).await;
+    let resp = app
+        .post_subscriber_action("confirm", &serde_json::json!({ "id": id }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    id
+}
+
+login_test!(the_webhook_requires_its_credentials, [app] {
+    let event = bounce("HardBounce", "alice@example.com");
+    let resp = app.post_email_webhook(&event, None).await;
+    assert_eq!(resp.status().as_u16(), 401);
+    assert_eq!(resp.headers()["WWW-Authenticate"], "Basic");
+    let resp = app
+        .post_email_webhook(&event, Some(("postmark", "wrong")))
+        .await;
+    assert_eq!(resp.status().as_u16(), 401);
+    let resp = app.post_email_webhook(&event, CREDENTIALS).await;
+    assert_eq!(resp.status().as_u16(), 200);
+});
+
+login_test!(bounces_and_complaints_suppress_the_address, [app] {
+    confirmed(&app, "alice", "alice@example.com").await;
+    confirmed(&app, "bob", "bob@example.com").await;
+    confirmed(&app, "carol", "carol@example.com").await;
+
+    // a soft bounce or a delivery changes nothing
+    for event in [
+        bounce("SoftBounce", "alice@example.com"),
+        serde_json::json!({ "RecordType": "Delivery", "Recipient": "alice@example.com" }),
+        serde_json::json!({ "RecordType": "Open", "Recipient": "alice@example.com" }),
+    ] {
+        let resp = app.post_email_webhook(&event, CREDENTIALS).await;
+        assert_eq!(resp.status().as_u16(), 200);
+    }
+    assert!(app
+        .get_subscribers_html("status=confirmed")
+        .await
+        .contains("alice@example.com"));
+
+    // the address is matched whatever its case
+    let resp = app
+        .post_email_webhook(&bounce("HardBounce", "Alice@Example.com"), CREDENTIALS)
+        .await;
+    assert_eq!(resp.status().as_u16(), 200);
+    let complaint = serde_json::json!({ "RecordType": "SpamComplaint", "Email": "bob@example.com" });
+    app.post_email_webhook(&complaint, CREDENTIALS).await;
+    app.post_email_webhook(&bounce("SpamComplaint", "carol@example.com"), CREDENTIALS)
+        .await;
+
+    let html = app.get_subscribers_html("status=bounced").await;
+    assert!(html.contains("alice@example.com") && !html.contains("bob@example.com"));
+    let html = app.get_subscribers_html("status=complained").await;
+    assert!(html.contains("bob@example.com") && html.contains("carol@example.com"));
+
+    // a later bounce doesn't downgrade a complaint
+    app.post_email_webhook(&bounce("HardBounce", "bob@example.com"), CREDENTIALS)
+        .await;
+    let html = app.get_subscribers_html("status=complained").await;
+    assert!(html.contains("bob@example.com"));
+
+    // the addresses are suppressed for good, the first reason is kept
+    let html = app.get_suppressions_html().await;
+    assert!(html.contains("<td>alice@example.com</td>"));
+    assert!(html.contains("<td>HardBounce</td>") && html.contains("<td>bounce</td>"));
+    assert!(html.contains("<td>bob@example.com</td>"));
+    assert_eq!(html.matches("<td>SpamComplaint</td>").count(), 2);
+});
+
+login_test!(the_worker_skips_a_suppressed_address, [app] {
+    confirmed(&app, "dave", "dave@example.com").await;
+    confirmed(&app, "erin", "erin@example.com").await;
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let request = serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let resp = app.post_newsletters(request).await;
+    assert_is_redirect_to(&resp, "/admin/newsletters");
+
+    // the bounce comes in while the issue is queued
+    app.post_email_webhook(&bounce("HardBounce", "dave@example.com"), CREDENTIALS)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let sent = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&sent.body)?;
+    assert_eq!(body["To"], "erin@example.com");
+});
+
+login_test!(a_removed_suppression_gets_the_address_mailed_again, [app] {
+    confirmed(&app, "frank", "frank@example.com").await;
+    app.post_email_webhook(&bounce("HardBounce", "frank@example.com"), CREDENTIALS)
+        .await;
+    assert!(app
+        .get_subscribers_html("status=bounced")
+        .await
+        .contains("frank@example.com"));
+
+    // the address was fixed on the recipient's side
+    let resp = app
+        .post_suppressions("/remove", &serde_json::json!({ "address": "Frank@example.com" }))
+        .await;
+    assert_is_redirect_to(&resp, "/admin/suppressions");
+    assert!(app
+        .get_subscribers_html("status=confirmed")
+        .await
+        .contains("frank@example.com"));
+
+    Mock::given(path("/email"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let request = serde_json::json!({
+        "title": "title",
+        "text_content": "plain text",
+        "html_content": "<p>html body</p>",
+        "idempotency_key": Uuid::new_v4().to_string(),
+    });
+    let resp = app.post_newsletters(request).await;
+    assert_is_redirect_to(&resp, "/admin/newsletters");
+    app.dispatch_all_pending_emails().await;
+    let sent = app.email_server.received_requests().await.unwrap().pop().unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&sent.body)?;
+    assert_eq!(body["To"], "frank@example.com");
+});
+
+login_test!(a_suppressed_subscriber_cannot_be_confirmed, [app] {
+    let id = confirmed(&app, "grace", "grace@example.com").await;
+    app.post_email_webhook(&bounce("HardBounce", "grace@example.com"), CREDENTIALS)
+        .await;
+
+    let body = serde_json::json!({ "id": id });
+    let resp = app.post_subscriber_action("confirm", &body).await;
+    assert_is_redirect_to(&resp, "/admin/subscribers");
+    assert!(flash_message(&resp).contains("bounced or complained"));
+    let token = create_token(&app, &["scope_subscribers_write"]).await;
+    let resp = app.post_api_subscriber_action(&token, "confirm", &body).await;
+    assert_eq!(resp.status().as_u16(), 409);
+    assert!(app
+        .get_subscribers_html("status=bounced")
+        .await
+        .contains("grace@example.com"));
+});